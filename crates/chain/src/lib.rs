@@ -15,9 +15,52 @@ pub mod control_plane {
 pub mod task_queue {
     use super::*;
     use anyhow::Result;
-    pub async fn poll_activity<P: Provider + Clone + Send + Sync + 'static>(_: &P, _: Address, _: &str) -> Result<Option<[u8;32]>> {
+    use alloy::primitives::B256;
+    use alloy::sol;
+
+    sol! {
+        #[sol(rpc)]
+        interface ITaskQueue {
+            struct Activity {
+                bytes32 activityId;
+                bytes32 workflowInstanceId;
+                address activityType;
+                bytes inputData;
+                bytes initData;
+                bytes resultData;
+                uint256 createdAt;
+                uint256 expiresAt;
+                address requester;
+                address assignedWorker;
+                uint256 assignedAt;
+                uint256 lastHeartbeat;
+                uint8 progress;
+                bool isAssigned;
+                bool isCompleted;
+                bool success;
+            }
+
+            function getWorkerActivities(address worker) external view returns (bytes32[] memory);
+            function getActivity(bytes32 activityId) external view returns (Activity memory);
+        }
+    }
+
+    pub use ITaskQueue::Activity;
+
+    pub async fn poll_activity<P: Provider + Clone + Send + Sync + 'static>(_: &P, _: Address, _: &str, _partition: u16) -> Result<Option<Activity>> {
         Ok(None)
     }
+
+    /// Activity ids currently assigned to `worker` on the TaskQueue.
+    pub async fn get_worker_activities<P: Provider + Clone + Send + Sync + 'static>(provider: &P, task_queue: Address, worker: Address) -> Result<Vec<B256>> {
+        let tq = ITaskQueue::new(task_queue, provider.clone());
+        Ok(tq.getWorkerActivities(worker).call().await?)
+    }
+
+    pub async fn get_activity<P: Provider + Clone + Send + Sync + 'static>(provider: &P, task_queue: Address, activity_id: B256) -> Result<Activity> {
+        let tq = ITaskQueue::new(task_queue, provider.clone());
+        Ok(tq.getActivity(activity_id).call().await?)
+    }
 }
 
 
@@ -1,10 +1,23 @@
 use serde::Deserialize;
+use std::path::PathBuf;
 use std::time::Duration;
 
-#[derive(Debug, Deserialize, Clone)]
+mod validate;
+
+pub use validate::ConfigProblem;
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct EthereumConfig {
     pub rpc_url: String,
+    /// Optional `ws://`/`wss://` endpoint for event subscriptions.
+    #[serde(default)]
+    pub ws_url: Option<String>,
+    /// Plaintext hex key, read only by `signer.kind = "private_key"`; dev use only.
+    #[serde(default)]
     pub wallet_private_key: String,
+    /// Expected signer address; derived from the signer when empty. Picks the key
+    /// on a remote signer that holds several.
+    #[serde(default)]
     pub wallet_address: String,
     pub workflow_engine_address: String,
     pub task_queue_address: String,
@@ -12,36 +25,454 @@ pub struct EthereumConfig {
     pub subnet_control_plane_address: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct SubnetApiConfig {
+    /// Storage broker endpoint; empty disables presigning (refs go to the WEP as-is).
     pub grpc_endpoint: String,
+    /// Lifetime requested for presigned download/upload URLs.
+    #[serde(with = "humantime_serde", default = "default_presign_ttl")]
+    pub presign_ttl: Duration,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+fn default_presign_ttl() -> Duration { Duration::from_secs(3600) }
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct SchedulerConfig {
     #[serde(with = "humantime_serde")]
     pub poll_interval: Duration,
     pub max_inflight: usize,
-    pub queue_name: String,
+    /// Single-queue shorthand, used when `queues` is empty.
+    #[serde(default)]
+    pub queue_name: Option<String>,
+    #[serde(default)]
+    pub queues: Vec<QueueConfig>,
+    /// How the scheduler picks which claim jobs to claim.
+    #[serde(default)]
+    pub policy: PolicyKind,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyKind {
+    #[default]
+    Fifo,
+    EarliestExpiry,
+    Weighted,
+    RewardPerSecond,
+}
+
+/// One `[[scheduler.queues]]` subscription.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct QueueConfig {
+    pub name: String,
+    #[serde(default = "default_partitions")]
+    pub partitions: Vec<u16>,
+    /// Relative share of poll slots among enabled queues.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Claim jobs plus inflight activities allowed for this queue; global `max_inflight` if unset.
+    #[serde(default)]
+    pub max_inflight: Option<usize>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Reward per activity, for `reward_per_second` scheduling.
+    #[serde(default)]
+    pub reward_wei: Option<u128>,
+    /// Typical run time of one activity, for `reward_per_second` scheduling.
+    #[serde(with = "humantime_serde", default)]
+    pub expected_duration: Option<Duration>,
+}
+
+fn default_partitions() -> Vec<u16> { vec![0] }
+fn default_weight() -> u32 { 1 }
+
+impl SchedulerConfig {
+    /// Enabled queue subscriptions, with `queue_name` as a single queue on partition 0 when `queues` is empty.
+    pub fn queues(&self) -> Vec<QueueConfig> {
+        if self.queues.is_empty() {
+            return self.queue_name.iter().map(|name| QueueConfig {
+                name: name.clone(),
+                partitions: default_partitions(),
+                weight: default_weight(),
+                max_inflight: None,
+                enabled: true,
+                reward_wei: None,
+                expected_duration: None,
+            }).collect();
+        }
+        self.queues.iter().filter(|q| q.enabled).cloned().collect()
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ReconcilerConfig {
+    #[serde(with = "humantime_serde", default = "default_reconcile_interval")]
+    pub interval: Duration,
+    /// Activities expire within this long of assignment; local records older
+    /// than the window with no on-chain counterpart are dropped.
+    #[serde(with = "humantime_serde", default = "default_reconcile_window")]
+    pub window: Duration,
+}
+
+fn default_reconcile_interval() -> Duration { Duration::from_secs(60) }
+fn default_reconcile_window() -> Duration { Duration::from_secs(3 * 60 * 60) }
+
+impl Default for ReconcilerConfig {
+    fn default() -> Self {
+        Self { interval: default_reconcile_interval(), window: default_reconcile_window() }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct EventsConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(with = "humantime_serde", default = "default_event_interval")]
+    pub interval: Duration,
+    /// Largest `eth_getLogs` range requested at once; split further if the RPC refuses it.
+    #[serde(default = "default_max_block_range")]
+    pub max_block_range: u64,
+    /// Blocks to scan back from head when there is no `last_scan_block` checkpoint.
+    #[serde(default = "default_lookback_blocks")]
+    pub lookback_blocks: u64,
+    /// How long to poll over HTTP after the WebSocket drops before reconnecting.
+    #[serde(with = "humantime_serde", default = "default_ws_retry")]
+    pub ws_retry: Duration,
+}
+
+fn default_true() -> bool { true }
+fn default_event_interval() -> Duration { Duration::from_secs(2) }
+fn default_max_block_range() -> u64 { 1000 }
+fn default_lookback_blocks() -> u64 { 10_000 }
+fn default_ws_retry() -> Duration { Duration::from_secs(30) }
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_true(),
+            interval: default_event_interval(),
+            max_block_range: default_max_block_range(),
+            lookback_blocks: default_lookback_blocks(),
+            ws_retry: default_ws_retry(),
+        }
+    }
+}
+
+/// Claim gate comparing an activity's lifecycle gas cost to its reward.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ProfitabilityConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_claim_gas")]
+    pub claim_gas: u64,
+    #[serde(default = "default_heartbeat_gas")]
+    pub heartbeat_gas: u64,
+    #[serde(default = "default_complete_gas")]
+    pub complete_gas: u64,
+    /// Heartbeat cadence used to count heartbeats over a queue's `expected_duration`.
+    #[serde(with = "humantime_serde", default = "default_heartbeat_interval")]
+    pub heartbeat_interval: Duration,
+    /// Extra margin the reward must cover on top of the gas cost.
+    #[serde(default)]
+    pub min_margin_percent: u32,
+    /// Skip activities whose reward is unknown instead of claiming them.
+    #[serde(default)]
+    pub require_reward: bool,
+}
+
+fn default_claim_gas() -> u64 { 120_000 }
+fn default_heartbeat_gas() -> u64 { 60_000 }
+fn default_complete_gas() -> u64 { 150_000 }
+fn default_heartbeat_interval() -> Duration { Duration::from_secs(10) }
+
+impl Default for ProfitabilityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            claim_gas: default_claim_gas(),
+            heartbeat_gas: default_heartbeat_gas(),
+            complete_gas: default_complete_gas(),
+            heartbeat_interval: default_heartbeat_interval(),
+            min_margin_percent: 0,
+            require_reward: false,
+        }
+    }
+}
+
+/// WCP HTTP API (storage URL refresh for the WEP, probes, metrics, admin).
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ApiConfig {
+    /// Listen address; empty disables the API.
+    #[serde(default = "default_api_listen")]
+    pub listen: String,
+    /// Bearer token for `/admin/*`; empty disables the admin routes.
+    /// Prefer `WCP__API__ADMIN_TOKEN` over committing it to a config file.
+    #[serde(default)]
+    pub admin_token: String,
+}
+
+fn default_api_listen() -> String { "127.0.0.1:9100".to_string() }
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self { listen: default_api_listen(), admin_token: String::new() }
+    }
+}
+
+/// `[storage]`: where the local database lives and how RocksDB is tuned.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct StorageConfig {
+    /// Holds `wcp.db` and `wcp.lock`; give each WCP on a host its own.
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+    #[serde(default)]
+    pub compression: DbCompression,
+    /// Block cache shared by all column families, in MiB.
+    #[serde(default = "default_block_cache_mb")]
+    pub block_cache_mb: usize,
+    /// Memtable size per column family, in MiB.
+    #[serde(default = "default_write_buffer_mb")]
+    pub write_buffer_mb: usize,
+    /// -1 keeps every SST file open.
+    #[serde(default = "default_max_open_files")]
+    pub max_open_files: i32,
+    /// Separate WAL directory (e.g. a faster disk); inside `wcp.db` if unset.
+    #[serde(default)]
+    pub wal_dir: Option<PathBuf>,
+    /// Flush memtables once the WALs exceed this many MiB; 0 lets RocksDB decide.
+    #[serde(default)]
+    pub max_total_wal_mb: u64,
+    /// fsync the WAL on every write instead of leaving it to the OS.
+    #[serde(default)]
+    pub sync_writes: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DbCompression {
+    None,
+    #[default]
+    Lz4,
+    Zstd,
+}
+
+fn default_data_dir() -> PathBuf { PathBuf::from(".") }
+fn default_block_cache_mb() -> usize { 64 }
+fn default_write_buffer_mb() -> usize { 16 }
+fn default_max_open_files() -> i32 { -1 }
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: default_data_dir(),
+            compression: DbCompression::default(),
+            block_cache_mb: default_block_cache_mb(),
+            write_buffer_mb: default_write_buffer_mb(),
+            max_open_files: default_max_open_files(),
+            wal_dir: None,
+            max_total_wal_mb: 0,
+            sync_writes: false,
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn db_path(&self) -> PathBuf { self.data_dir.join("wcp.db") }
+    pub fn lock_path(&self) -> PathBuf { self.data_dir.join("wcp.lock") }
+
+    /// Only the `[storage]` section from the same sources as [`WcpConfig::from_env`],
+    /// for tools that must find the DB without a full (wallet-bearing) config.
+    pub fn from_env() -> anyhow::Result<Self> {
+        section("storage")
+    }
+}
+
+/// `[signer]`: where the worker's transaction signing key comes from.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct SignerConfig {
+    #[serde(default)]
+    pub kind: SignerKind,
+    /// `keystore`: encrypted JSON keystore; `key_file`: file holding the hex key.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// `keystore`: file holding the password; `password_env` is read when unset.
+    #[serde(default)]
+    pub password_file: Option<PathBuf>,
+    #[serde(default = "default_password_env")]
+    pub password_env: String,
+    /// `remote`: base URL of a web3signer-style signer.
+    #[serde(default)]
+    pub url: String,
+    /// `remote`: per-request timeout.
+    #[serde(with = "humantime_serde", default = "default_signer_timeout")]
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerKind {
+    /// `ethereum.wallet_private_key` in plaintext.
+    #[default]
+    PrivateKey,
+    Keystore,
+    KeyFile,
+    Remote,
+}
+
+impl SignerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignerKind::PrivateKey => "private_key",
+            SignerKind::Keystore => "keystore",
+            SignerKind::KeyFile => "key_file",
+            SignerKind::Remote => "remote",
+        }
+    }
+}
+
+fn default_password_env() -> String { "WCP_KEYSTORE_PASSWORD".to_string() }
+fn default_signer_timeout() -> Duration { Duration::from_secs(10) }
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        Self {
+            kind: SignerKind::default(),
+            path: None,
+            password_file: None,
+            password_env: default_password_env(),
+            url: String::new(),
+            timeout: default_signer_timeout(),
+        }
+    }
+}
+
+/// `[logging]`: log line format and default level.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+    /// `EnvFilter` directives used when `RUST_LOG` is unset.
+    #[serde(default = "default_log_level")]
+    pub level: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with the enclosing spans' fields.
+    Json,
+}
+
+fn default_log_level() -> String { "info".to_string() }
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self { format: LogFormat::default(), level: default_log_level() }
+    }
+}
+
+impl LoggingConfig {
+    /// Only the `[logging]` section, so logging is set up before the full config loads.
+    pub fn from_env() -> anyhow::Result<Self> {
+        section("logging")
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// `[telemetry]`: OpenTelemetry trace export. Off while `otlp_endpoint` is empty.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct TelemetryConfig {
+    /// OTLP/HTTP collector base URL, e.g. `http://127.0.0.1:4318`; spans go to `{otlp_endpoint}/v1/traces`.
+    #[serde(default)]
+    pub otlp_endpoint: String,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Fraction of activities traced, decided per activity so every component agrees.
+    #[serde(default = "default_sample_ratio")]
+    pub sample_ratio: f64,
+    #[serde(default = "default_export_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+fn default_service_name() -> String { "subnet-wcp".to_string() }
+fn default_sample_ratio() -> f64 { 1.0 }
+fn default_export_timeout() -> Duration { Duration::from_secs(10) }
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: String::new(),
+            service_name: default_service_name(),
+            sample_ratio: default_sample_ratio(),
+            timeout: default_export_timeout(),
+        }
+    }
+}
+
+impl TelemetryConfig {
+    /// Only the `[telemetry]` section, loaded alongside `[logging]`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        section("telemetry")
+    }
+}
+
+/// `[reload]`: picking up `[scheduler]` and `[tx_policy]` changes without a restart.
+/// SIGHUP always reloads; this only controls watching the config file.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ReloadConfig {
+    /// Reload when the config file's modification time changes.
+    #[serde(default = "default_true")]
+    pub watch_file: bool,
+    /// How often to check the file.
+    #[serde(with = "humantime_serde", default = "default_reload_interval")]
+    pub interval: Duration,
+}
+
+fn default_reload_interval() -> Duration { Duration::from_secs(5) }
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self { watch_file: default_true(), interval: default_reload_interval() }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct TxPolicyConfig {
     pub gas_bump_percent: u32,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ProtocolConfig {
     pub contract_min: String,
     pub contract_max: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct WcpConfig {
     pub ethereum: EthereumConfig,
     pub subnet_api: SubnetApiConfig,
     pub scheduler: SchedulerConfig,
     pub tx_policy: TxPolicyConfig,
+    #[serde(default)]
+    pub reconciler: ReconcilerConfig,
+    #[serde(default)]
+    pub events: EventsConfig,
+    #[serde(default)]
+    pub profitability: ProfitabilityConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub signer: SignerConfig,
+    #[serde(default)]
+    pub reload: ReloadConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
     pub wep_endpoint: Option<String>,           // REST API endpoint (default)
     #[serde(alias = "wep_endpoint_http")]
     pub wep_grpc_endpoint: Option<String>,      // gRPC endpoint (legacy)
@@ -50,31 +481,66 @@ pub struct WcpConfig {
     pub dev_mode: Option<bool>,
 }
 
+/// `configs/{ENVIRONMENT}.toml`, `local` by default.
+pub fn config_file() -> String {
+    let env = std::env::var("ENVIRONMENT").unwrap_or_else(|_| "local".to_string());
+    format!("configs/{}.toml", env)
+}
+
+/// The config file overlaid with `WCP__*` env overrides.
+fn sources() -> config::ConfigBuilder<config::builder::DefaultState> {
+    config::Config::builder()
+        .add_source(config::File::with_name(&config_file()).required(false))
+        .add_source(config::Environment::with_prefix("WCP").separator("__"))
+}
+
+/// One section from [`sources`], defaulted when absent.
+fn section<T: serde::de::DeserializeOwned + Default>(name: &str) -> anyhow::Result<T> {
+    match sources().build()?.get::<T>(name) {
+        Ok(section) => Ok(section),
+        Err(config::ConfigError::NotFound(_)) => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
 impl WcpConfig {
+    /// The validated config; fails listing every [`ConfigProblem`] found.
     pub fn from_env() -> anyhow::Result<Self> {
-        let env = std::env::var("ENVIRONMENT").unwrap_or_else(|_| "local".to_string());
-        let file = format!("configs/{}.toml", env);
-        let c = config::Config::builder()
-            .add_source(config::File::with_name(&file).required(false))
-            .add_source(config::Environment::with_prefix("WCP").separator("__"))
-            .build()?;
-        let mut cfg: WcpConfig = c.try_deserialize()?;
+        let (cfg, problems) = Self::load()?;
+        if !problems.is_empty() {
+            anyhow::bail!("{}", report(&problems));
+        }
+        Ok(cfg)
+    }
+
+    /// The config plus everything wrong with it, including keys nothing reads.
+    /// Fails only when it cannot be parsed at all (missing sections, wrong types).
+    pub fn load() -> anyhow::Result<(Self, Vec<ConfigProblem>)> {
+        let c = sources().build()?;
+        let mut unknown = Vec::new();
+        let mut cfg: WcpConfig = serde_ignored::deserialize(c, |path| unknown.push(path.to_string()))
+            .map_err(|e| anyhow::anyhow!("{}: {}", config_file(), e))?;
 
         // Default RPC to devnet if not provided
         if cfg.ethereum.rpc_url.trim().is_empty() {
             cfg.ethereum.rpc_url = "https://devnet-proteus.psdnrpc.io".to_string();
         }
 
-        // Require wallet address and private key to be set
-        if cfg.ethereum.wallet_private_key.trim().is_empty() || cfg.ethereum.wallet_address.trim().is_empty() {
-            anyhow::bail!(
-                "Missing ethereum wallet configuration. Ensure both wallet_private_key and wallet_address are set via {} or environment variables WCP__ETHEREUM__WALLET_PRIVATE_KEY and WCP__ETHEREUM__WALLET_ADDRESS.",
-                file
-            );
-        }
-
-        Ok(cfg)
+        let mut problems: Vec<ConfigProblem> = unknown
+            .into_iter()
+            .map(|key| ConfigProblem::new(key, "unknown key (misspelt, or under the wrong [section]?)"))
+            .collect();
+        problems.extend(cfg.validate());
+        Ok((cfg, problems))
     }
 }
 
-
+/// `problems` as one message naming the config sources.
+pub fn report(problems: &[ConfigProblem]) -> String {
+    let mut out = format!("invalid config ({} and WCP__* environment overrides):", config_file());
+    for p in problems {
+        out.push_str("\n  - ");
+        out.push_str(&p.to_string());
+    }
+    out
+}
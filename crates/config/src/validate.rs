@@ -0,0 +1,390 @@
+//! Checks that `serde` cannot express: address checksums, URL schemes, semver
+//! ranges and cross-field requirements. Every problem is collected so one run
+//! reports them all.
+use std::fmt;
+use std::net::SocketAddr;
+
+use alloy_primitives::Address;
+use serde::Serialize;
+
+use crate::{SignerKind, WcpConfig};
+
+/// One thing wrong with the config, by dotted key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigProblem {
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigProblem {
+    pub fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { key: key.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+impl WcpConfig {
+    pub fn validate(&self) -> Vec<ConfigProblem> {
+        let mut p = Problems::default();
+        let eth = &self.ethereum;
+
+        p.url("ethereum.rpc_url", &eth.rpc_url, &["http", "https"]);
+        if let Some(ws) = eth.ws_url.as_deref().filter(|u| !u.trim().is_empty()) {
+            p.url("ethereum.ws_url", ws, &["ws", "wss"]);
+        }
+        if !eth.wallet_address.trim().is_empty() {
+            p.address("ethereum.wallet_address", &eth.wallet_address);
+        }
+        p.address("ethereum.workflow_engine_address", &eth.workflow_engine_address);
+        p.address("ethereum.task_queue_address", &eth.task_queue_address);
+        p.address("ethereum.multicall3_address", &eth.multicall3_address);
+        p.address("ethereum.subnet_control_plane_address", &eth.subnet_control_plane_address);
+
+        match self.signer.kind {
+            SignerKind::PrivateKey => {
+                let key = eth.wallet_private_key.trim();
+                if key.is_empty() {
+                    p.push(
+                        "ethereum.wallet_private_key",
+                        "required by signer.kind = \"private_key\" (the default); configure [signer] with kind = \"keystore\", \"key_file\" or \"remote\", or set WCP__ETHEREUM__WALLET_PRIVATE_KEY for development",
+                    );
+                } else {
+                    let hex = key.strip_prefix("0x").unwrap_or(key);
+                    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        p.push("ethereum.wallet_private_key", "must be 32 bytes of hex");
+                    }
+                }
+            }
+            SignerKind::Keystore | SignerKind::KeyFile => {
+                if self.signer.path.is_none() {
+                    p.push("signer.path", format!("required by signer.kind = \"{}\"", self.signer.kind.as_str()));
+                }
+            }
+            SignerKind::Remote => p.url("signer.url", &self.signer.url, &["http", "https"]),
+        }
+
+        if !self.subnet_api.grpc_endpoint.trim().is_empty() {
+            p.url("subnet_api.grpc_endpoint", &self.subnet_api.grpc_endpoint, &["http", "https"]);
+        }
+        if let Some(wep) = &self.wep_endpoint {
+            p.url("wep_endpoint", wep, &["http", "https"]);
+        }
+        if let Some(wep) = self.wep_grpc_endpoint.as_deref().filter(|u| !u.trim().is_empty()) {
+            p.url("wep_grpc_endpoint", wep, &["http", "https"]);
+        }
+
+        if self.scheduler.max_inflight == 0 {
+            p.push("scheduler.max_inflight", "must be at least 1");
+        }
+        let queues = self.scheduler.queues();
+        if queues.is_empty() {
+            p.push("scheduler.queues", "no enabled queues; set scheduler.queues (or scheduler.queue_name)");
+        }
+        for (i, q) in queues.iter().enumerate() {
+            if queues[..i].iter().any(|other| other.name == q.name) {
+                p.push("scheduler.queues", format!("queue {:?} is listed twice", q.name));
+            }
+            if q.weight == 0 {
+                p.push("scheduler.queues", format!("queue {:?} has weight 0; disable it with enabled = false instead", q.name));
+            }
+        }
+
+        let min = p.semver("protocol.contract_min", &self.protocol.contract_min);
+        let max = p.semver("protocol.contract_max", &self.protocol.contract_max);
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                p.push("protocol", format!("contract_min {} is above contract_max {}", min, max));
+            }
+        }
+
+        if !self.telemetry.otlp_endpoint.trim().is_empty() {
+            p.url("telemetry.otlp_endpoint", &self.telemetry.otlp_endpoint, &["http", "https"]);
+        }
+        if !(0.0..=1.0).contains(&self.telemetry.sample_ratio) {
+            p.push("telemetry.sample_ratio", format!("{} is not between 0 and 1", self.telemetry.sample_ratio));
+        }
+
+        if !self.api.listen.trim().is_empty() {
+            match self.api.listen.parse::<SocketAddr>() {
+                Err(_) => p.push("api.listen", format!("{:?} is not a host:port socket address", self.api.listen)),
+                // `/storage/refresh` has no auth of its own; only the WEP on this host may reach it.
+                Ok(addr) if !addr.ip().is_loopback() => p.push(
+                    "api.listen",
+                    format!("{} is not a loopback address; put a proxy in front to expose the API beyond localhost", addr),
+                ),
+                Ok(_) => {}
+            }
+        }
+        p.0
+    }
+}
+
+#[derive(Default)]
+struct Problems(Vec<ConfigProblem>);
+
+impl Problems {
+    fn push(&mut self, key: &str, message: impl Into<String>) {
+        self.0.push(ConfigProblem::new(key, message));
+    }
+
+    fn url(&mut self, key: &str, value: &str, schemes: &[&str]) {
+        if value.trim().is_empty() {
+            return self.push(key, "required");
+        }
+        match url::Url::parse(value.trim()) {
+            Ok(u) if !schemes.contains(&u.scheme()) => {
+                self.push(key, format!("{:?} must use {}", value, schemes.join(" or ")));
+            }
+            Ok(u) if u.host_str().is_none_or(str::is_empty) => self.push(key, format!("{:?} has no host", value)),
+            Ok(_) => {}
+            Err(e) => self.push(key, format!("{:?} is not a URL: {}", value, e)),
+        }
+    }
+
+    /// Mixed-case addresses must carry a valid EIP-55 checksum; all-lower/upper are accepted as-is.
+    fn address(&mut self, key: &str, value: &str) {
+        let value = value.trim();
+        match value.parse::<Address>() {
+            Ok(addr) => {
+                let hex = value.strip_prefix("0x").unwrap_or(value);
+                let mixed = hex.bytes().any(|b| b.is_ascii_lowercase()) && hex.bytes().any(|b| b.is_ascii_uppercase());
+                if mixed && addr.to_checksum(None)[2..] != *hex {
+                    self.push(key, format!("{} has a bad EIP-55 checksum; check it for typos", value));
+                }
+            }
+            Err(_) if value.is_empty() => self.push(key, "required"),
+            Err(e) => self.push(key, format!("{:?} is not an address: {}", value, e)),
+        }
+    }
+
+    fn semver(&mut self, key: &str, value: &str) -> Option<semver::Version> {
+        match semver::Version::parse(value.trim()) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.push(key, format!("{:?} is not a semver version: {}", value, e));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r#"
+        wep_endpoint = "http://127.0.0.1:8080"
+
+        [ethereum]
+        rpc_url = "https://rpc.example"
+        wallet_private_key = "0x0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"
+        workflow_engine_address = "0x3C3Af67a24Ca3E9c60cc70e8e6533246Bb2Ba2cf"
+        task_queue_address = "0x904410cF5B972E5F4a7771c5f6198F72091914F2"
+        multicall3_address = "0xcA11bde05977b3631167028862bE2a173976CA11"
+        subnet_control_plane_address = "0xdC805e279e3A4C1F8d244858CaD99f4b5FF9cC0A"
+
+        [subnet_api]
+        grpc_endpoint = "http://127.0.0.1:50051"
+
+        [scheduler]
+        poll_interval = "1s"
+        max_inflight = 4
+
+        [[scheduler.queues]]
+        name = "video"
+
+        [tx_policy]
+        gas_bump_percent = 10
+
+        [protocol]
+        contract_min = "0.2.0"
+        contract_max = "0.2.0"
+    "#;
+
+    fn valid() -> WcpConfig {
+        config::Config::builder()
+            .add_source(config::File::from_str(VALID, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    /// The problems `edit` introduces into an otherwise valid config.
+    fn problems(edit: impl FnOnce(&mut WcpConfig)) -> Vec<ConfigProblem> {
+        let mut cfg = valid();
+        edit(&mut cfg);
+        cfg.validate()
+    }
+
+    fn problem_keys(edit: impl FnOnce(&mut WcpConfig)) -> Vec<String> {
+        problems(edit).into_iter().map(|p| p.key).collect()
+    }
+
+    /// `edit` must yield exactly one problem, at `key`, whose message contains `message`.
+    fn assert_problem(edit: impl FnOnce(&mut WcpConfig), key: &str, message: &str) {
+        let found = problems(edit);
+        assert!(found.len() == 1 && found[0].key == key && found[0].message.contains(message), "want {key}: {message:?}, got {found:?}");
+    }
+
+    #[test]
+    fn base_config_is_valid() {
+        assert_eq!(valid().validate(), Vec::new());
+    }
+
+    #[test]
+    fn api_listen_must_be_loopback() {
+        for (listen, token, ok) in [
+            ("127.0.0.1:9100", "", true),
+            ("[::1]:9100", "", true),
+            ("", "", true),
+            ("0.0.0.0:9100", "", false),
+            ("10.0.0.5:9100", "", false),
+            // The admin token does not cover `/storage/refresh`
+            ("0.0.0.0:9100", "secret", false),
+            ("localhost:9100", "", false),
+        ] {
+            let keys = problem_keys(|c| {
+                c.api.listen = listen.into();
+                c.api.admin_token = token.into();
+            });
+            assert_eq!(keys.is_empty(), ok, "{listen:?} with token {token:?}: {keys:?}");
+        }
+    }
+
+    #[test]
+    fn addresses_need_a_valid_checksum_only_when_mixed_case() {
+        for (address, problem) in [
+            ("0x3C3Af67a24Ca3E9c60cc70e8e6533246Bb2Ba2cf", None),
+            ("0x3c3af67a24ca3e9c60cc70e8e6533246bb2ba2cf", None),
+            ("0x3C3AF67A24CA3E9C60CC70E8E6533246BB2BA2CF", None),
+            ("3C3Af67a24Ca3E9c60cc70e8e6533246Bb2Ba2cf", None),
+            ("0x3c3Af67a24Ca3E9c60cc70e8e6533246Bb2Ba2cf", Some("bad EIP-55 checksum")),
+            ("0x3C3Af67a24Ca3E9c60cc70e8e6533246Bb2Ba2cF", Some("bad EIP-55 checksum")),
+            ("0x3C3Af67a24Ca3E9c60cc70e8e6533246Bb2Ba2", Some("is not an address")),
+            ("0xZZ3Af67a24Ca3E9c60cc70e8e6533246Bb2Ba2cf", Some("is not an address")),
+            ("", Some("required")),
+        ] {
+            let edit = |c: &mut WcpConfig| c.ethereum.task_queue_address = address.into();
+            match problem {
+                None => assert_eq!(problems(edit), Vec::new(), "{address:?}"),
+                Some(message) => assert_problem(edit, "ethereum.task_queue_address", message),
+            }
+        }
+        // Optional, but checked when set
+        assert_eq!(problem_keys(|c| c.ethereum.wallet_address = String::new()), Vec::<String>::new());
+        assert_problem(|c| c.ethereum.wallet_address = "0x8b997e52A44DE1E00cB9aD1ef376BFc01cCc1057".into(), "ethereum.wallet_address", "checksum");
+    }
+
+    #[test]
+    fn urls_need_an_allowed_scheme_and_a_host() {
+        for (url, problem) in [
+            ("https://rpc.example", None),
+            ("http://127.0.0.1:8545", None),
+            (" https://rpc.example/path ", None),
+            ("ws://rpc.example", Some("must use http or https")),
+            ("ftp://rpc.example", Some("must use http or https")),
+            ("http://", Some("empty host")),
+            ("https://:8545", Some("empty host")),
+            ("rpc.example:8545", Some("must use http or https")),
+            ("not a url", Some("is not a URL")),
+            ("", Some("required")),
+        ] {
+            let edit = |c: &mut WcpConfig| c.ethereum.rpc_url = url.into();
+            match problem {
+                None => assert_eq!(problems(edit), Vec::new(), "{url:?}"),
+                Some(message) => assert_problem(edit, "ethereum.rpc_url", message),
+            }
+        }
+        assert_problem(|c| c.ethereum.ws_url = Some("https://rpc.example".into()), "ethereum.ws_url", "must use ws or wss");
+        assert_problem(|c| c.wep_endpoint = Some("http://".into()), "wep_endpoint", "empty host");
+        assert_eq!(problem_keys(|c| c.ethereum.ws_url = Some(" ".into())), Vec::<String>::new());
+    }
+
+    #[test]
+    fn protocol_range_must_parse_and_be_ordered() {
+        for (min, max, problem) in [
+            ("0.1.0", "0.2.0", None),
+            ("0.2.0", "0.2.0", None),
+            ("0.2.0-rc.1", "0.2.0", None),
+            ("0.3.0", "0.2.0", Some(("protocol", "contract_min 0.3.0 is above contract_max 0.2.0"))),
+            ("0.2.0", "0.2.0-rc.1", Some(("protocol", "is above"))),
+            ("0.2", "0.2.0", Some(("protocol.contract_min", "is not a semver version"))),
+            ("0.2.0", "v0.2.0", Some(("protocol.contract_max", "is not a semver version"))),
+            ("", "0.2.0", Some(("protocol.contract_min", "is not a semver version"))),
+        ] {
+            let edit = |c: &mut WcpConfig| {
+                c.protocol.contract_min = min.into();
+                c.protocol.contract_max = max.into();
+            };
+            match problem {
+                None => assert_eq!(problems(edit), Vec::new(), "{min}..{max}"),
+                Some((key, message)) => assert_problem(edit, key, message),
+            }
+        }
+    }
+
+    #[test]
+    fn queues_must_be_unique_with_a_nonzero_weight() {
+        assert_problem(
+            |c| {
+                let q = c.scheduler.queues[0].clone();
+                c.scheduler.queues.push(q);
+            },
+            "scheduler.queues",
+            "\"video\" is listed twice",
+        );
+        assert_problem(|c| c.scheduler.queues[0].weight = 0, "scheduler.queues", "has weight 0");
+        assert_problem(|c| c.scheduler.queues[0].enabled = false, "scheduler.queues", "no enabled queues");
+        // A disabled duplicate or zero weight is never scheduled, so not a problem.
+        assert_eq!(
+            problem_keys(|c| {
+                let mut q = c.scheduler.queues[0].clone();
+                q.enabled = false;
+                q.weight = 0;
+                c.scheduler.queues.push(q);
+            }),
+            Vec::<String>::new()
+        );
+        assert_problem(|c| c.scheduler.max_inflight = 0, "scheduler.max_inflight", "at least 1");
+    }
+
+    #[test]
+    fn sample_ratio_must_be_a_fraction() {
+        for (ratio, ok) in [(0.0, true), (0.25, true), (1.0, true), (-0.1, false), (1.5, false), (f64::NAN, false)] {
+            let keys = problem_keys(|c| c.telemetry.sample_ratio = ratio);
+            assert_eq!(keys.is_empty(), ok, "{ratio}: {keys:?}");
+            if !ok {
+                assert_eq!(keys, ["telemetry.sample_ratio"]);
+            }
+        }
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let keys = problem_keys(|c| {
+            c.ethereum.rpc_url = "ftp://rpc.example".into();
+            c.ethereum.multicall3_address = "0xca11bde05977b3631167028862bE2a173976CA11".into();
+            c.ethereum.wallet_private_key = "0x1234".into();
+            c.scheduler.queues[0].weight = 0;
+            c.protocol.contract_min = "0.3.0".into();
+            c.telemetry.sample_ratio = 2.0;
+        });
+        assert_eq!(
+            keys,
+            [
+                "ethereum.rpc_url",
+                "ethereum.multicall3_address",
+                "ethereum.wallet_private_key",
+                "scheduler.queues",
+                "protocol",
+                "telemetry.sample_ratio",
+            ]
+        );
+    }
+}
@@ -1,12 +1,515 @@
-use anyhow::Result;
+//! Chain event intake for the WCP.
+//!
+//! [`EventPoller`] scans TaskQueue and WorkflowEngine logs with `eth_getLogs`
+//! in bounded block ranges, decodes them into [`ChainEvent`]s and routes them
+//! over bounded channels: enqueues to the Poller, events naming our worker to
+//! the reconciler. Progress is checkpointed under [`keys::LAST_SCAN_BLOCK`]
+//! after each range, so a restart resumes where it stopped; receivers must
+//! tolerate replays of the last range.
+//!
+//! With a WebSocket URL configured, new heads and logs arrive by subscription
+//! and HTTP polling only runs while the socket is down. Events seen on both
+//! paths are routed once. Heads can run ahead of logs still in flight, so the
+//! checkpoint is rewound by [`WS_OVERLAP_BLOCKS`] whenever a session starts or
+//! ends. Logs a reorg removes are forgotten, so their replacements are routed.
+use anyhow::{bail, Result};
+use alloy::primitives::{Address, B256};
+use alloy::providers::{Provider, ProviderBuilder, WsConnect};
+use alloy::rpc::types::{Filter, Log};
+use alloy::sol_types::SolEvent;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use subnet_wcp_config::EventsConfig;
+use subnet_wcp_metrics as metrics;
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
+use subnet_wcp_persistence::records::ScanCheckpoint;
+use subnet_wcp_persistence::{keys, KvStore};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
 
-pub struct EventPoller;
+pub mod abi {
+    use alloy::sol;
+
+    sol! {
+        /// TaskQueue and WorkflowEngine events the WCP reacts to.
+        event ActivityEnqueued(bytes32 indexed activityId, bytes32 indexed workflowInstanceId, string queueName);
+        event ActivityClaimed(bytes32 indexed activityId, address indexed worker);
+        event ActivityHeartbeat(bytes32 indexed activityId, address indexed worker, uint8 progress);
+        event ActivityCompleted(bytes32 indexed activityId, address indexed worker, bool success);
+        event ActivityFailed(bytes32 indexed activityId, address indexed worker, string reason);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    Enqueued { queue: String },
+    Claimed { worker: Address },
+    Heartbeat { worker: Address, progress: u8 },
+    Completed { worker: Address, success: bool },
+    Failed { worker: Address, reason: String },
+}
+
+/// A decoded log; `(block_number, log_index)` identifies it uniquely.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainEvent {
+    pub activity_id: B256,
+    pub block_number: u64,
+    pub log_index: u64,
+    pub kind: EventKind,
+}
+
+impl EventKind {
+    /// Short name used in logs and metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Enqueued { .. } => "enqueued",
+            EventKind::Claimed { .. } => "claimed",
+            EventKind::Heartbeat { .. } => "heartbeat",
+            EventKind::Completed { .. } => "completed",
+            EventKind::Failed { .. } => "failed",
+        }
+    }
+}
+
+impl ChainEvent {
+    /// Worker the event is about; `None` for enqueues.
+    pub fn worker(&self) -> Option<Address> {
+        match &self.kind {
+            EventKind::Enqueued { .. } => None,
+            EventKind::Claimed { worker }
+            | EventKind::Heartbeat { worker, .. }
+            | EventKind::Completed { worker, .. }
+            | EventKind::Failed { worker, .. } => Some(*worker),
+        }
+    }
+
+    /// Decode a TaskQueue/WorkflowEngine log; `None` for unrelated or pending logs.
+    pub fn from_log(log: &Log) -> Option<Self> {
+        let block_number = log.block_number?;
+        let log_index = log.log_index?;
+        let (activity_id, kind) = match *log.topic0()? {
+            abi::ActivityEnqueued::SIGNATURE_HASH => {
+                let e = log.log_decode::<abi::ActivityEnqueued>().ok()?.inner.data;
+                (e.activityId, EventKind::Enqueued { queue: e.queueName })
+            }
+            abi::ActivityClaimed::SIGNATURE_HASH => {
+                let e = log.log_decode::<abi::ActivityClaimed>().ok()?.inner.data;
+                (e.activityId, EventKind::Claimed { worker: e.worker })
+            }
+            abi::ActivityHeartbeat::SIGNATURE_HASH => {
+                let e = log.log_decode::<abi::ActivityHeartbeat>().ok()?.inner.data;
+                (e.activityId, EventKind::Heartbeat { worker: e.worker, progress: e.progress })
+            }
+            abi::ActivityCompleted::SIGNATURE_HASH => {
+                let e = log.log_decode::<abi::ActivityCompleted>().ok()?.inner.data;
+                (e.activityId, EventKind::Completed { worker: e.worker, success: e.success })
+            }
+            abi::ActivityFailed::SIGNATURE_HASH => {
+                let e = log.log_decode::<abi::ActivityFailed>().ok()?.inner.data;
+                (e.activityId, EventKind::Failed { worker: e.worker, reason: e.reason })
+            }
+            _ => return None,
+        };
+        Some(Self { activity_id, block_number, log_index, kind })
+    }
+}
+
+/// Topic0 of every event in [`abi`], used to narrow `eth_getLogs`.
+pub fn signatures() -> Vec<B256> {
+    vec![
+        abi::ActivityEnqueued::SIGNATURE_HASH,
+        abi::ActivityClaimed::SIGNATURE_HASH,
+        abi::ActivityHeartbeat::SIGNATURE_HASH,
+        abi::ActivityCompleted::SIGNATURE_HASH,
+        abi::ActivityFailed::SIGNATURE_HASH,
+    ]
+}
+
+/// Blocks rescanned after a websocket session, covering logs that had not
+/// arrived when a later head moved the checkpoint past them.
+pub const WS_OVERLAP_BLOCKS: u64 = 64;
+
+/// Events already routed, keyed by `(block_number, log_index)`; bounded so a
+/// long-running node does not grow it without limit.
+#[derive(Default)]
+struct Recent {
+    order: VecDeque<(u64, u64)>,
+    set: HashSet<(u64, u64)>,
+}
+
+impl Recent {
+    const CAPACITY: usize = 4096;
+
+    /// `true` if `id` was not seen before.
+    fn insert(&mut self, id: (u64, u64)) -> bool {
+        if !self.set.insert(id) {
+            return false;
+        }
+        self.order.push_back(id);
+        if self.order.len() > Self::CAPACITY {
+            if let Some(old) = self.order.pop_front() {
+                self.set.remove(&old);
+            }
+        }
+        true
+    }
+
+    /// Forget `id` so a different log at the same position is routed again.
+    fn remove(&mut self, id: (u64, u64)) {
+        if self.set.remove(&id) {
+            self.order.retain(|o| *o != id);
+        }
+    }
+}
+
+pub struct EventPoller<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend = RocksBackend> {
+    store: KvStore<B>,
+    provider: P,
+    contracts: Vec<Address>,
+    interval: Duration,
+    max_block_range: u64,
+    lookback_blocks: u64,
+    ws_url: Option<String>,
+    ws_retry: Duration,
+    worker: Address,
+    enqueued_tx: mpsc::Sender<ChainEvent>,
+    worker_tx: mpsc::Sender<ChainEvent>,
+    recent: Mutex<Recent>,
+}
+
+impl<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend> EventPoller<P, B> {
+    pub fn new(
+        store: KvStore<B>,
+        provider: P,
+        contracts: Vec<Address>,
+        cfg: &EventsConfig,
+        worker: Address,
+        enqueued_tx: mpsc::Sender<ChainEvent>,
+        worker_tx: mpsc::Sender<ChainEvent>,
+    ) -> Self {
+        Self {
+            store,
+            provider,
+            contracts,
+            interval: cfg.interval,
+            max_block_range: cfg.max_block_range.max(1),
+            lookback_blocks: cfg.lookback_blocks,
+            ws_url: None,
+            ws_retry: cfg.ws_retry,
+            worker,
+            enqueued_tx,
+            worker_tx,
+            recent: Mutex::new(Recent::default()),
+        }
+    }
+
+    /// Subscribe to new heads and logs over `url`, polling over HTTP only
+    /// while the socket is down.
+    pub fn with_ws(mut self, url: String) -> Self {
+        self.ws_url = Some(url);
+        self
+    }
 
-impl EventPoller {
     pub async fn run(&self) -> Result<()> {
-        // TODO: subscribe to TaskQueue/WorkflowEngine events and surface to scheduler
+        let Some(url) = self.ws_url.as_deref() else {
+            self.poll_http(None).await;
+            return Ok(());
+        };
+        // The last run may have stopped mid-session.
+        self.rewind_checkpoint();
+        loop {
+            match self.run_ws(url).await {
+                Ok(()) => warn!("websocket subscription closed; falling back to HTTP polling"),
+                Err(e) => warn!(error = %e, "websocket subscription failed; falling back to HTTP polling"),
+            }
+            self.rewind_checkpoint();
+            self.poll_http(Some(Instant::now() + self.ws_retry)).await;
+        }
+    }
+
+    /// HTTP `eth_getLogs` polling every `interval`, until `until` if given.
+    async fn poll_http(&self, until: Option<Instant>) {
+        loop {
+            if let Err(e) = self.scan_to_head().await {
+                warn!(error = %e, "event scan failed; retrying next tick");
+            }
+            if until.is_some_and(|t| Instant::now() >= t) {
+                return;
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    /// One WebSocket session: subscribe, close the gap since the checkpoint
+    /// over HTTP, then route subscribed logs until the socket drops.
+    async fn run_ws(&self, url: &str) -> Result<()> {
+        let ws = ProviderBuilder::new().connect_ws(WsConnect::new(url)).await?;
+        let mut heads = ws.subscribe_blocks().await?;
+        let mut logs = ws.subscribe_logs(&self.filter()).channel_size(1024).await?;
+        info!(url, "subscribed to new heads and logs over websocket");
+
+        // Anything both the catch-up scan and the subscription deliver is deduplicated in `route`.
+        self.scan_to_head().await?;
+        let mut resume_from = self.checkpoint()?.unwrap_or_default();
+        loop {
+            tokio::select! {
+                log = logs.recv() => match log {
+                    Ok(log) if log.removed => self.forget(&log),
+                    Ok(log) => {
+                        if let Some(ev) = ChainEvent::from_log(&log) {
+                            resume_from = resume_from.max(ev.block_number.saturating_sub(1));
+                            self.route(ev).await;
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        // Dropped logs are newer than the last one routed; rescan from there over HTTP.
+                        self.save_checkpoint(resume_from)?;
+                        bail!("websocket log subscription lagged by {} messages", n);
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                head = heads.recv() => match head {
+                    // Logs of the newest block may still be in flight, and older ones can
+                    // lag behind heads too; the overlap rescan after the session covers them.
+                    Ok(head) => {
+                        let done = head.number.saturating_sub(1);
+                        if self.checkpoint()?.is_none_or(|cp| cp < done) {
+                            self.save_checkpoint(done)?;
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
+
+    /// Scan from the checkpoint (or `head - lookback_blocks`) up to the current head.
+    pub async fn scan_to_head(&self) -> Result<()> {
+        let head = self.provider.get_block_number().await?;
+        let mut from = match self.checkpoint()? {
+            Some(block) => block + 1,
+            None => {
+                let start = head.saturating_sub(self.lookback_blocks);
+                info!(head, start, "no scan checkpoint; starting from lookback");
+                start
+            }
+        };
+        while from <= head {
+            let to = head.min(from + self.max_block_range - 1);
+            let logs = self.get_logs_split(from, to).await?;
+            let mut routed = 0usize;
+            for ev in logs.iter().filter_map(ChainEvent::from_log) {
+                routed += self.route(ev).await as usize;
+            }
+            debug!(from, to, routed, "scanned block range");
+            self.save_checkpoint(to)?;
+            from = to + 1;
+        }
+        Ok(())
+    }
+
+    /// Send `ev` to its receiver unless it was routed already or concerns another worker.
+    async fn route(&self, ev: ChainEvent) -> bool {
+        let tx = match ev.worker() {
+            None => &self.enqueued_tx,
+            Some(w) if w == self.worker => &self.worker_tx,
+            Some(_) => return false,
+        };
+        let fresh = self.recent.lock().map(|mut r| r.insert((ev.block_number, ev.log_index))).unwrap_or(true);
+        if !fresh {
+            return false;
+        }
+        metrics::CHAIN_EVENTS.with_label_values(&[ev.kind.name()]).inc();
+        // A dropped receiver is fine; the checkpoint still advances.
+        let _ = tx.send(ev).await;
+        true
+    }
+
+    /// A log dropped by a reorg: route whatever replaces it at that position.
+    fn forget(&self, log: &Log) {
+        if let (Some(block), Some(index)) = (log.block_number, log.log_index) {
+            debug!(block, index, "log removed by reorg");
+            if let Ok(mut recent) = self.recent.lock() {
+                recent.remove((block, index));
+            }
+        }
+    }
+
+    /// Move the checkpoint back by [`WS_OVERLAP_BLOCKS`]; replays are deduplicated in `route`.
+    fn rewind_checkpoint(&self) {
+        let rewound = match self.checkpoint() {
+            Ok(Some(cp)) => self.save_checkpoint(cp.saturating_sub(WS_OVERLAP_BLOCKS)),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = rewound {
+            warn!(error = %e, "failed to rewind scan checkpoint");
+        }
+    }
+
+    fn checkpoint(&self) -> Result<Option<u64>> {
+        Ok(self.store.get_record::<ScanCheckpoint>(keys::LAST_SCAN_BLOCK)?.map(|cp| cp.block))
+    }
+
+    fn save_checkpoint(&self, block: u64) -> Result<()> {
+        self.store.put_record(keys::LAST_SCAN_BLOCK, &ScanCheckpoint { block, updated_at_ms: now_ms() })?;
+        metrics::EVENT_CHECKPOINT_BLOCK.set(block as i64);
         Ok(())
     }
+
+    fn filter(&self) -> Filter {
+        Filter::new().address(self.contracts.clone()).event_signature(signatures())
+    }
+
+    /// `eth_getLogs` over `[from, to]`, halving the range whenever the RPC
+    /// rejects it as too large. Logs come back in block order.
+    async fn get_logs_split(&self, from: u64, to: u64) -> Result<Vec<Log>> {
+        let mut out = Vec::new();
+        // Stack of pending ranges; the lower half is pushed last so it is fetched first.
+        let mut pending = vec![(from, to)];
+        while let Some((lo, hi)) = pending.pop() {
+            let filter = self.filter().from_block(lo).to_block(hi);
+            match self.provider.get_logs(&filter).await {
+                Ok(logs) => out.extend(logs),
+                Err(e) if hi > lo && is_range_error(&e.to_string()) => {
+                    let mid = lo + (hi - lo) / 2;
+                    debug!(lo, hi, mid, error = %e, "getLogs range rejected; splitting");
+                    pending.push((mid + 1, hi));
+                    pending.push((lo, mid));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Whether an RPC error means "ask for fewer blocks/logs"; providers word it differently.
+fn is_range_error(msg: &str) -> bool {
+    let msg = msg.to_lowercase();
+    ["block range", "range too", "too many", "exceed", "query returned more than", "-32005"]
+        .iter()
+        .any(|needle| msg.contains(needle))
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or_default()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::transports::mock::Asserter;
+    use subnet_wcp_persistence::backend::MemBackend;
+
+    const WORKER: Address = Address::repeat_byte(0x11);
+
+    type Channels = (mpsc::Receiver<ChainEvent>, mpsc::Receiver<ChainEvent>);
+
+    fn poller(asserter: Asserter, cfg: &EventsConfig) -> (EventPoller<impl Provider + Clone, MemBackend>, Channels) {
+        let provider = ProviderBuilder::new().connect_mocked_client(asserter);
+        let (enqueued_tx, enqueued_rx) = mpsc::channel(16);
+        let (worker_tx, worker_rx) = mpsc::channel(16);
+        let poller = EventPoller::new(KvStore::in_memory(), provider, vec![Address::ZERO], cfg, WORKER, enqueued_tx, worker_tx);
+        (poller, (enqueued_rx, worker_rx))
+    }
+
+    fn log_at(block: u64) -> Log {
+        Log { block_number: Some(block), log_index: Some(0), ..Default::default() }
+    }
+
+    fn event(block: u64, log_index: u64, worker: Address) -> ChainEvent {
+        ChainEvent { activity_id: B256::ZERO, block_number: block, log_index, kind: EventKind::Claimed { worker } }
+    }
+
+    #[test]
+    fn range_errors_are_recognised_across_providers() {
+        for (msg, expected) in [
+            ("query exceeds max block range 1000", true),
+            ("Block range is too large", true),
+            ("query returned more than 10000 results", true),
+            ("server error -32005: limit exceeded", true),
+            ("log response size exceeded", true),
+            ("connection refused", false),
+            ("execution reverted", false),
+        ] {
+            assert_eq!(is_range_error(msg), expected, "{msg}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_ranges_are_halved_and_returned_in_block_order() {
+        let asserter = Asserter::new();
+        // [0,7] and then [0,3] are rejected; [0,1], [2,3] and [4,7] succeed.
+        asserter.push_failure_msg("block range too large");
+        asserter.push_failure_msg("block range too large");
+        asserter.push_success(&vec![log_at(1)]);
+        asserter.push_success(&vec![log_at(2)]);
+        asserter.push_success(&vec![log_at(5), log_at(7)]);
+        let (p, _rx) = poller(asserter.clone(), &EventsConfig::default());
+
+        let logs = p.get_logs_split(0, 7).await.unwrap();
+        assert_eq!(logs.iter().map(|l| l.block_number.unwrap()).collect::<Vec<_>>(), vec![1, 2, 5, 7]);
+        assert!(asserter.read_q().is_empty());
+    }
+
+    #[tokio::test]
+    async fn other_errors_and_single_blocks_are_not_split() {
+        let asserter = Asserter::new();
+        asserter.push_failure_msg("connection refused");
+        asserter.push_failure_msg("block range too large");
+        let (p, _rx) = poller(asserter, &EventsConfig::default());
+
+        assert!(p.get_logs_split(0, 7).await.is_err());
+        assert!(p.get_logs_split(3, 3).await.is_err());
+    }
 
+    #[test]
+    fn recent_dedups_evicts_oldest_and_forgets() {
+        let mut recent = Recent::default();
+        assert!(recent.insert((1, 0)));
+        assert!(!recent.insert((1, 0)));
+        recent.remove((1, 0));
+        assert!(recent.insert((1, 0)));
+
+        for i in 0..Recent::CAPACITY as u64 {
+            assert!(recent.insert((2, i)));
+        }
+        assert_eq!(recent.order.len(), Recent::CAPACITY);
+        // (1, 0) was the oldest and has been evicted.
+        assert!(recent.insert((1, 0)));
+        assert!(!recent.insert((2, Recent::CAPACITY as u64 - 1)));
+    }
+
+    #[tokio::test]
+    async fn events_route_once_until_a_reorg_removes_them() {
+        let (p, (_enqueued, mut worker)) = poller(Asserter::new(), &EventsConfig::default());
+
+        assert!(p.route(event(10, 3, WORKER)).await);
+        assert!(!p.route(event(10, 3, WORKER)).await);
+        assert!(!p.route(event(11, 0, Address::repeat_byte(0x22))).await);
+        assert_eq!(worker.recv().await.unwrap().block_number, 10);
+
+        let removed = Log { block_number: Some(10), log_index: Some(3), removed: true, ..Default::default() };
+        p.forget(&removed);
+        assert!(p.route(event(10, 3, WORKER)).await);
+    }
+
+    #[test]
+    fn rewind_moves_the_checkpoint_back_by_the_overlap() {
+        let (p, _rx) = poller(Asserter::new(), &EventsConfig::default());
+        p.rewind_checkpoint();
+        assert_eq!(p.checkpoint().unwrap(), None);
+
+        p.save_checkpoint(1_000).unwrap();
+        p.rewind_checkpoint();
+        assert_eq!(p.checkpoint().unwrap(), Some(1_000 - WS_OVERLAP_BLOCKS));
+        p.save_checkpoint(10).unwrap();
+        p.rewind_checkpoint();
+        assert_eq!(p.checkpoint().unwrap(), Some(0));
+    }
+}
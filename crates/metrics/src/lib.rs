@@ -0,0 +1,137 @@
+//! Prometheus metrics shared by all WCP components, served at `/metrics`.
+//!
+//! Metrics register themselves in [`REGISTRY`] on first use; [`init`] touches
+//! all of them so every series is exported from the first scrape.
+use once_cell::sync::Lazy;
+use prometheus::core::Collector;
+use prometheus::{
+    exponential_buckets, CounterVec, Encoder, Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+fn register<C: Collector + Clone + 'static>(c: C) -> C {
+    REGISTRY.register(Box::new(c.clone())).expect("metric registered twice");
+    c
+}
+
+fn counter_vec(name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    register(IntCounterVec::new(Opts::new(name, help), labels).expect("valid metric"))
+}
+
+fn histogram_vec(name: &str, help: &str, labels: &[&str], buckets: Vec<f64>) -> HistogramVec {
+    register(HistogramVec::new(HistogramOpts::new(name, help).buckets(buckets), labels).expect("valid metric"))
+}
+
+// Poller / events
+
+pub static POLL_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    histogram_vec("wcp_poll_duration_seconds", "pollActivity call latency", &["queue"], prometheus::DEFAULT_BUCKETS.to_vec())
+});
+/// `result`: found | empty | error.
+pub static POLLS: Lazy<IntCounterVec> = Lazy::new(|| counter_vec("wcp_polls_total", "pollActivity calls", &["queue", "result"]));
+pub static CLAIM_JOBS_ENQUEUED: Lazy<IntCounterVec> =
+    Lazy::new(|| counter_vec("wcp_claim_jobs_enqueued_total", "claim_job records written", &["queue"]));
+/// `kind`: enqueued | claimed | heartbeat | completed | failed.
+pub static CHAIN_EVENTS: Lazy<IntCounterVec> =
+    Lazy::new(|| counter_vec("wcp_chain_events_total", "Chain events routed to components", &["kind"]));
+pub static EVENT_CHECKPOINT_BLOCK: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::new("wcp_event_checkpoint_block", "Last block scanned for events").expect("valid metric"))
+});
+
+// Scheduler / claims
+
+pub static CLAIMS_SCHEDULED: Lazy<IntCounterVec> =
+    Lazy::new(|| counter_vec("wcp_claims_scheduled_total", "Claim intents written by the scheduler", &["queue"]));
+pub static PROFIT_SKIPS: Lazy<IntCounterVec> =
+    Lazy::new(|| counter_vec("wcp_profit_skips_total", "Claim jobs passed over by the profitability gate, per tick", &["queue"]));
+/// `result`: won | lost (claimed by another worker) | error.
+pub static CLAIMS: Lazy<IntCounterVec> = Lazy::new(|| counter_vec("wcp_claims_total", "Claim outcomes", &["result"]));
+
+// Assigner / storage
+
+/// `outcome`: ok | failed | rejected | timeout.
+pub static ASSIGNMENT_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    histogram_vec(
+        "wcp_assignment_duration_seconds",
+        "Time from WEP assignment to a terminal status",
+        &["outcome"],
+        exponential_buckets(1.0, 2.0, 13).expect("valid buckets"),
+    )
+});
+/// `outcome`: ok | failed | rejected | timeout | error (assignment not accepted).
+pub static ASSIGNMENTS: Lazy<IntCounterVec> =
+    Lazy::new(|| counter_vec("wcp_assignments_total", "WEP assignment outcomes", &["outcome"]));
+/// `source`: cache | broker | error.
+pub static PRESIGNS: Lazy<IntCounterVec> =
+    Lazy::new(|| counter_vec("wcp_presigns_total", "Presigned URL requests", &["method", "source"]));
+
+// Transactions
+
+/// `kind`: claim | heartbeat | complete | resume; `status`: confirmed | failed.
+pub static TXS: Lazy<IntCounterVec> = Lazy::new(|| counter_vec("wcp_txs_total", "Transactions by final status", &["kind", "status"]));
+pub static TX_CONFIRM_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    histogram_vec(
+        "wcp_tx_confirmation_seconds",
+        "Time from submission to confirmation",
+        &["kind"],
+        exponential_buckets(0.5, 2.0, 12).expect("valid buckets"),
+    )
+});
+pub static GAS_SPENT_WEI: Lazy<CounterVec> = Lazy::new(|| {
+    register(CounterVec::new(Opts::new("wcp_gas_spent_wei_total", "Fees paid for confirmed transactions"), &["kind"]).expect("valid metric"))
+});
+pub static GAS_BUMPS: Lazy<IntCounterVec> = Lazy::new(|| counter_vec("wcp_gas_bumps_total", "Fee bumps of stuck transactions", &["kind"]));
+
+// Reconciler / sampled state
+
+pub static REPAIRS: Lazy<IntCounterVec> =
+    Lazy::new(|| counter_vec("wcp_reconciler_repairs_total", "Local state repairs by the reconciler", &["repair"]));
+pub static RECONCILE_ERRORS: Lazy<IntCounterVec> =
+    Lazy::new(|| counter_vec("wcp_reconciler_errors_total", "getActivity failures skipped by reconcile passes", &["call"]));
+pub static KEYS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register(IntGaugeVec::new(Opts::new("wcp_keys", "Records per key prefix"), &["prefix"]).expect("valid metric"))
+});
+pub static WALLET_BALANCE_WEI: Lazy<Gauge> =
+    Lazy::new(|| register(Gauge::new("wcp_wallet_balance_wei", "Worker wallet balance").expect("valid metric")));
+
+/// Register every metric up front.
+pub fn init() {
+    Lazy::force(&POLL_SECONDS);
+    Lazy::force(&POLLS);
+    Lazy::force(&CLAIM_JOBS_ENQUEUED);
+    Lazy::force(&CHAIN_EVENTS);
+    Lazy::force(&EVENT_CHECKPOINT_BLOCK);
+    Lazy::force(&CLAIMS_SCHEDULED);
+    Lazy::force(&PROFIT_SKIPS);
+    Lazy::force(&CLAIMS);
+    Lazy::force(&ASSIGNMENT_SECONDS);
+    Lazy::force(&ASSIGNMENTS);
+    Lazy::force(&PRESIGNS);
+    Lazy::force(&TXS);
+    Lazy::force(&TX_CONFIRM_SECONDS);
+    Lazy::force(&GAS_SPENT_WEI);
+    Lazy::force(&GAS_BUMPS);
+    Lazy::force(&REPAIRS);
+    Lazy::force(&RECONCILE_ERRORS);
+    Lazy::force(&KEYS);
+    Lazy::force(&WALLET_BALANCE_WEI);
+}
+
+/// Record a confirmed transaction submitted at `submitted_at_ms`; `fee_wei` is
+/// `gas_used * effective_gas_price` when a receipt is available.
+pub fn tx_confirmed(kind: &str, submitted_at_ms: i64, now_ms: i64, fee_wei: Option<u128>) {
+    TXS.with_label_values(&[kind, "confirmed"]).inc();
+    TX_CONFIRM_SECONDS.with_label_values(&[kind]).observe((now_ms - submitted_at_ms).max(0) as f64 / 1000.0);
+    if let Some(fee) = fee_wei {
+        GAS_SPENT_WEI.with_label_values(&[kind]).inc_by(fee as f64);
+    }
+}
+
+/// Prometheus text exposition of everything in [`REGISTRY`].
+pub fn gather() -> String {
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&REGISTRY.gather(), &mut buf).expect("text encoding never fails");
+    String::from_utf8(buf).expect("text encoding is utf-8")
+}
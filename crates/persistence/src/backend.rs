@@ -0,0 +1,215 @@
+//! Column-family level storage backends behind [`crate::KvStore`].
+//!
+//! `KvStore` owns key routing, record encoding and index maintenance; a
+//! backend only stores bytes per column family and applies writes atomically.
+//! [`RocksBackend`] is used in production, [`MemBackend`] keeps everything in
+//! ordered in-memory maps so components can be exercised without a DB on disk.
+use anyhow::{anyhow, Result};
+use rocksdb::{BlockBasedOptions, Cache, ColumnFamilyDescriptor, DBCompressionType, Options, WriteBatch, WriteOptions, DB, DEFAULT_COLUMN_FAMILY_NAME};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::RwLock;
+use subnet_wcp_config::{DbCompression, StorageConfig};
+
+use crate::{index, FAMILIES};
+
+/// One put (`value: Some`) or delete (`value: None`) in a column family.
+pub struct CfWrite {
+    pub cf: &'static str,
+    pub key: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+}
+
+pub trait StorageBackend: Send + Sync + 'static {
+    /// Value for `key` in `cf`; `None` if either is missing.
+    fn get_cf(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// All pairs in `cf` whose key starts with `prefix`, in key order (empty if `cf` is missing).
+    fn scan_cf(&self, cf: &str, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// [`scan_cf`](Self::scan_cf) over each of `cfs`, all read at one point in
+    /// time so a batch moving a key between families is seen whole. Per-CF
+    /// results are concatenated in `cfs` order.
+    fn scan_cfs(&self, cfs: &[&str], prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// How many keys [`scan_cfs`](Self::scan_cfs) would return, without copying any values.
+    fn count_cfs(&self, cfs: &[&str], prefix: &[u8]) -> Result<usize>;
+
+    /// Apply `writes` in order, atomically.
+    fn write_cf(&self, writes: Vec<CfWrite>) -> Result<()>;
+}
+
+pub struct RocksBackend {
+    pub(crate) db: DB,
+    /// fsync the WAL on every write (`storage.sync_writes`).
+    pub(crate) sync: bool,
+    /// Held shared by multi-family scans and exclusively by writes, so a scan
+    /// never sees half of a batch written by this process.
+    pub(crate) scan_guard: RwLock<()>,
+}
+
+impl RocksBackend {
+    pub(crate) fn new(db: DB, sync: bool) -> Self {
+        Self { db, sync, scan_guard: RwLock::new(()) }
+    }
+}
+
+/// DB and column family options from `[storage]`; every CF shares one block cache.
+pub(crate) fn tuned_options(cfg: &StorageConfig) -> Options {
+    let mut opts = Options::default();
+    opts.set_compression_type(match cfg.compression {
+        DbCompression::None => DBCompressionType::None,
+        DbCompression::Lz4 => DBCompressionType::Lz4,
+        DbCompression::Zstd => DBCompressionType::Zstd,
+    });
+    let mut table: BlockBasedOptions = Default::default();
+    table.set_block_cache(&Cache::new_lru_cache(cfg.block_cache_mb << 20));
+    opts.set_block_based_table_factory(&table);
+    opts.set_write_buffer_size(cfg.write_buffer_mb << 20);
+    opts.set_max_open_files(cfg.max_open_files);
+    if let Some(dir) = &cfg.wal_dir {
+        opts.set_wal_dir(dir);
+    }
+    if cfg.max_total_wal_mb > 0 {
+        opts.set_max_total_wal_size(cfg.max_total_wal_mb << 20);
+    }
+    opts
+}
+
+/// Descriptors for our families plus any CF already present at `path`
+/// (RocksDB requires every existing CF to be opened, including ones added by newer builds).
+pub(crate) fn descriptors(opts: &Options, path: &Path) -> Vec<ColumnFamilyDescriptor> {
+    let mut cfs: Vec<String> = FAMILIES.iter().chain([&index::CF]).map(|s| s.to_string()).collect();
+    for existing in DB::list_cf(opts, path).unwrap_or_default() {
+        if existing != DEFAULT_COLUMN_FAMILY_NAME && !cfs.contains(&existing) {
+            cfs.push(existing);
+        }
+    }
+    cfs.into_iter().map(|name| ColumnFamilyDescriptor::new(name, opts.clone())).collect()
+}
+
+/// Descriptors for the CFs that already exist at `path`; read-only and
+/// secondary instances cannot create missing ones.
+pub(crate) fn existing_descriptors(opts: &Options, path: &Path) -> Result<Vec<ColumnFamilyDescriptor>> {
+    Ok(DB::list_cf(opts, path)?
+        .into_iter()
+        .filter(|name| name != DEFAULT_COLUMN_FAMILY_NAME)
+        .map(|name| ColumnFamilyDescriptor::new(name, opts.clone()))
+        .collect())
+}
+
+impl StorageBackend for RocksBackend {
+    fn get_cf(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        // A reader opened on an older layout may lack the family's CF: no such keys yet.
+        match self.db.cf_handle(cf) {
+            Some(cf) => Ok(self.db.get_cf(cf, key)?),
+            None => Ok(None),
+        }
+    }
+
+    fn scan_cf(&self, cf: &str, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.scan_cfs(&[cf], prefix)
+    }
+
+    fn scan_cfs(&self, cfs: &[&str], prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut out = Vec::new();
+        let _guard = self.scan_guard.read().map_err(|_| anyhow!("scan guard poisoned"))?;
+        for cf in cfs {
+            let Some(cf) = self.db.cf_handle(cf) else { continue };
+            let mut it = self.db.raw_iterator_cf(cf);
+            it.seek(prefix);
+            while it.valid() {
+                if let (Some(k), Some(v)) = (it.key(), it.value()) {
+                    if !k.starts_with(prefix) { break; }
+                    out.push((k.to_vec(), v.to_vec()));
+                } else {
+                    break;
+                }
+                it.next();
+            }
+        }
+        Ok(out)
+    }
+
+    fn count_cfs(&self, cfs: &[&str], prefix: &[u8]) -> Result<usize> {
+        let mut n = 0;
+        let _guard = self.scan_guard.read().map_err(|_| anyhow!("scan guard poisoned"))?;
+        for cf in cfs {
+            let Some(cf) = self.db.cf_handle(cf) else { continue };
+            let mut it = self.db.raw_iterator_cf(cf);
+            it.seek(prefix);
+            while it.key().is_some_and(|k| k.starts_with(prefix)) {
+                n += 1;
+                it.next();
+            }
+        }
+        Ok(n)
+    }
+
+    fn write_cf(&self, writes: Vec<CfWrite>) -> Result<()> {
+        let mut wb = WriteBatch::default();
+        for w in writes {
+            let cf = self.db.cf_handle(w.cf).ok_or_else(|| anyhow!("column family {} not open", w.cf))?;
+            match w.value {
+                Some(v) => wb.put_cf(cf, w.key, v),
+                None => wb.delete_cf(cf, w.key),
+            }
+        }
+        let mut wo = WriteOptions::default();
+        wo.set_sync(self.sync);
+        let _guard = self.scan_guard.write().map_err(|_| anyhow!("scan guard poisoned"))?;
+        Ok(self.db.write_opt(wb, &wo)?)
+    }
+}
+
+type Table = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Ordered in-memory backend with the same prefix-scan semantics as RocksDB.
+#[derive(Default)]
+pub struct MemBackend {
+    cfs: RwLock<HashMap<&'static str, Table>>,
+}
+
+impl StorageBackend for MemBackend {
+    fn get_cf(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let cfs = self.cfs.read().map_err(|_| anyhow!("mem backend lock poisoned"))?;
+        Ok(cfs.get(cf).and_then(|t| t.get(key)).cloned())
+    }
+
+    fn scan_cf(&self, cf: &str, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.scan_cfs(&[cf], prefix)
+    }
+
+    fn scan_cfs(&self, cfs: &[&str], prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        // One read lock for every family, like RocksBackend's scan guard.
+        let tables = self.cfs.read().map_err(|_| anyhow!("mem backend lock poisoned"))?;
+        Ok(cfs
+            .iter()
+            .filter_map(|cf| tables.get(cf))
+            .flat_map(|table| table.range(prefix.to_vec()..).take_while(|(k, _)| k.starts_with(prefix)))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    fn count_cfs(&self, cfs: &[&str], prefix: &[u8]) -> Result<usize> {
+        let tables = self.cfs.read().map_err(|_| anyhow!("mem backend lock poisoned"))?;
+        Ok(cfs
+            .iter()
+            .filter_map(|cf| tables.get(cf))
+            .map(|table| table.range(prefix.to_vec()..).take_while(|(k, _)| k.starts_with(prefix)).count())
+            .sum())
+    }
+
+    fn write_cf(&self, writes: Vec<CfWrite>) -> Result<()> {
+        // One write lock for the whole batch keeps it atomic for readers.
+        let mut cfs = self.cfs.write().map_err(|_| anyhow!("mem backend lock poisoned"))?;
+        for w in writes {
+            let table = cfs.entry(w.cf).or_default();
+            match w.value {
+                Some(v) => { table.insert(w.key, v); }
+                None => { table.remove(&w.key); }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+//! Secondary indexes kept in the `index` column family.
+//!
+//! Index keys are derived from record values on every write (see
+//! [`crate::KvStore::write`]) and carry no value of their own:
+//! - `state:{family}:{state}:{activity_id}` — inflight assignment status, tx status, done outcome
+//! - `instance:{workflow_instance_id}:{family}:{activity_id}` — inflight and done records
+//! - `nonce:{nonce:020}:{activity_id}` — tx records with a nonce
+//! - `expiry:{expires_at_ms:020}:{activity_id}` — inflight records with a known expiry
+use serde::Serialize;
+
+use crate::records::{self, DoneRecord, InflightRecord, TxRecord};
+
+pub const CF: &str = "index";
+
+pub fn state(family: &str, state: &str, activity_id: &str) -> String {
+    format!("state:{}:{}:{}", family, state, activity_id)
+}
+pub fn instance(instance_id: &str, family: &str, activity_id: &str) -> String {
+    format!("instance:{}:{}:{}", instance_id, family, activity_id)
+}
+pub fn nonce(nonce: u64, activity_id: &str) -> String {
+    format!("nonce:{:020}:{}", nonce, activity_id)
+}
+pub fn expiry(expires_at_ms: i64, activity_id: &str) -> String {
+    format!("expiry:{:020}:{}", expires_at_ms.max(0), activity_id)
+}
+
+/// Lowercase serde name of a unit enum variant (`Running` → `running`).
+fn label<T: Serialize>(v: &T) -> String {
+    serde_json::to_value(v)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_lowercase))
+        .unwrap_or_default()
+}
+
+/// Index keys for the record stored at `key`; empty for unindexed families or
+/// values that do not decode.
+pub(crate) fn entries(key: &str, value: &[u8]) -> Vec<String> {
+    let mut out = Vec::new();
+    if let Some(id) = key.strip_prefix("inflight:") {
+        if let Ok(rec) = records::decode::<InflightRecord>(value) {
+            out.push(state("inflight", &label(&rec.assignment_status), id));
+            if let Some(inst) = &rec.instance_id {
+                out.push(instance(inst, "inflight", id));
+            }
+            if let Some(ts) = rec.expires_at_ms {
+                out.push(expiry(ts, id));
+            }
+        }
+    } else if let Some(id) = key.strip_prefix("tx:") {
+        if let Ok(rec) = records::decode::<TxRecord>(value) {
+            out.push(state("tx", &label(&rec.status), id));
+            if let Some(n) = rec.nonce {
+                out.push(nonce(n, id));
+            }
+        }
+    } else if let Some(id) = key.strip_prefix("done:") {
+        if let Ok(rec) = records::decode::<DoneRecord>(value) {
+            out.push(state("done", &label(&rec.outcome), id));
+            if let Some(inst) = &rec.instance_id {
+                out.push(instance(inst, "done", id));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keys;
+    use crate::records::{AssignmentStatus, DoneOutcome, DoneRecord, InflightRecord, TxKind, TxRecord, TxStatus};
+    use crate::{Batch, KvStore};
+
+    fn inflight(id: &str, status: AssignmentStatus, instance: Option<&str>, expires_at_ms: Option<i64>) -> InflightRecord {
+        InflightRecord {
+            activity_id: id.into(),
+            queue: "video".into(),
+            claimed_at_ms: 0,
+            assignment_status: status,
+            instance_id: instance.map(str::to_string),
+            expires_at_ms,
+            inputs: Vec::new(),
+            upload_prefix: None,
+        }
+    }
+
+    fn tx(id: &str, status: TxStatus, nonce: Option<u64>) -> TxRecord {
+        TxRecord { activity_id: id.into(), kind: TxKind::Claim, status, tx_hash: None, submitted_at_ms: 0, last_bump_at_ms: None, nonce }
+    }
+
+    #[test]
+    fn entries_follow_inflight_state_changes() {
+        let store = KvStore::in_memory();
+        store.put_record(keys::inflight("0x01"), &inflight("0x01", AssignmentStatus::Pending, None, Some(5_000))).unwrap();
+        store.put_record(keys::inflight("0x02"), &inflight("0x02", AssignmentStatus::Pending, None, Some(9_000))).unwrap();
+        assert_eq!(store.ids_by_state("inflight", "pending").unwrap(), ["0x01", "0x02"]);
+        assert_eq!(store.expiring_before(6_000).unwrap(), [(5_000, "0x01".to_string())]);
+
+        // Running with an instance: the pending entry goes, the new ones appear.
+        store.put_record(keys::inflight("0x01"), &inflight("0x01", AssignmentStatus::Running, Some("0xaa"), Some(7_000))).unwrap();
+        assert_eq!(store.ids_by_state("inflight", "pending").unwrap(), ["0x02"]);
+        assert_eq!(store.ids_by_state("inflight", "running").unwrap(), ["0x01"]);
+        assert_eq!(store.ids_by_instance("0xaa").unwrap(), ["0x01"]);
+        assert!(store.expiring_before(6_000).unwrap().is_empty());
+        assert_eq!(store.expiring_before(10_000).unwrap(), [(7_000, "0x01".to_string()), (9_000, "0x02".to_string())]);
+
+        store.delete(keys::inflight("0x01")).unwrap();
+        assert!(store.ids_by_state("inflight", "running").unwrap().is_empty());
+        assert!(store.ids_by_instance("0xaa").unwrap().is_empty());
+        assert_eq!(store.expiring_before(10_000).unwrap(), [(9_000, "0x02".to_string())]);
+    }
+
+    #[test]
+    fn entries_follow_tx_and_done_records() {
+        let store = KvStore::in_memory();
+        store.put_record(keys::tx("0x01"), &tx("0x01", TxStatus::Submitted, Some(7))).unwrap();
+        assert_eq!(store.ids_by_state("tx", "submitted").unwrap(), ["0x01"]);
+        assert_eq!(store.ids_by_nonce(7).unwrap(), ["0x01"]);
+
+        // Replaced at a new nonce.
+        store.put_record(keys::tx("0x01"), &tx("0x01", TxStatus::Confirmed, Some(8))).unwrap();
+        assert!(store.ids_by_state("tx", "submitted").unwrap().is_empty());
+        assert_eq!(store.ids_by_state("tx", "confirmed").unwrap(), ["0x01"]);
+        assert!(store.ids_by_nonce(7).unwrap().is_empty());
+        assert_eq!(store.ids_by_nonce(8).unwrap(), ["0x01"]);
+
+        // Retiring inflight into done in one batch moves the instance entry between families.
+        store.put_record(keys::inflight("0x02"), &inflight("0x02", AssignmentStatus::Running, Some("0xaa"), None)).unwrap();
+        let done = DoneRecord {
+            activity_id: "0x02".into(),
+            outcome: DoneOutcome::Ok,
+            result_ref: None,
+            error: None,
+            finished_at_ms: 0,
+            instance_id: Some("0xaa".into()),
+            queue: None,
+        };
+        let mut batch = Batch::new();
+        batch.put_record(keys::done("0x02"), &done).unwrap().delete(keys::inflight("0x02"));
+        store.write(batch).unwrap();
+        assert_eq!(store.ids_by_instance("0xaa").unwrap(), ["0x02"]);
+        assert!(store.ids_by_state("inflight", "running").unwrap().is_empty());
+        assert_eq!(store.ids_by_state("done", "ok").unwrap(), ["0x02"]);
+        assert_eq!(store.scan_cf(super::CF, "instance:0xaa:").unwrap().len(), 1);
+
+        store.delete(keys::done("0x02")).unwrap();
+        store.delete(keys::tx("0x01")).unwrap();
+        assert!(store.scan_cf(super::CF, "").unwrap().is_empty());
+    }
+
+    #[test]
+    fn undecodable_values_are_not_indexed() {
+        let store = KvStore::in_memory();
+        store.put(keys::inflight("0x01"), b"garbage").unwrap();
+        assert!(store.scan_cf(super::CF, "").unwrap().is_empty());
+    }
+}
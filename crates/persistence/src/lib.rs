@@ -1,43 +1,375 @@
-use anyhow::Result;
-use rocksdb::{Options, DB};
-use std::sync::Arc;
+use anyhow::{anyhow, bail, Result};
+use rocksdb::{DB, DEFAULT_COLUMN_FAMILY_NAME};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-#[derive(Clone)]
-pub struct KvStore { db: Arc<DB> }
+pub mod backend;
+pub mod index;
+pub mod lock;
+pub mod migrations;
+pub mod records;
+pub mod view;
+
+use backend::{CfWrite, MemBackend, RocksBackend, StorageBackend};
+use records::Record;
+use subnet_wcp_config::StorageConfig;
+
+/// Key families, each stored in its own column family named after the key
+/// prefix (`inflight:{id}` lives in CF `inflight`). Keys keep their prefix so
+/// callers address them exactly as before; unknown prefixes go to `default`.
+pub const FAMILIES: &[&str] = &["claim_job", "inflight", "tx", "done", "broadcast", "nonce", "meta"];
+
+/// Key/value store used by every component: routes keys to their family's
+/// column family, encodes typed records and keeps secondary indexes current.
+/// Generic over the [`StorageBackend`]; RocksDB unless stated otherwise.
+pub struct KvStore<B: StorageBackend = RocksBackend> {
+    backend: Arc<B>,
+    /// Serializes writers so index maintenance (read old value, swap entries) is consistent.
+    write_lock: Arc<Mutex<()>>,
+    mode: Mode,
+}
+
+impl<B: StorageBackend> Clone for KvStore<B> {
+    fn clone(&self) -> Self {
+        Self { backend: self.backend.clone(), write_lock: self.write_lock.clone(), mode: self.mode }
+    }
+}
+
+/// How the underlying RocksDB instance was opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Owns the DB lock; the WCP itself.
+    Primary,
+    /// Lock-free snapshot as of open time.
+    ReadOnly,
+    /// Lock-free follower that can catch up with the primary.
+    Secondary,
+}
+
+enum Op {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    /// Legacy (pre column family) layout; used by migrations only.
+    PutDefault(Vec<u8>, Vec<u8>),
+    DeleteDefault(Vec<u8>),
+}
+
+/// A set of puts/deletes applied atomically by [`KvStore::write`].
+///
+/// Use this for every state transition that touches more than one key so a
+/// crash can never leave e.g. `done:{id}` written while `inflight:{id}` remains.
+#[derive(Default)]
+pub struct Batch { ops: Vec<Op> }
+
+impl Batch {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn put(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> &mut Self {
+        self.ops.push(Op::Put(key.as_ref().to_vec(), value.as_ref().to_vec()));
+        self
+    }
+
+    pub fn delete(&mut self, key: impl AsRef<[u8]>) -> &mut Self {
+        self.ops.push(Op::Delete(key.as_ref().to_vec()));
+        self
+    }
+
+    pub fn put_record<R: Record>(&mut self, key: impl AsRef<[u8]>, rec: &R) -> Result<&mut Self> {
+        Ok(self.put(key, records::encode(rec)?))
+    }
+
+    pub(crate) fn put_default_cf(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> &mut Self {
+        self.ops.push(Op::PutDefault(key.as_ref().to_vec(), value.as_ref().to_vec()));
+        self
+    }
+
+    pub(crate) fn delete_default_cf(&mut self, key: impl AsRef<[u8]>) -> &mut Self {
+        self.ops.push(Op::DeleteDefault(key.as_ref().to_vec()));
+        self
+    }
+
+    pub fn len(&self) -> usize { self.ops.len() }
+
+    pub fn is_empty(&self) -> bool { self.ops.is_empty() }
+}
+
+/// Column family for `key`, from the text before the first `:`.
+fn family_of(key: &[u8]) -> &'static str {
+    let prefix = key.split(|b| *b == b':').next().unwrap_or_default();
+    FAMILIES
+        .iter()
+        .find(|f| f.as_bytes() == prefix && key.len() > prefix.len())
+        .copied()
+        .unwrap_or(DEFAULT_COLUMN_FAMILY_NAME)
+}
 
 impl KvStore {
-    pub fn open(path: &str) -> Result<Self> {
-        let mut opts = Options::default();
+    /// Open (creating if missing) with the `[storage]` tuning.
+    pub fn open(path: impl AsRef<Path>, cfg: &StorageConfig) -> Result<Self> {
+        let path = path.as_ref();
+        let cf_opts = backend::tuned_options(cfg);
+        let mut opts = cf_opts.clone();
         opts.create_if_missing(true);
-        let db = DB::open(&opts, path)?;
-        Ok(Self { db: Arc::new(db) })
+        opts.create_missing_column_families(true);
+        let db = DB::open_cf_descriptors(&opts, path, backend::descriptors(&cf_opts, path))?;
+        Ok(Self::with_backend(RocksBackend::new(db, cfg.sync_writes), Mode::Primary))
+    }
+
+    /// Open without taking the DB lock, so inspection tools can run next to a
+    /// live WCP. Sees the data as of open time; writes are rejected. `cfg`
+    /// must match the WCP's (notably `wal_dir`) to see unflushed writes.
+    pub fn open_read_only(path: impl AsRef<Path>, cfg: &StorageConfig) -> Result<Self> {
+        let path = path.as_ref();
+        let opts = backend::tuned_options(cfg);
+        let db = DB::open_cf_descriptors_read_only(&opts, path, backend::existing_descriptors(&opts, path)?, false)?;
+        Ok(Self::with_backend(RocksBackend::new(db, false), Mode::ReadOnly))
+    }
+
+    /// Open as a RocksDB secondary instance that follows a live primary via
+    /// [`KvStore::catch_up`]; for long-running readers. `secondary_path` holds
+    /// this instance's own info logs and must be unique per reader.
+    pub fn open_secondary(path: impl AsRef<Path>, secondary_path: impl AsRef<Path>, cfg: &StorageConfig) -> Result<Self> {
+        let path = path.as_ref();
+        let mut opts = backend::tuned_options(cfg);
+        // secondaries must keep every SST open so catch-up does not race compaction deletes
+        opts.set_max_open_files(-1);
+        let cfs = backend::existing_descriptors(&opts, path)?;
+        let db = DB::open_cf_descriptors_as_secondary(&opts, path, secondary_path.as_ref(), cfs)?;
+        Ok(Self::with_backend(RocksBackend::new(db, false), Mode::Secondary))
     }
 
+    /// Compact the default and every family/index CF, e.g. after a large
+    /// cleanup. Needs the primary, so the WCP must be stopped.
+    pub fn compact(&self) -> Result<()> {
+        if self.mode != Mode::Primary {
+            bail!("compaction needs the DB opened as primary");
+        }
+        let db = &self.backend.db;
+        db.compact_range(None::<&[u8]>, None::<&[u8]>);
+        for name in FAMILIES.iter().chain([&index::CF]) {
+            if let Some(cf) = db.cf_handle(name) {
+                db.compact_range_cf(cf, None::<&[u8]>, None::<&[u8]>);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replay writes the primary made since open (secondary instances only;
+    /// a no-op otherwise).
+    pub fn catch_up(&self) -> Result<()> {
+        if self.mode == Mode::Secondary {
+            self.backend.db.try_catch_up_with_primary()?;
+        }
+        Ok(())
+    }
+}
+
+impl KvStore<MemBackend> {
+    /// Empty store backed by ordered in-memory maps; for tests.
+    pub fn in_memory() -> Self {
+        Self::with_backend(MemBackend::default(), Mode::Primary)
+    }
+}
+
+impl<B: StorageBackend> KvStore<B> {
+    pub fn with_backend(backend: B, mode: Mode) -> Self {
+        Self { backend: Arc::new(backend), write_lock: Arc::new(Mutex::new(())), mode }
+    }
+
+    pub fn mode(&self) -> Mode { self.mode }
+
     pub fn put(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
-        Ok(self.db.put(key, value)?)
+        let mut batch = Batch::new();
+        batch.put(key, value);
+        self.write(batch)
     }
 
     pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
-        Ok(self.db.get(key)?)
+        let key = key.as_ref();
+        self.backend.get_cf(family_of(key), key)
     }
 
     pub fn delete(&self, key: impl AsRef<[u8]>) -> Result<()> {
-        Ok(self.db.delete(key)?)
+        let mut batch = Batch::new();
+        batch.delete(key);
+        self.write(batch)
+    }
+
+    pub fn put_record<R: Record>(&self, key: impl AsRef<[u8]>, rec: &R) -> Result<()> {
+        self.put(key, records::encode(rec)?)
+    }
+
+    pub fn get_record<R: Record>(&self, key: impl AsRef<[u8]>) -> Result<Option<R>> {
+        self.get(key)?.map(|v| records::decode(&v)).transpose()
+    }
+
+    /// Decode every record under `prefix`, returning `(key, record)` pairs.
+    pub fn scan_records<R: Record>(&self, prefix: &str) -> Result<Vec<(String, R)>> {
+        self.scan_prefix(prefix)?
+            .into_iter()
+            .map(|(k, v)| Ok((String::from_utf8_lossy(&k).into_owned(), records::decode(&v)?)))
+            .collect()
+    }
+
+    /// Apply all operations in `batch` atomically (all or nothing), updating
+    /// secondary indexes for the touched records in the same write.
+    pub fn write(&self, batch: Batch) -> Result<()> {
+        if self.mode != Mode::Primary {
+            bail!("kv store is open {:?}; writes require the primary instance", self.mode);
+        }
+        let _guard = self.write_lock.lock().map_err(|_| anyhow!("kv write lock poisoned"))?;
+        self.write_locked(batch)
+    }
+
+    /// The body of [`write`](Self::write); the caller holds `write_lock`.
+    fn write_locked(&self, batch: Batch) -> Result<()> {
+        let mut writes = Vec::with_capacity(batch.ops.len());
+        // Values as they will be after the ops seen so far, for keys touched twice in one batch.
+        let mut pending: HashMap<Vec<u8>, Option<Vec<u8>>> = HashMap::new();
+        for op in batch.ops {
+            let (cf, key, value) = match op {
+                Op::Put(key, value) => {
+                    self.reindex(&mut writes, &mut pending, &key, Some(&value))?;
+                    (family_of(&key), key, Some(value))
+                }
+                Op::Delete(key) => {
+                    self.reindex(&mut writes, &mut pending, &key, None)?;
+                    (family_of(&key), key, None)
+                }
+                Op::PutDefault(key, value) => (DEFAULT_COLUMN_FAMILY_NAME, key, Some(value)),
+                Op::DeleteDefault(key) => (DEFAULT_COLUMN_FAMILY_NAME, key, None),
+            };
+            writes.push(CfWrite { cf, key, value });
+        }
+        self.backend.write_cf(writes)
+    }
+
+    /// [`write`](Self::write) `batch` only if `key` does not exist, checked
+    /// under the same lock; `false` (nothing written) if it does. Lets two
+    /// writers race to retire an activity without one overwriting the other.
+    pub fn write_if_absent(&self, key: impl AsRef<[u8]>, batch: Batch) -> Result<bool> {
+        self.write_if(key, false, batch)
+    }
+
+    /// [`write`](Self::write) `batch` only if `key` exists; the counterpart of
+    /// [`write_if_absent`](Self::write_if_absent).
+    pub fn write_if_present(&self, key: impl AsRef<[u8]>, batch: Batch) -> Result<bool> {
+        self.write_if(key, true, batch)
     }
 
+    fn write_if(&self, key: impl AsRef<[u8]>, present: bool, batch: Batch) -> Result<bool> {
+        if self.mode != Mode::Primary {
+            bail!("kv store is open {:?}; writes require the primary instance", self.mode);
+        }
+        let _guard = self.write_lock.lock().map_err(|_| anyhow!("kv write lock poisoned"))?;
+        if self.get(key)?.is_some() != present {
+            return Ok(false);
+        }
+        self.write_locked(batch)?;
+        Ok(true)
+    }
+
+    fn reindex(
+        &self,
+        writes: &mut Vec<CfWrite>,
+        pending: &mut HashMap<Vec<u8>, Option<Vec<u8>>>,
+        key: &[u8],
+        new: Option<&[u8]>,
+    ) -> Result<()> {
+        let key_str = String::from_utf8_lossy(key);
+        let old = match pending.get(key) {
+            Some(v) => v.clone(),
+            None => self.get(key)?,
+        };
+        let old_entries = old.as_deref().map(|v| index::entries(&key_str, v)).unwrap_or_default();
+        let new_entries = new.map(|v| index::entries(&key_str, v)).unwrap_or_default();
+        for e in old_entries.iter().filter(|e| !new_entries.contains(e)) {
+            writes.push(CfWrite { cf: index::CF, key: e.clone().into_bytes(), value: None });
+        }
+        for e in new_entries.iter().filter(|e| !old_entries.contains(e)) {
+            writes.push(CfWrite { cf: index::CF, key: e.clone().into_bytes(), value: Some(Vec::new()) });
+        }
+        pending.insert(key.to_vec(), new.map(<[u8]>::to_vec));
+        Ok(())
+    }
+
+    /// All `(key, value)` pairs whose key starts with `prefix`, in key order.
+    ///
+    /// A prefix naming a family (`inflight:`) reads only that column family;
+    /// anything shorter (including `""`) merges every data family, read at
+    /// one point in time.
     pub fn scan_prefix(&self, prefix: &str) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let family = family_of(prefix.as_bytes());
+        if family != DEFAULT_COLUMN_FAMILY_NAME {
+            return self.scan_cf(family, prefix);
+        }
+        let cfs: Vec<&str> = [DEFAULT_COLUMN_FAMILY_NAME].into_iter().chain(FAMILIES.iter().copied()).collect();
+        let mut out = self.backend.scan_cfs(&cfs, prefix.as_bytes())?;
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(out)
+    }
+
+    /// Number of keys [`scan_prefix`](Self::scan_prefix) would return; reads
+    /// keys only, for gauges over large families.
+    pub fn count_prefix(&self, prefix: &str) -> Result<usize> {
+        let family = family_of(prefix.as_bytes());
+        if family != DEFAULT_COLUMN_FAMILY_NAME {
+            return self.backend.count_cfs(&[family], prefix.as_bytes());
+        }
+        let cfs: Vec<&str> = [DEFAULT_COLUMN_FAMILY_NAME].into_iter().chain(FAMILIES.iter().copied()).collect();
+        self.backend.count_cfs(&cfs, prefix.as_bytes())
+    }
+
+    pub(crate) fn scan_cf(&self, cf: &str, prefix: &str) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.backend.scan_cf(cf, prefix.as_bytes())
+    }
+
+    pub(crate) fn get_default_cf(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        self.backend.get_cf(DEFAULT_COLUMN_FAMILY_NAME, key.as_ref())
+    }
+
+    /// Index key suffixes (the part after `prefix`) for index entries under `prefix`.
+    fn index_suffixes(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(self
+            .scan_cf(index::CF, prefix)?
+            .into_iter()
+            .map(|(k, _)| String::from_utf8_lossy(&k[prefix.len()..]).into_owned())
+            .collect())
+    }
+
+    /// Activity ids in `family` (`inflight`, `tx`, `done`) whose status is `state`
+    /// (lowercase, e.g. `running`, `submitted`, `timeout`).
+    pub fn ids_by_state(&self, family: &str, state: &str) -> Result<Vec<String>> {
+        self.index_suffixes(&format!("state:{}:{}:", family, state))
+    }
+
+    /// Activity ids (inflight or done) belonging to a workflow instance.
+    pub fn ids_by_instance(&self, instance_id: &str) -> Result<Vec<String>> {
+        let mut ids: Vec<String> = self
+            .index_suffixes(&format!("instance:{}:", instance_id))?
+            .into_iter()
+            .filter_map(|s| s.split_once(':').map(|(_family, id)| id.to_string()))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Activity ids whose `tx:*` record used `nonce`.
+    pub fn ids_by_nonce(&self, nonce: u64) -> Result<Vec<String>> {
+        self.index_suffixes(&format!("nonce:{:020}:", nonce))
+    }
+
+    /// `(expires_at_ms, activity_id)` for inflight records expiring strictly before `before_ms`,
+    /// earliest first.
+    pub fn expiring_before(&self, before_ms: i64) -> Result<Vec<(i64, String)>> {
         let mut out = Vec::new();
-        let mut it = self.db.raw_iterator();
-        let pref = prefix.as_bytes();
-        it.seek(pref);
-        while it.valid() {
-            if let (Some(k), Some(v)) = (it.key(), it.value()) {
-                if !k.starts_with(pref) { break; }
-                out.push((k.to_vec(), v.to_vec()));
-            } else {
-                break;
-            }
-            it.next();
+        for entry in self.index_suffixes("expiry:")? {
+            let Some((ts, id)) = entry.split_once(':') else { continue };
+            let Ok(ts) = ts.parse::<i64>() else { continue };
+            if ts >= before_ms { break; }
+            out.push((ts, id.to_string()));
         }
         Ok(out)
     }
@@ -49,9 +381,174 @@ pub mod keys {
     pub fn done(activity_id: &str) -> String { format!("done:{}", activity_id) }
     pub fn claim_job(activity_id: &str) -> String { format!("claim_job:{}", activity_id) }
     pub const NONCE_LAST: &str = "nonce:last";
+    pub const SCHEMA_VERSION: &str = "meta:schema_version";
+    pub const LAST_SCAN_BLOCK: &str = "meta:last_scan_block";
+    /// Rewritten by `/readyz` to prove the DB accepts writes.
+    pub const HEALTH_PROBE: &str = "meta:health_probe";
+    /// Present while an operator has paused polling for the queue.
+    pub fn paused_queue(queue: &str) -> String { format!("meta:paused_queue:{}", queue) }
     pub fn broadcast_claim(activity_id: &str) -> String { format!("broadcast:claim:{}", activity_id) }
     pub fn broadcast_complete(activity_id: &str) -> String { format!("broadcast:complete:{}", activity_id) }
     pub fn broadcast_resume(instance_id: &str) -> String { format!("broadcast:resume:{}", instance_id) }
+    /// Legacy value a schema migration could not read, kept byte-for-byte under
+    /// its original key (default column family).
+    pub fn quarantine(key: &str) -> String { format!("quarantine:{}", key) }
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use records::{AssignmentStatus, DoneOutcome, DoneRecord, InflightRecord};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn inflight(id: &str, status: AssignmentStatus) -> InflightRecord {
+        InflightRecord {
+            activity_id: id.into(),
+            queue: "video".into(),
+            claimed_at_ms: 0,
+            assignment_status: status,
+            instance_id: None,
+            expires_at_ms: None,
+            inputs: Vec::new(),
+            upload_prefix: None,
+        }
+    }
+
+    fn done(id: &str) -> DoneRecord {
+        DoneRecord { activity_id: id.into(), outcome: DoneOutcome::Ok, result_ref: None, error: None, finished_at_ms: 0, instance_id: None, queue: None }
+    }
+
+    fn rocks() -> (tempfile::TempDir, KvStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KvStore::open(dir.path().join("wcp.db"), &StorageConfig::default()).unwrap();
+        (dir, store)
+    }
+
+    /// Flip `0x01` between inflight and done in batches while a reader checks
+    /// it never sees both or neither.
+    fn assert_batches_atomic<B: StorageBackend>(store: KvStore<B>) {
+        store.put_record(keys::inflight("0x01"), &inflight("0x01", AssignmentStatus::Running)).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let (store, stop) = (store.clone(), stop.clone());
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let families: Vec<_> = store.scan_prefix("").unwrap().into_iter().map(|(k, _)| k).collect();
+                    assert_eq!(families.len(), 1, "saw a half-applied batch: {:?}", families);
+                }
+            })
+        };
+        for i in 0..2000 {
+            let mut batch = Batch::new();
+            if i % 2 == 0 {
+                batch.put_record(keys::done("0x01"), &done("0x01")).unwrap().delete(keys::inflight("0x01"));
+            } else {
+                batch.put_record(keys::inflight("0x01"), &inflight("0x01", AssignmentStatus::Running)).unwrap().delete(keys::done("0x01"));
+            }
+            store.write(batch).unwrap();
+        }
+        stop.store(true, Ordering::Relaxed);
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn batches_are_atomic_for_readers() {
+        assert_batches_atomic(KvStore::in_memory());
+        let (_dir, store) = rocks();
+        assert_batches_atomic(store);
+    }
+
+    #[test]
+    fn writes_are_rejected_off_primary() {
+        let store = KvStore::with_backend(MemBackend::default(), Mode::ReadOnly);
+        let mut batch = Batch::new();
+        batch.put("inflight:0x01", b"x").put("done:0x01", b"y");
+        assert!(store.write(batch).is_err());
+        assert!(store.scan_prefix("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn conditional_writes_check_the_guard_key() {
+        let store = KvStore::in_memory();
+        let batch = |key: &str| {
+            let mut b = Batch::new();
+            b.put(key, b"x");
+            b
+        };
+        assert!(!store.write_if_present("done:0x01", batch("inflight:0x01")).unwrap());
+        assert!(store.write_if_absent("done:0x01", batch("done:0x01")).unwrap());
+        assert!(!store.write_if_absent("done:0x01", batch("inflight:0x01")).unwrap());
+        assert!(store.get("inflight:0x01").unwrap().is_none());
+        assert!(store.write_if_present("done:0x01", batch("inflight:0x01")).unwrap());
+        assert!(store.get("inflight:0x01").unwrap().is_some());
+    }
+
+    #[test]
+    fn index_follows_a_key_touched_twice_in_one_batch() {
+        let store = KvStore::in_memory();
+        let mut batch = Batch::new();
+        batch
+            .put_record(keys::inflight("0x01"), &inflight("0x01", AssignmentStatus::Pending))
+            .unwrap()
+            .put_record(keys::inflight("0x01"), &inflight("0x01", AssignmentStatus::Running))
+            .unwrap()
+            .put_record(keys::inflight("0x02"), &inflight("0x02", AssignmentStatus::Pending))
+            .unwrap()
+            .delete(keys::inflight("0x02"));
+        store.write(batch).unwrap();
+        assert!(store.ids_by_state("inflight", "pending").unwrap().is_empty());
+        assert_eq!(store.ids_by_state("inflight", "running").unwrap(), ["0x01"]);
+        assert_eq!(store.scan_cf(index::CF, "").unwrap().len(), 1);
+    }
+
+    /// Keys in several families plus the default CF, with prefix-sharing ids.
+    fn seed<B: StorageBackend>(store: &KvStore<B>) {
+        let mut batch = Batch::new();
+        for key in ["tx:0x01", "tx:0x010", "tx:0x02", "txt:note", "done:0x01", "inflight:0x01", "meta:schema_version", "zzz", "t"] {
+            batch.put(key, key.as_bytes());
+        }
+        store.write(batch).unwrap();
+    }
+
+    fn keys_of(pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<String> {
+        pairs.into_iter().map(|(k, _)| String::from_utf8(k).unwrap()).collect()
+    }
+
+    #[test]
+    fn short_prefixes_merge_every_family_in_key_order() {
+        let store = KvStore::in_memory();
+        seed(&store);
+        assert_eq!(
+            keys_of(store.scan_prefix("").unwrap()),
+            ["done:0x01", "inflight:0x01", "meta:schema_version", "t", "tx:0x01", "tx:0x010", "tx:0x02", "txt:note", "zzz"]
+        );
+        assert_eq!(keys_of(store.scan_prefix("t").unwrap()), ["t", "tx:0x01", "tx:0x010", "tx:0x02", "txt:note"]);
+        assert_eq!(keys_of(store.scan_prefix("tx").unwrap()), ["tx:0x01", "tx:0x010", "tx:0x02", "txt:note"]);
+        // A family prefix reads only that family.
+        assert_eq!(keys_of(store.scan_prefix("tx:").unwrap()), ["tx:0x01", "tx:0x010", "tx:0x02"]);
+        assert_eq!(keys_of(store.scan_prefix("tx:0x01").unwrap()), ["tx:0x01", "tx:0x010"]);
+        assert_eq!(family_of(b"txt:note"), DEFAULT_COLUMN_FAMILY_NAME);
+    }
+
+    #[test]
+    fn mem_backend_scans_like_rocksdb() {
+        let mem = KvStore::in_memory();
+        let (_dir, rocks) = rocks();
+        seed(&mem);
+        seed(&rocks);
+        for prefix in ["", "t", "tx", "tx:", "tx:0x01", "tx:0x03", "txt", "m", "zzz", "zzzz", "~"] {
+            assert_eq!(keys_of(mem.scan_prefix(prefix).unwrap()), keys_of(rocks.scan_prefix(prefix).unwrap()), "scan_prefix({prefix:?})");
+            let n = mem.scan_prefix(prefix).unwrap().len();
+            assert_eq!((mem.count_prefix(prefix).unwrap(), rocks.count_prefix(prefix).unwrap()), (n, n), "count_prefix({prefix:?})");
+        }
+        for cf in FAMILIES.iter().chain([&DEFAULT_COLUMN_FAMILY_NAME, &index::CF]) {
+            for prefix in ["", "t", "tx:0", "none"] {
+                assert_eq!(mem.scan_cf(cf, prefix).unwrap(), rocks.scan_cf(cf, prefix).unwrap(), "scan_cf({cf}, {prefix:?})");
+            }
+        }
+        // A family the backend has never seen reads as empty on both.
+        assert!(mem.scan_cf("absent", "").unwrap().is_empty());
+        assert!(rocks.scan_cf("absent", "").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+//! `wcp.lock`: the wallet that owns a data directory.
+//!
+//! RocksDB's own `LOCK` only stops two processes opening the DB at once; this
+//! file also stops a WCP with a different wallet from taking over (and mixing
+//! its activities into) another worker's DB after the first one stops. It is
+//! written on first start, atomically, and left in place.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataDirLock {
+    pub wallet_address: String,
+    pub created_at_ms: i64,
+}
+
+/// Claim `path` for `wallet` on first use; afterwards fail unless it names `wallet`.
+///
+/// The file is written whole to a temporary name and linked into place, so a
+/// crash mid-write leaves no lock rather than a partial one. An empty or
+/// unparseable lock (from a version that wrote it in place) counts as unclaimed.
+pub fn acquire(path: &Path, wallet: &str) -> Result<DataDirLock> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let lock = new_lock(wallet);
+            if write(path, &lock, false)? {
+                return Ok(lock);
+            }
+            // Another process claimed it first; its lock is complete by now.
+            std::fs::read(path).with_context(|| format!("reading {}", path.display()))?
+        }
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    let lock = match serde_json::from_slice::<DataDirLock>(&bytes) {
+        Ok(lock) => lock,
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "data dir lock is empty or unreadable (interrupted first start?); claiming it");
+            let lock = new_lock(wallet);
+            write(path, &lock, true)?;
+            return Ok(lock);
+        }
+    };
+    if !lock.wallet_address.eq_ignore_ascii_case(wallet) {
+        bail!(
+            "{} belongs to wallet {}, not {}; use a separate storage.data_dir per worker (delete the lock only to hand the DB to another wallet)",
+            path.display(),
+            lock.wallet_address,
+            wallet
+        );
+    }
+    Ok(lock)
+}
+
+pub fn read(path: &Path) -> Result<DataDirLock> {
+    let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    serde_json::from_slice(&bytes).with_context(|| format!("parsing {}", path.display()))
+}
+
+fn new_lock(wallet: &str) -> DataDirLock {
+    let created_at_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or_default();
+    DataDirLock { wallet_address: wallet.to_string(), created_at_ms }
+}
+
+/// Write `lock` to a synced temporary file next to `path`, then move it into
+/// place: replacing whatever is there, or only if `path` does not exist
+/// (`false` if it does).
+fn write(path: &Path, lock: &DataDirLock, replace: bool) -> Result<bool> {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    let placed = (|| -> Result<bool> {
+        let mut file = File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(lock)?)?;
+        file.sync_all()?;
+        if replace {
+            std::fs::rename(&tmp, path)?;
+            return Ok(true);
+        }
+        match std::fs::hard_link(&tmp, path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    })();
+    let _ = std::fs::remove_file(&tmp);
+    placed.with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "0x8b997E52A44DE1E00cB9aD1ef376BFc01cCc1057";
+    const B: &str = "0x904410cF5B972E5F4a7771c5f6198F72091914F2";
+
+    #[test]
+    fn first_wallet_owns_the_data_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wcp.lock");
+        let lock = acquire(&path, A).unwrap();
+        assert_eq!(read(&path).unwrap(), lock);
+        assert_eq!(acquire(&path, &A.to_lowercase()).unwrap(), lock);
+        assert!(acquire(&path, B).unwrap_err().to_string().contains("belongs to wallet"));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1, "no temporary files left behind");
+    }
+
+    #[test]
+    fn truncated_lock_is_claimed_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wcp.lock");
+        for partial in ["", "{\"wallet_address\": \"0x8b99"] {
+            std::fs::write(&path, partial).unwrap();
+            assert_eq!(acquire(&path, B).unwrap().wallet_address, B);
+            assert_eq!(read(&path).unwrap().wallet_address, B);
+            assert!(acquire(&path, A).is_err());
+        }
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,382 @@
+//! Schema migrations for `wcp.db`, applied in order at startup.
+//!
+//! The current schema version lives under [`keys::SCHEMA_VERSION`] (absent on
+//! databases written before versioning, which count as v0). Each step rewrites
+//! its records and bumps the version in a single [`Batch`], so an interrupted
+//! upgrade is simply re-run on the next start.
+//!
+//! A legacy value a step cannot read is moved to [`keys::quarantine`] (in the
+//! default column family, raw bytes unchanged) and logged, rather than failing
+//! the upgrade and keeping the WCP from starting.
+use anyhow::{anyhow, bail, Context, Result};
+use rocksdb::DEFAULT_COLUMN_FAMILY_NAME;
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::records::{
+    self, AssignmentStatus, BroadcastJob, ClaimJob, DoneOutcome, DoneRecord, InflightRecord, NonceRecord, TxKind,
+    TxRecord, TxStatus,
+};
+use crate::backend::StorageBackend;
+use crate::{family_of, keys, Batch, KvStore};
+
+pub const CURRENT_VERSION: u32 = 2;
+
+type Step<B> = fn(&KvStore<B>, &mut Batch) -> Result<()>;
+
+/// `(target version, description, step)`; must stay sorted by version.
+fn steps<B: StorageBackend>() -> [(u32, &'static str, Step<B>); 2] {
+    [
+        (1, "typed records with schema version envelope", v0_to_v1),
+        (2, "column family per key family with secondary indexes", v1_to_v2),
+    ]
+}
+
+#[derive(Debug, Clone)]
+pub struct Applied {
+    pub version: u32,
+    pub description: &'static str,
+}
+
+pub fn schema_version<B: StorageBackend>(store: &KvStore<B>) -> Result<u32> {
+    // v1 databases keep the version key in the default column family.
+    let stored = match store.get(keys::SCHEMA_VERSION)? {
+        Some(v) => Some(v),
+        None => store.get_default_cf(keys::SCHEMA_VERSION)?,
+    };
+    match stored {
+        Some(v) => String::from_utf8_lossy(&v)
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid {} value", keys::SCHEMA_VERSION)),
+        None => Ok(0),
+    }
+}
+
+/// Bring the store up to [`CURRENT_VERSION`], returning the steps that ran.
+pub fn run<B: StorageBackend>(store: &KvStore<B>) -> Result<Vec<Applied>> {
+    let from = schema_version(store)?;
+    if from > CURRENT_VERSION {
+        bail!(
+            "database schema v{} is newer than this binary supports (v{}); refusing to open",
+            from,
+            CURRENT_VERSION
+        );
+    }
+    let mut applied = Vec::new();
+    for (version, description, step) in steps::<B>().into_iter().filter(|(v, _, _)| *v > from) {
+        let mut batch = Batch::new();
+        step(store, &mut batch).with_context(|| format!("migration to schema v{} failed", version))?;
+        batch.put(keys::SCHEMA_VERSION, version.to_string());
+        store.write(batch)?;
+        applied.push(Applied { version, description });
+    }
+    Ok(applied)
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or_default()
+}
+
+fn str_field(v: &Value, name: &str) -> Option<String> {
+    v.get(name).and_then(Value::as_str).map(str::to_string)
+}
+
+fn ms_field(v: &Value, name: &str) -> Option<i64> {
+    v.get(name).and_then(|x| x.as_i64().or_else(|| x.as_u64().map(|u| u as i64)))
+}
+
+/// Legacy JSON value under `key`, or `None` if it already carries a version.
+fn legacy_json(key: &str, raw: &[u8]) -> Result<Option<Value>> {
+    let v: Value = serde_json::from_slice(raw).with_context(|| format!("{} is not valid JSON", key))?;
+    Ok(if v.get("v").is_some() { None } else { Some(v) })
+}
+
+/// Move the v0 value at `key` out of the way; see the module docs.
+fn quarantine(batch: &mut Batch, key: &str, raw: &[u8], reason: impl std::fmt::Display) {
+    let to = keys::quarantine(key);
+    warn!(%key, %reason, quarantined_as = %to, "unreadable legacy record; moved aside");
+    batch.delete_default_cf(key).put_default_cf(to, raw);
+}
+
+/// `legacy_json`, quarantining what does not parse; `None` means skip the key.
+fn legacy_or_quarantine(batch: &mut Batch, key: &str, raw: &[u8]) -> Option<Value> {
+    match legacy_json(key, raw) {
+        Ok(v) => v,
+        Err(e) => {
+            quarantine(batch, key, raw, format!("{:#}", e));
+            None
+        }
+    }
+}
+
+fn legacy_tx(key: &str, v: &Value, now: i64) -> Result<TxRecord> {
+    let kind = match str_field(v, "kind").as_deref() {
+        None | Some("claim") => TxKind::Claim,
+        Some("heartbeat") => TxKind::Heartbeat,
+        Some("complete") => TxKind::Complete,
+        Some("resume") => TxKind::Resume,
+        Some(other) => return Err(anyhow!("{} has unknown tx kind {:?}", key, other)),
+    };
+    // The dev-mock claim path wrote no status and treated the claim as confirmed.
+    let status = match str_field(v, "status").as_deref() {
+        None | Some("confirmed") => TxStatus::Confirmed,
+        Some("pending") => TxStatus::Pending,
+        Some("submitted") => TxStatus::Submitted,
+        Some("dropped") => TxStatus::Dropped,
+        Some("replaced") => TxStatus::Replaced,
+        Some(other) => return Err(anyhow!("{} has unknown tx status {:?}", key, other)),
+    };
+    Ok(TxRecord {
+        activity_id: str_field(v, "activity_id").unwrap_or_else(|| key["tx:".len()..].to_string()),
+        kind,
+        status,
+        tx_hash: str_field(v, "tx_hash"),
+        submitted_at_ms: ms_field(v, "submitted_at_ms").unwrap_or(now),
+        last_bump_at_ms: ms_field(v, "last_bump_at_ms"),
+        nonce: None,
+    })
+}
+
+/// v0 → v1: wrap every family in a versioned typed record.
+///
+/// v0 had several writers with diverging shapes: the Broadcaster's inflight
+/// (`claimed_at_ms`, `assignment_status`), the Assigner's overwrite
+/// (`instance_id`, `wep_status`), tx records without `status`, bare
+/// `ok`/`failed`/`timeout` done markers and `1` broadcast markers. Both
+/// versions used the single default column family, so this step reads and
+/// writes there.
+fn v0_to_v1<B: StorageBackend>(store: &KvStore<B>, batch: &mut Batch) -> Result<()> {
+    let now = now_ms();
+
+    for (k, raw) in store.scan_cf(DEFAULT_COLUMN_FAMILY_NAME, "claim_job:")? {
+        let key = String::from_utf8_lossy(&k).into_owned();
+        let Some(v) = legacy_or_quarantine(batch, &key, &raw) else { continue };
+        let rec = ClaimJob {
+            activity_id: str_field(&v, "activity_id").unwrap_or_else(|| key["claim_job:".len()..].to_string()),
+            queue_name: str_field(&v, "queue_name").unwrap_or_default(),
+            created_at_ms: ms_field(&v, "created_at_ms").unwrap_or(now),
+            partition: 0,
+            expires_at_ms: None,
+        };
+        batch.put_default_cf(&key, records::encode(&rec)?);
+    }
+
+    for (k, raw) in store.scan_cf(DEFAULT_COLUMN_FAMILY_NAME, "inflight:")? {
+        let key = String::from_utf8_lossy(&k).into_owned();
+        let Some(v) = legacy_or_quarantine(batch, &key, &raw) else { continue };
+        let running = [str_field(&v, "assignment_status"), str_field(&v, "wep_status")]
+            .iter()
+            .any(|s| s.as_deref() == Some("Running"));
+        let rec = InflightRecord {
+            activity_id: str_field(&v, "activity_id").unwrap_or_else(|| key["inflight:".len()..].to_string()),
+            queue: str_field(&v, "queue").unwrap_or_default(),
+            claimed_at_ms: ms_field(&v, "claimed_at_ms").unwrap_or(now),
+            assignment_status: if running { AssignmentStatus::Running } else { AssignmentStatus::Pending },
+            instance_id: str_field(&v, "instance_id"),
+            expires_at_ms: None,
+            inputs: Vec::new(),
+            upload_prefix: None,
+        };
+        batch.put_default_cf(&key, records::encode(&rec)?);
+    }
+
+    for (k, raw) in store.scan_cf(DEFAULT_COLUMN_FAMILY_NAME, "tx:")? {
+        let key = String::from_utf8_lossy(&k).into_owned();
+        let Some(v) = legacy_or_quarantine(batch, &key, &raw) else { continue };
+        match legacy_tx(&key, &v, now) {
+            Ok(rec) => { batch.put_default_cf(&key, records::encode(&rec)?); }
+            Err(e) => quarantine(batch, &key, &raw, e),
+        }
+    }
+
+    for (k, raw) in store.scan_cf(DEFAULT_COLUMN_FAMILY_NAME, "done:")? {
+        let key = String::from_utf8_lossy(&k).into_owned();
+        if serde_json::from_slice::<Value>(&raw).is_ok_and(|v| v.get("v").is_some()) {
+            continue;
+        }
+        let marker = String::from_utf8_lossy(&raw).into_owned();
+        let outcome = match marker.as_str() {
+            "ok" => DoneOutcome::Ok,
+            "timeout" => DoneOutcome::Timeout,
+            _ => DoneOutcome::Failed,
+        };
+        let rec = DoneRecord {
+            activity_id: key["done:".len()..].to_string(),
+            outcome,
+            result_ref: None,
+            error: (outcome == DoneOutcome::Failed && marker != "failed").then_some(marker),
+            finished_at_ms: now,
+            instance_id: None,
+            queue: None,
+        };
+        batch.put_default_cf(&key, records::encode(&rec)?);
+    }
+
+    for (k, raw) in store.scan_cf(DEFAULT_COLUMN_FAMILY_NAME, "broadcast:")? {
+        let key = String::from_utf8_lossy(&k).into_owned();
+        if serde_json::from_slice::<Value>(&raw).is_ok_and(|v| v.get("v").is_some()) {
+            continue;
+        }
+        // broadcast:{claim|complete|resume}:{id}
+        let id = key.splitn(3, ':').nth(2).unwrap_or_default().to_string();
+        batch.put_default_cf(&key, records::encode(&BroadcastJob { id, created_at_ms: now })?);
+    }
+
+    // A bare integer in v0; the sender re-reads the chain nonce when it is missing.
+    if let Some(raw) = store.get_default_cf(keys::NONCE_LAST)? {
+        let text = String::from_utf8_lossy(&raw);
+        match text.trim().trim_matches('"').parse::<u64>() {
+            Ok(nonce) => {
+                batch.put_default_cf(keys::NONCE_LAST, records::encode(&NonceRecord { nonce, updated_at_ms: now })?);
+            }
+            Err(_) if serde_json::from_slice::<Value>(&raw).is_ok_and(|v| v.get("v").is_some()) => {}
+            Err(e) => quarantine(batch, keys::NONCE_LAST, &raw, format!("not a nonce: {}", e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// v1 → v2: move every known family out of the default column family into its
+/// own; the routed puts rebuild the secondary indexes as they go.
+fn v1_to_v2<B: StorageBackend>(store: &KvStore<B>, batch: &mut Batch) -> Result<()> {
+    for (k, v) in store.scan_cf(DEFAULT_COLUMN_FAMILY_NAME, "")? {
+        if family_of(&k) == DEFAULT_COLUMN_FAMILY_NAME {
+            continue;
+        }
+        batch.delete_default_cf(&k).put(&k, &v);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemBackend;
+
+    /// A v0 database: every key in the default column family, no schema version.
+    fn v0_store(pairs: &[(&str, &[u8])]) -> KvStore<MemBackend> {
+        let store = KvStore::in_memory();
+        let mut batch = Batch::new();
+        for (k, v) in pairs {
+            batch.put_default_cf(k, v);
+        }
+        store.write(batch).unwrap();
+        store
+    }
+
+    #[test]
+    fn v0_shapes_become_typed_records() {
+        let store = v0_store(&[
+            ("claim_job:0x01", br#"{"activity_id":"0x01","queue_name":"video","created_at_ms":1000}"#),
+            ("inflight:0x01", br#"{"activity_id":"0x01","queue":"video","claimed_at_ms":2000,"assignment_status":"Pending"}"#),
+            // the Assigner's overwrite
+            ("inflight:0x02", br#"{"activity_id":"0x02","instance_id":"0xdeadbeef","wep_status":"Running"}"#),
+            // dev-mock claim: no status
+            ("tx:0x01", br#"{"activity_id":"0x01","kind":"claim","tx_hash":"0xdevmock","submitted_at_ms":3000}"#),
+            ("tx:0x02", br#"{"activity_id":"0x02","kind":"complete","status":"submitted","submitted_at_ms":4000,"last_bump_at_ms":4500}"#),
+            ("done:0x03", b"ok"),
+            ("done:0x04", b"failed"),
+            ("done:0x05", b"timeout"),
+            ("done:0x06", b"wep crashed"),
+            ("broadcast:claim:0x01", b"1"),
+            ("broadcast:resume:0xdeadbeef", b"1"),
+            (keys::NONCE_LAST, b"42"),
+        ]);
+        let applied = run(&store).unwrap();
+        assert_eq!(applied.iter().map(|a| a.version).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(schema_version(&store).unwrap(), CURRENT_VERSION);
+
+        let job: ClaimJob = store.get_record("claim_job:0x01").unwrap().unwrap();
+        assert_eq!((job.queue_name.as_str(), job.created_at_ms, job.partition), ("video", 1000, 0));
+
+        let a: InflightRecord = store.get_record("inflight:0x01").unwrap().unwrap();
+        assert_eq!((a.queue.as_str(), a.claimed_at_ms, a.assignment_status), ("video", 2000, AssignmentStatus::Pending));
+        let b: InflightRecord = store.get_record("inflight:0x02").unwrap().unwrap();
+        assert_eq!(b.assignment_status, AssignmentStatus::Running);
+        assert_eq!(b.instance_id.as_deref(), Some("0xdeadbeef"));
+
+        let claim: TxRecord = store.get_record("tx:0x01").unwrap().unwrap();
+        assert_eq!((claim.kind, claim.status, claim.tx_hash.as_deref()), (TxKind::Claim, TxStatus::Confirmed, Some("0xdevmock")));
+        let complete: TxRecord = store.get_record("tx:0x02").unwrap().unwrap();
+        assert_eq!((complete.kind, complete.status, complete.last_bump_at_ms), (TxKind::Complete, TxStatus::Submitted, Some(4500)));
+
+        let done = |id: &str| store.get_record::<DoneRecord>(keys::done(id)).unwrap().unwrap();
+        assert_eq!((done("0x03").outcome, done("0x03").error), (DoneOutcome::Ok, None));
+        assert_eq!((done("0x04").outcome, done("0x04").error), (DoneOutcome::Failed, None));
+        assert_eq!(done("0x05").outcome, DoneOutcome::Timeout);
+        assert_eq!((done("0x06").outcome, done("0x06").error.as_deref()), (DoneOutcome::Failed, Some("wep crashed")));
+
+        let job: BroadcastJob = store.get_record(keys::broadcast_claim("0x01")).unwrap().unwrap();
+        assert_eq!(job.id, "0x01");
+        let job: BroadcastJob = store.get_record(keys::broadcast_resume("0xdeadbeef")).unwrap().unwrap();
+        assert_eq!(job.id, "0xdeadbeef");
+        let nonce: NonceRecord = store.get_record(keys::NONCE_LAST).unwrap().unwrap();
+        assert_eq!(nonce.nonce, 42);
+
+        // Idempotent: nothing left to do.
+        assert!(run(&store).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unreadable_v0_values_are_quarantined() {
+        let store = v0_store(&[
+            ("claim_job:0x01", b"not json"),
+            ("inflight:0x02", b"{truncated"),
+            ("tx:0x03", br#"{"kind":"airdrop"}"#),
+            ("tx:0x04", br#"{"kind":"claim","status":"lost"}"#),
+            (keys::NONCE_LAST, b"abc"),
+            ("claim_job:0x05", br#"{"queue_name":"video"}"#),
+        ]);
+        run(&store).unwrap();
+
+        for key in ["claim_job:0x01", "inflight:0x02", "tx:0x03", "tx:0x04", keys::NONCE_LAST] {
+            assert_eq!(store.get(key).unwrap(), None, "{key} left in place");
+            assert_eq!(store.get_default_cf(key).unwrap(), None, "{key} left in the default CF");
+            assert!(store.get_default_cf(keys::quarantine(key)).unwrap().is_some(), "{key} not quarantined");
+        }
+        assert_eq!(store.get_default_cf(keys::quarantine("tx:0x03")).unwrap().unwrap(), br#"{"kind":"airdrop"}"#);
+        // Readable neighbours still migrate.
+        let job: ClaimJob = store.get_record("claim_job:0x05").unwrap().unwrap();
+        assert_eq!(job.activity_id, "0x05");
+    }
+
+    #[test]
+    fn v2_moves_every_key_to_its_family() {
+        let store = v0_store(&[
+            ("claim_job:0x01", br#"{"queue_name":"video"}"#),
+            ("inflight:0x02", br#"{"queue":"video","assignment_status":"Running","instance_id":"0xaa"}"#),
+            ("tx:0x02", br#"{"kind":"claim","status":"submitted"}"#),
+            ("done:0x03", b"timeout"),
+            ("broadcast:complete:0x03", b"1"),
+            (keys::NONCE_LAST, b"7"),
+            (keys::LAST_SCAN_BLOCK, b"100"),
+            ("custom:key", b"kept"),
+        ]);
+        run(&store).unwrap();
+
+        let keys = [
+            "claim_job:0x01", "inflight:0x02", "tx:0x02", "done:0x03", "broadcast:complete:0x03",
+            keys::NONCE_LAST, keys::LAST_SCAN_BLOCK, keys::SCHEMA_VERSION, "custom:key",
+        ];
+        for key in keys {
+            let family = family_of(key.as_bytes());
+            let in_family = store.scan_cf(family, key).unwrap();
+            assert_eq!(in_family.len(), 1, "{key} not in CF {family}");
+            if family != DEFAULT_COLUMN_FAMILY_NAME {
+                assert_eq!(store.get_default_cf(key).unwrap(), None, "{key} left in the default CF");
+            }
+        }
+        assert_eq!(family_of(b"custom:key"), DEFAULT_COLUMN_FAMILY_NAME);
+        assert_eq!(family_of(keys::LAST_SCAN_BLOCK.as_bytes()), "meta");
+        assert_eq!(store.get("custom:key").unwrap().as_deref(), Some(&b"kept"[..]));
+
+        // Indexes were rebuilt by the routed puts.
+        assert_eq!(store.ids_by_state("inflight", "running").unwrap(), ["0x02"]);
+        assert_eq!(store.ids_by_state("tx", "submitted").unwrap(), ["0x02"]);
+        assert_eq!(store.ids_by_state("done", "timeout").unwrap(), ["0x03"]);
+        assert_eq!(store.ids_by_instance("0xaa").unwrap(), ["0x02"]);
+    }
+}
@@ -0,0 +1,215 @@
+//! Typed records stored under each key family (see [`crate::keys`]).
+//!
+//! Every value is JSON with a top-level `"v"` field carrying the record's
+//! schema version. Readers reject versions they do not know; older shapes are
+//! rewritten by [`crate::migrations`] at startup.
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub trait Record: Serialize + DeserializeOwned {
+    /// Human-readable family name, used in error messages.
+    const KIND: &'static str;
+    /// Version written into `"v"`; bump together with a migration step.
+    const VERSION: u32;
+}
+
+#[derive(Serialize)]
+struct EnvelopeRef<'a, R> {
+    v: u32,
+    #[serde(flatten)]
+    rec: &'a R,
+}
+
+#[derive(Deserialize)]
+struct Envelope<R> {
+    #[serde(default)]
+    v: u32,
+    #[serde(flatten)]
+    rec: R,
+}
+
+pub fn encode<R: Record>(rec: &R) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&EnvelopeRef { v: R::VERSION, rec })?)
+}
+
+pub fn decode<R: Record>(bytes: &[u8]) -> Result<R> {
+    let env: Envelope<R> = serde_json::from_slice(bytes)?;
+    if env.v != R::VERSION {
+        bail!("{} record has schema v{} (expected v{}); run migrations", R::KIND, env.v, R::VERSION);
+    }
+    Ok(env.rec)
+}
+
+/// `claim_job:{activity_id}` — written by the Poller for each visible activity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClaimJob {
+    pub activity_id: String,
+    pub queue_name: String,
+    pub created_at_ms: i64,
+    #[serde(default)]
+    pub partition: u16,
+    #[serde(default)]
+    pub expires_at_ms: Option<i64>,
+}
+
+impl Record for ClaimJob {
+    const KIND: &'static str = "claim_job";
+    const VERSION: u32 = 1;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssignmentStatus {
+    Pending,
+    Running,
+}
+
+/// `inflight:{activity_id}` — claimed on-chain and owned by this worker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InflightRecord {
+    pub activity_id: String,
+    pub queue: String,
+    pub claimed_at_ms: i64,
+    pub assignment_status: AssignmentStatus,
+    #[serde(default)]
+    pub instance_id: Option<String>,
+    #[serde(default)]
+    pub expires_at_ms: Option<i64>,
+    /// Inputs as assigned to the WEP, with their original (not presigned) refs.
+    #[serde(default)]
+    pub inputs: Vec<AssignedInput>,
+    #[serde(default)]
+    pub upload_prefix: Option<String>,
+}
+
+/// An input the Assigner handed to the WEP; the WEP may ask to re-presign `reference`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssignedInput {
+    pub name: String,
+    pub reference: String,
+}
+
+impl Record for InflightRecord {
+    const KIND: &'static str = "inflight";
+    const VERSION: u32 = 1;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxKind {
+    Claim,
+    Heartbeat,
+    Complete,
+    Resume,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxStatus {
+    Pending,
+    Submitted,
+    Confirmed,
+    Dropped,
+    Replaced,
+}
+
+/// `tx:{activity_id}` — latest on-chain transaction for the activity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxRecord {
+    pub activity_id: String,
+    pub kind: TxKind,
+    pub status: TxStatus,
+    pub tx_hash: Option<String>,
+    pub submitted_at_ms: i64,
+    pub last_bump_at_ms: Option<i64>,
+    #[serde(default)]
+    pub nonce: Option<u64>,
+}
+
+impl Record for TxRecord {
+    const KIND: &'static str = "tx";
+    const VERSION: u32 = 1;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DoneOutcome {
+    Ok,
+    Failed,
+    Timeout,
+    /// Lease ran out on-chain before we completed, or the chain no longer
+    /// assigns the activity to us (`error` says which); set by the reconciler.
+    Expired,
+    /// Dropped by an operator before it was claimed.
+    Cancelled,
+}
+
+/// `done:{activity_id}` — terminal summary once the WEP finished (or gave up).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DoneRecord {
+    pub activity_id: String,
+    pub outcome: DoneOutcome,
+    #[serde(default)]
+    pub result_ref: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub finished_at_ms: i64,
+    #[serde(default)]
+    pub instance_id: Option<String>,
+    /// Queue it was served from, so an operator retry can restore it; unset when unknown.
+    #[serde(default)]
+    pub queue: Option<String>,
+}
+
+impl Record for DoneRecord {
+    const KIND: &'static str = "done";
+    const VERSION: u32 = 1;
+}
+
+/// `broadcast:{claim,complete,resume}:{id}` — pending chain follow-up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastJob {
+    pub id: String,
+    pub created_at_ms: i64,
+}
+
+impl Record for BroadcastJob {
+    const KIND: &'static str = "broadcast";
+    const VERSION: u32 = 1;
+}
+
+/// `nonce:last` — last nonce used by the wallet's tx lane.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NonceRecord {
+    pub nonce: u64,
+    pub updated_at_ms: i64,
+}
+
+impl Record for NonceRecord {
+    const KIND: &'static str = "nonce";
+    const VERSION: u32 = 1;
+}
+
+/// `meta:last_scan_block` — last block whose logs the EventPoller has published.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanCheckpoint {
+    pub block: u64,
+    pub updated_at_ms: i64,
+}
+
+impl Record for ScanCheckpoint {
+    const KIND: &'static str = "scan_checkpoint";
+    const VERSION: u32 = 1;
+}
+
+/// `meta:paused_queue:{queue}` — polling for the queue paused through the admin API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuePause {
+    pub queue: String,
+    pub paused_at_ms: i64,
+}
+
+impl Record for QueuePause {
+    const KIND: &'static str = "queue_pause";
+    const VERSION: u32 = 1;
+}
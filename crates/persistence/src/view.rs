@@ -0,0 +1,143 @@
+//! Per-activity view across the key families, as shown by the admin API and `wcpctl`.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::backend::StorageBackend;
+use crate::records::{BroadcastJob, ClaimJob, DoneOutcome, DoneRecord, InflightRecord, TxKind, TxRecord, TxStatus};
+use crate::{keys, KvStore};
+
+/// Where an activity is in the local pipeline; the furthest family holding it wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityState {
+    /// `claim_job:*` only; waiting for the Scheduler.
+    Queued,
+    /// `broadcast:claim:*` pending.
+    Claiming,
+    Inflight,
+    Done,
+}
+
+impl ActivityState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityState::Queued => "queued",
+            ActivityState::Claiming => "claiming",
+            ActivityState::Inflight => "inflight",
+            ActivityState::Done => "done",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivitySummary {
+    pub activity_id: String,
+    pub state: ActivityState,
+    pub queue: Option<String>,
+    pub outcome: Option<DoneOutcome>,
+    /// When the activity entered its current state.
+    pub since_ms: i64,
+}
+
+/// Every local record for one activity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityDetail {
+    pub activity_id: String,
+    pub state: ActivityState,
+    pub claim_job: Option<ClaimJob>,
+    pub claim_broadcast: Option<BroadcastJob>,
+    pub inflight: Option<InflightRecord>,
+    pub done: Option<DoneRecord>,
+    pub complete_broadcast: Option<BroadcastJob>,
+    /// Latest transaction for the activity, with its bump/replacement state.
+    pub tx: Option<TxRecord>,
+}
+
+impl ActivityDetail {
+    pub fn summary(&self) -> ActivitySummary {
+        let queue = self
+            .inflight
+            .as_ref()
+            .map(|r| r.queue.clone())
+            .or_else(|| self.claim_job.as_ref().map(|j| j.queue_name.clone()))
+            .or_else(|| self.done.as_ref().and_then(|d| d.queue.clone()))
+            .filter(|q| !q.is_empty());
+        let since_ms = match self.state {
+            ActivityState::Done => self.done.as_ref().map(|d| d.finished_at_ms),
+            ActivityState::Inflight => self.inflight.as_ref().map(|r| r.claimed_at_ms),
+            ActivityState::Claiming => self.claim_broadcast.as_ref().map(|b| b.created_at_ms),
+            ActivityState::Queued => self.claim_job.as_ref().map(|j| j.created_at_ms),
+        };
+        ActivitySummary {
+            activity_id: self.activity_id.clone(),
+            state: self.state,
+            queue,
+            outcome: self.done.as_ref().map(|d| d.outcome),
+            since_ms: since_ms.unwrap_or_default(),
+        }
+    }
+
+    /// A claim tx is on its way; the claim can no longer be called off locally.
+    pub fn claim_submitted(&self) -> bool {
+        self.tx.as_ref().is_some_and(|t| t.kind == TxKind::Claim && matches!(t.status, TxStatus::Submitted | TxStatus::Confirmed))
+    }
+}
+
+/// `None` when no family knows the activity.
+pub fn activity<B: StorageBackend>(store: &KvStore<B>, activity_id: &str) -> Result<Option<ActivityDetail>> {
+    let claim_job: Option<ClaimJob> = store.get_record(keys::claim_job(activity_id))?;
+    let claim_broadcast: Option<BroadcastJob> = store.get_record(keys::broadcast_claim(activity_id))?;
+    let inflight: Option<InflightRecord> = store.get_record(keys::inflight(activity_id))?;
+    let done: Option<DoneRecord> = store.get_record(keys::done(activity_id))?;
+    let state = if done.is_some() {
+        ActivityState::Done
+    } else if inflight.is_some() {
+        ActivityState::Inflight
+    } else if claim_broadcast.is_some() {
+        ActivityState::Claiming
+    } else if claim_job.is_some() {
+        ActivityState::Queued
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(ActivityDetail {
+        activity_id: activity_id.to_string(),
+        state,
+        claim_job,
+        claim_broadcast,
+        inflight,
+        done,
+        complete_broadcast: store.get_record(keys::broadcast_complete(activity_id))?,
+        tx: store.get_record(keys::tx(activity_id))?,
+    }))
+}
+
+/// Summaries of every known activity in id order, optionally only those in `state`.
+pub fn activities<B: StorageBackend>(store: &KvStore<B>, state: Option<ActivityState>) -> Result<Vec<ActivitySummary>> {
+    let mut ids = BTreeSet::new();
+    for prefix in ["claim_job:", "broadcast:claim:", "inflight:", "done:"] {
+        for (k, _) in store.scan_prefix(prefix)? {
+            ids.insert(String::from_utf8_lossy(&k[prefix.len()..]).into_owned());
+        }
+    }
+    let mut out = Vec::new();
+    for id in ids {
+        if let Some(d) = activity(store, &id)? {
+            if state.is_none_or(|s| s == d.state) {
+                out.push(d.summary());
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// `tx:*` records in id order, optionally only those with `status`.
+pub fn txs<B: StorageBackend>(store: &KvStore<B>, status: Option<TxStatus>) -> Result<Vec<TxRecord>> {
+    Ok(store
+        .scan_records::<TxRecord>("tx:")?
+        .into_iter()
+        .map(|(_, t)| t)
+        .filter(|t| status.is_none_or(|s| s == t.status))
+        .collect())
+}
@@ -7,6 +7,7 @@ use tonic::transport::Channel;
 use execution_v1::{execution_client::ExecutionClient, Envelope};
 use tokio::sync::mpsc::{Sender, channel};
 use tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::AsciiMetadataKey;
 use tonic::Streaming;
 
 pub struct WepGrpcClient {
@@ -19,9 +20,15 @@ impl WepGrpcClient {
         Ok(Self { inner: ExecutionClient::new(channel) })
     }
 
+    /// Opens the stream with the current span's W3C trace context in its metadata.
     pub async fn open_task_stream(&mut self) -> Result<(Sender<Envelope>, Streaming<Envelope>)> {
         let (tx, rx) = channel::<Envelope>(32);
-        let request = tonic::Request::new(ReceiverStream::new(rx));
+        let mut request = tonic::Request::new(ReceiverStream::new(rx));
+        for (key, value) in subnet_wcp_telemetry::trace_context() {
+            if let (Ok(key), Ok(value)) = (key.parse::<AsciiMetadataKey>(), value.parse()) {
+                request.metadata_mut().insert(key, value);
+            }
+        }
         let response = self.inner.task_stream(request).await?;
         Ok((tx, response.into_inner()))
     }
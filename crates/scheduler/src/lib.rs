@@ -1,53 +1,354 @@
+//! Claim scheduling: turns `claim_job:*` into `broadcast:claim:*` intents.
+//!
+//! Each tick reads the pending claim jobs, what is already inflight or being
+//! claimed, and the WEP's free capacity, drops jobs the [`ProfitGate`] rejects,
+//! asks the configured [`Policy`] for an order of preference and claims as many
+//! as capacity and per-queue limits allow.
+//!
+//! With [`Scheduler::with_reload`], queue, limit, interval and policy changes
+//! from a config reload apply from the next tick.
+use alloy::providers::Provider;
 use anyhow::Result;
-use subnet_wcp_persistence::{KvStore, keys};
-use serde::{Serialize, Deserialize};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tracing::{info, warn};
-use alloy::providers::Provider;
-use alloy::primitives::Address;
-use hex::ToHex;
-use subnet_wcp_chain as chain;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ClaimJob {
-    pub activity_id: String,
-    pub queue_name: String,
-    pub created_at_ms: u128,
+use subnet_wcp_config::{QueueConfig, SchedulerConfig};
+use subnet_wcp_metrics as metrics;
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
+use subnet_wcp_persistence::records::{BroadcastJob, ClaimJob, InflightRecord};
+use subnet_wcp_persistence::{keys, Batch, KvStore};
+use subnet_wcp_telemetry::activity_span;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+pub mod policy;
+pub mod profit;
+
+pub use policy::{Candidate, Policy};
+pub use profit::ProfitGate;
+
+/// Pick up to `slots` candidates in `policy` order, skipping any whose queue
+/// already has `limits[queue]` activities busy (inflight or being claimed).
+/// Queues missing from `limits` (removed or disabled by a reload, or never
+/// configured) get nothing.
+pub fn decide(
+    policy: &dyn Policy,
+    candidates: Vec<Candidate>,
+    limits: &HashMap<String, usize>,
+    busy: &HashMap<String, usize>,
+    slots: usize,
+) -> Vec<Candidate> {
+    let mut busy = busy.clone();
+    let mut picked = Vec::new();
+    for c in policy.order(candidates) {
+        if picked.len() >= slots {
+            break;
+        }
+        let Some(limit) = limits.get(&c.queue) else {
+            debug!(activity_id = %c.activity_id, queue = %c.queue, "skipping claim job for a queue that is not configured");
+            continue;
+        };
+        let used = busy.entry(c.queue.clone()).or_default();
+        if *used >= *limit {
+            continue;
+        }
+        *used += 1;
+        picked.push(c);
+    }
+    picked
 }
 
-pub struct Scheduler<P: Provider + Clone + Send + Sync + 'static> {
-    store: KvStore,
-    poll_interval: Duration,
-    queue_name: String,
+/// `capacity` block of the WEP `/health` response.
+#[derive(Deserialize)]
+struct WepHealth {
+    capacity: Option<WepCapacity>,
+}
+
+#[derive(Deserialize)]
+struct WepCapacity {
+    available: usize,
+}
+
+pub struct Scheduler<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend = RocksBackend> {
+    store: KvStore<B>,
     provider: P,
-    task_queue_addr: Address,
+    policy: Box<dyn Policy>,
+    profit_gate: Option<ProfitGate>,
+    queues: HashMap<String, QueueConfig>,
+    max_inflight: usize,
+    interval: Duration,
+    /// `GET {wep_endpoint}/health`; `None` when there is no WEP to ask (dev mock).
+    wep_health_url: Option<String>,
+    client: reqwest::Client,
+    reload: Option<watch::Receiver<SchedulerConfig>>,
 }
 
-impl<P: Provider + Clone + Send + Sync + 'static> Scheduler<P> {
-    pub fn new(store: KvStore, poll_interval: Duration, queue_name: String, provider: P, task_queue_addr: Address) -> Self {
-        Self { store, poll_interval, queue_name, provider, task_queue_addr }
+impl<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend> Scheduler<P, B> {
+    pub fn new(
+        store: KvStore<B>,
+        provider: P,
+        policy: Box<dyn Policy>,
+        queues: Vec<QueueConfig>,
+        max_inflight: usize,
+        interval: Duration,
+        wep_endpoint: Option<String>,
+    ) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .expect("Failed to create HTTP client");
+        Self {
+            store,
+            provider,
+            policy,
+            profit_gate: None,
+            queues: queues.into_iter().map(|q| (q.name.clone(), q)).collect(),
+            max_inflight,
+            interval,
+            wep_health_url: wep_endpoint.map(|e| format!("{}/health", e.trim_end_matches('/'))),
+            client,
+            reload: None,
+        }
+    }
+
+    /// Skip claim jobs that `gate` deems unprofitable at current fees.
+    pub fn with_profit_gate(mut self, gate: ProfitGate) -> Self {
+        self.profit_gate = Some(gate);
+        self
     }
 
-    pub async fn run(&self) -> Result<()> {
+    /// Follow `[scheduler]` reloads: queues, `max_inflight`, `poll_interval` and `policy`.
+    pub fn with_reload(mut self, settings: watch::Receiver<SchedulerConfig>) -> Self {
+        self.reload = Some(settings);
+        self
+    }
+
+    pub async fn run(mut self) -> Result<()> {
+        info!(policy = self.policy.name(), profit_gate = self.profit_gate.is_some(), "scheduler started");
         loop {
-            if let Some(id) = poll_once(&self.provider, self.task_queue_addr, &self.queue_name).await? {
-                let job = ClaimJob {
-                    activity_id: format!("0x{}", id.encode_hex::<String>()),
-                    queue_name: self.queue_name.clone(),
-                    created_at_ms: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis(),
-                };
-                let key = keys::claim_job(&job.activity_id);
-                let val = serde_json::to_vec(&job)?;
-                self.store.put(key, val)?;
-                info!(activity_id = %job.activity_id, "enqueued claim job");
+            self.apply_reload();
+            if let Err(e) = self.tick().await {
+                warn!(error = %e, "scheduler tick failed");
             }
-            tokio::time::sleep(self.poll_interval).await;
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    /// One scheduling round; returns the activity ids it decided to claim.
+    pub async fn tick(&self) -> Result<Vec<String>> {
+        let claiming: HashSet<String> = self
+            .store
+            .scan_records::<BroadcastJob>("broadcast:claim:")?
+            .into_iter()
+            .map(|(_, job)| job.id)
+            .collect();
+        let inflight = self.store.scan_records::<InflightRecord>("inflight:")?;
+
+        let now = now_ms();
+        let mut busy: HashMap<String, usize> = HashMap::new();
+        let mut candidates = Vec::new();
+        let mut expired = Batch::new();
+        for (key, job) in self.store.scan_records::<ClaimJob>("claim_job:")? {
+            if claiming.contains(&job.activity_id) {
+                *busy.entry(job.queue_name.clone()).or_default() += 1;
+                continue;
+            }
+            // Past its lease it can no longer be claimed; this is where skipped jobs end.
+            if job.expires_at_ms.is_some_and(|ts| ts <= now) {
+                debug!(activity_id = %job.activity_id, queue = %job.queue_name, "dropping expired claim job");
+                expired.delete(key);
+                continue;
+            }
+            let queue = self.queues.get(&job.queue_name);
+            candidates.push(Candidate {
+                activity_id: job.activity_id,
+                queue: job.queue_name,
+                created_at_ms: job.created_at_ms,
+                expires_at_ms: job.expires_at_ms,
+                reward_wei: queue.and_then(|q| q.reward_wei),
+                expected_duration: queue.and_then(|q| q.expected_duration),
+            });
+        }
+        if !expired.is_empty() {
+            self.store.write(expired)?;
+        }
+        for (_, rec) in &inflight {
+            *busy.entry(rec.queue.clone()).or_default() += 1;
+        }
+        let candidates = self.filter_profitable(candidates).await;
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let local_free = self.max_inflight.saturating_sub(inflight.len() + claiming.len());
+        let slots = match self.wep_free().await {
+            Some(wep_free) => local_free.min(wep_free),
+            None => local_free,
+        };
+        let limits = self
+            .queues
+            .values()
+            .map(|q| (q.name.clone(), q.max_inflight.unwrap_or(self.max_inflight)))
+            .collect();
+        let picked = decide(self.policy.as_ref(), candidates, &limits, &busy, slots);
+        debug!(slots, picked = picked.len(), "scheduler decision");
+        if picked.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut batch = Batch::new();
+        for c in &picked {
+            batch.put_record(keys::broadcast_claim(&c.activity_id), &BroadcastJob { id: c.activity_id.clone(), created_at_ms: now })?;
+        }
+        self.store.write(batch)?;
+        for c in &picked {
+            metrics::CLAIMS_SCHEDULED.with_label_values(&[&c.queue]).inc();
+            let span = activity_span("schedule", &c.activity_id);
+            span.record("queue", c.queue.as_str());
+            span.in_scope(|| info!(activity_id = %c.activity_id, queue = %c.queue, policy = self.policy.name(), "scheduled claim"));
+        }
+        Ok(picked.into_iter().map(|c| c.activity_id).collect())
+    }
+
+    fn apply_reload(&mut self) {
+        let Some(settings) = self.reload.as_mut().filter(|s| s.has_changed().unwrap_or(false)) else { return };
+        let cfg = settings.borrow_and_update().clone();
+        let queues = cfg.queues();
+        let weights = queues.iter().map(|q| (q.name.clone(), q.weight)).collect();
+        self.policy = policy::from_kind(cfg.policy, weights);
+        self.queues = queues.into_iter().map(|q| (q.name.clone(), q)).collect();
+        self.max_inflight = cfg.max_inflight;
+        self.interval = cfg.poll_interval;
+        info!(policy = self.policy.name(), queues = self.queues.len(), max_inflight = self.max_inflight, "scheduler settings reloaded");
+    }
+
+    /// Candidates the profit gate accepts. Rejected claim jobs stay in place:
+    /// fees move, so they are checked again next tick until claimed or expired.
+    /// Without a fee quote nothing is claimed this tick.
+    async fn filter_profitable(&self, candidates: Vec<Candidate>) -> Vec<Candidate> {
+        let Some(gate) = &self.profit_gate else { return candidates };
+        if candidates.is_empty() {
+            return candidates;
+        }
+        let fee_per_gas = match self.provider.estimate_eip1559_fees().await {
+            Ok(fees) => fees.max_fee_per_gas,
+            Err(_) => match self.provider.get_gas_price().await {
+                Ok(price) => price,
+                Err(e) => {
+                    warn!(error = %e, "no fee quote for profitability check; not claiming");
+                    return Vec::new();
+                }
+            },
+        };
+        let mut keep = Vec::new();
+        for c in candidates {
+            match gate.check(&c, fee_per_gas) {
+                None => keep.push(c),
+                Some(reason) => {
+                    metrics::PROFIT_SKIPS.with_label_values(&[&c.queue]).inc();
+                    let span = activity_span("schedule", &c.activity_id);
+                    span.record("queue", c.queue.as_str());
+                    span.in_scope(|| debug!(activity_id = %c.activity_id, queue = %c.queue, %reason, "skipping unprofitable activity this tick"));
+                }
+            }
+        }
+        keep
+    }
+
+    /// Free WEP slots; `Some(0)` if the WEP cannot be reached, `None` if it
+    /// does not report capacity (or there is no WEP to ask).
+    async fn wep_free(&self) -> Option<usize> {
+        let url = self.wep_health_url.as_ref()?;
+        let resp = match self.client.get(url).send().await.and_then(|r| r.error_for_status()) {
+            Ok(resp) => resp,
+            Err(e) => {
+                warn!(error = %e, "WEP health check failed; not claiming");
+                return Some(0);
+            }
+        };
+        match resp.json::<WepHealth>().await {
+            Ok(health) => health.capacity.map(|c| c.available),
+            Err(_) => None,
         }
     }
 }
 
-async fn poll_once<P: Provider + Clone + Send + Sync + 'static>(provider: &P, task_queue_addr: Address, queue: &str) -> Result<Option<[u8;32]>> {
-    chain::task_queue::poll_activity(provider, task_queue_addr, queue).await
+fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or_default()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use policy::Fifo;
+
+    fn cand(id: &str, queue: &str, created: i64) -> Candidate {
+        Candidate {
+            activity_id: id.into(),
+            queue: queue.into(),
+            created_at_ms: created,
+            expires_at_ms: None,
+            reward_wei: None,
+            expected_duration: None,
+        }
+    }
+
+    #[test]
+    fn decide_respects_slots() {
+        let limits = HashMap::from([("q".to_string(), 10)]);
+        let picked = decide(&Fifo, vec![cand("a", "q", 0), cand("b", "q", 1)], &limits, &HashMap::new(), 1);
+        assert_eq!(picked.len(), 1);
+        assert_eq!(picked[0].activity_id, "a");
+    }
+
+    #[test]
+    fn decide_skips_queues_at_limit() {
+        let limits = HashMap::from([("video".to_string(), 2), ("audio".to_string(), 1)]);
+        let busy = HashMap::from([("video".to_string(), 1)]);
+        let input = vec![cand("v0", "video", 0), cand("v1", "video", 1), cand("a0", "audio", 2)];
+        let picked = decide(&Fifo, input, &limits, &busy, 10);
+        let ids: Vec<_> = picked.iter().map(|c| c.activity_id.as_str()).collect();
+        assert_eq!(ids, ["v0", "a0"]);
+    }
+
+    #[test]
+    fn decide_with_no_slots_picks_nothing() {
+        let limits = HashMap::from([("q".to_string(), 10)]);
+        assert!(decide(&Fifo, vec![cand("a", "q", 0)], &limits, &HashMap::new(), 0).is_empty());
+    }
+
+    #[test]
+    fn decide_skips_queues_that_are_not_configured() {
+        let limits = HashMap::from([("video".to_string(), 10)]);
+        let input = vec![cand("r0", "removed", 0), cand("v0", "video", 1), cand("r1", "removed", 2)];
+        let picked = decide(&Fifo, input, &limits, &HashMap::new(), 10);
+        let ids: Vec<_> = picked.iter().map(|c| c.activity_id.as_str()).collect();
+        assert_eq!(ids, ["v0"]);
+    }
+
+    #[tokio::test]
+    async fn unprofitable_jobs_are_kept_and_expired_ones_dropped() {
+        use alloy::providers::ProviderBuilder;
+        use alloy::transports::mock::Asserter;
+        use subnet_wcp_config::ProfitabilityConfig;
 
+        let store = KvStore::in_memory();
+        let job = |id: &str, expires_at_ms| ClaimJob { activity_id: id.into(), queue_name: "q".into(), created_at_ms: 0, partition: 0, expires_at_ms };
+        store.put_record(keys::claim_job("0x01"), &job("0x01", None)).unwrap();
+        store.put_record(keys::claim_job("0x02"), &job("0x02", Some(1))).unwrap();
+        // No EIP-1559 estimate, so the gate falls back to eth_gasPrice.
+        let asserter = Asserter::new();
+        asserter.push_failure_msg("method not found");
+        asserter.push_success(&"0x1");
+        let provider = ProviderBuilder::new().connect_mocked_client(asserter);
+        let gate = ProfitGate::new(ProfitabilityConfig { require_reward: true, ..Default::default() });
+        let scheduler = Scheduler::new(store.clone(), provider, Box::new(Fifo), Vec::new(), 4, Duration::from_secs(1), None).with_profit_gate(gate);
+
+        let skips = metrics::PROFIT_SKIPS.with_label_values(&["q"]);
+        let before = skips.get();
+        assert!(scheduler.tick().await.unwrap().is_empty());
+        assert_eq!(skips.get(), before + 1);
+        let left: Vec<_> = store.scan_records::<ClaimJob>("claim_job:").unwrap().into_iter().map(|(_, j)| j.activity_id).collect();
+        assert_eq!(left, ["0x01"]);
+    }
+}
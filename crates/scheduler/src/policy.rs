@@ -0,0 +1,200 @@
+//! Claim selection policies.
+//!
+//! A policy only orders candidates by preference; [`crate::decide`] then walks
+//! that order and applies free capacity and per-queue limits.
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
+
+use subnet_wcp_config::PolicyKind;
+
+/// A `claim_job:*` the scheduler may turn into a claim.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub activity_id: String,
+    pub queue: String,
+    pub created_at_ms: i64,
+    pub expires_at_ms: Option<i64>,
+    pub reward_wei: Option<u128>,
+    pub expected_duration: Option<Duration>,
+}
+
+pub trait Policy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// `candidates` reordered from most to least preferred.
+    fn order(&self, candidates: Vec<Candidate>) -> Vec<Candidate>;
+}
+
+/// Build the policy selected in config; `weights` are the per-queue weights.
+pub fn from_kind(kind: PolicyKind, weights: HashMap<String, u32>) -> Box<dyn Policy> {
+    match kind {
+        PolicyKind::Fifo => Box::new(Fifo),
+        PolicyKind::EarliestExpiry => Box::new(EarliestExpiry),
+        PolicyKind::Weighted => Box::new(WeightedQueues { weights }),
+        PolicyKind::RewardPerSecond => Box::new(RewardPerSecond),
+    }
+}
+
+fn by_age(a: &Candidate, b: &Candidate) -> Ordering {
+    a.created_at_ms.cmp(&b.created_at_ms).then_with(|| a.activity_id.cmp(&b.activity_id))
+}
+
+/// Oldest claim job first.
+pub struct Fifo;
+
+impl Policy for Fifo {
+    fn name(&self) -> &'static str { "fifo" }
+
+    fn order(&self, mut candidates: Vec<Candidate>) -> Vec<Candidate> {
+        candidates.sort_by(by_age);
+        candidates
+    }
+}
+
+/// Soonest `expires_at_ms` first; unknown expiry after all known ones, then FIFO.
+pub struct EarliestExpiry;
+
+impl Policy for EarliestExpiry {
+    fn name(&self) -> &'static str { "earliest_expiry" }
+
+    fn order(&self, mut candidates: Vec<Candidate>) -> Vec<Candidate> {
+        candidates.sort_by(|a, b| {
+            let key = |c: &Candidate| (c.expires_at_ms.is_none(), c.expires_at_ms.unwrap_or_default());
+            key(a).cmp(&key(b)).then_with(|| by_age(a, b))
+        });
+        candidates
+    }
+}
+
+/// Interleave queues by weight (smooth weighted round-robin), FIFO within a
+/// queue. Queues without a weight count as 1; weight 0 goes last.
+pub struct WeightedQueues {
+    pub weights: HashMap<String, u32>,
+}
+
+impl Policy for WeightedQueues {
+    fn name(&self) -> &'static str { "weighted" }
+
+    fn order(&self, mut candidates: Vec<Candidate>) -> Vec<Candidate> {
+        candidates.sort_by(by_age);
+        let mut lanes: BTreeMap<String, VecDeque<Candidate>> = BTreeMap::new();
+        for c in candidates {
+            lanes.entry(c.queue.clone()).or_default().push_back(c);
+        }
+        let weight = |q: &str| self.weights.get(q).copied().unwrap_or(1) as i64;
+        let mut credit: BTreeMap<String, i64> = lanes.keys().map(|q| (q.clone(), 0)).collect();
+        let mut out = Vec::new();
+        loop {
+            let live: Vec<String> = lanes.iter().filter(|(q, l)| !l.is_empty() && weight(q) > 0).map(|(q, _)| q.clone()).collect();
+            if live.is_empty() {
+                break;
+            }
+            let total: i64 = live.iter().map(|q| weight(q)).sum();
+            for q in &live {
+                *credit.get_mut(q).unwrap() += weight(q);
+            }
+            // Ties go to the first queue by name so the order is deterministic.
+            let best = live.iter().max_by(|a, b| credit[*a].cmp(&credit[*b]).then_with(|| b.cmp(a))).unwrap().clone();
+            *credit.get_mut(&best).unwrap() -= total;
+            out.extend(lanes.get_mut(&best).unwrap().pop_front());
+        }
+        out.extend(lanes.into_values().flatten());
+        out
+    }
+}
+
+/// Highest `reward_wei / expected_duration` first; candidates missing either
+/// go after all rated ones, then FIFO.
+pub struct RewardPerSecond;
+
+impl RewardPerSecond {
+    fn rate(c: &Candidate) -> Option<f64> {
+        let secs = c.expected_duration?.as_secs_f64();
+        let reward = c.reward_wei? as f64;
+        (secs > 0.0).then(|| reward / secs)
+    }
+}
+
+impl Policy for RewardPerSecond {
+    fn name(&self) -> &'static str { "reward_per_second" }
+
+    fn order(&self, mut candidates: Vec<Candidate>) -> Vec<Candidate> {
+        candidates.sort_by(|a, b| match (Self::rate(a), Self::rate(b)) {
+            (Some(x), Some(y)) => y.total_cmp(&x).then_with(|| by_age(a, b)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => by_age(a, b),
+        });
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cand(id: &str, queue: &str, created: i64) -> Candidate {
+        Candidate {
+            activity_id: id.into(),
+            queue: queue.into(),
+            created_at_ms: created,
+            expires_at_ms: None,
+            reward_wei: None,
+            expected_duration: None,
+        }
+    }
+
+    fn ids(v: &[Candidate]) -> Vec<&str> {
+        v.iter().map(|c| c.activity_id.as_str()).collect()
+    }
+
+    #[test]
+    fn fifo_orders_by_creation_then_id() {
+        let out = Fifo.order(vec![cand("c", "q", 3), cand("b", "q", 1), cand("a", "q", 1)]);
+        assert_eq!(ids(&out), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn earliest_expiry_puts_unknown_expiry_last() {
+        let mut a = cand("a", "q", 1);
+        let mut b = cand("b", "q", 2);
+        let c = cand("c", "q", 0);
+        a.expires_at_ms = Some(500);
+        b.expires_at_ms = Some(100);
+        let out = EarliestExpiry.order(vec![a, b, c]);
+        assert_eq!(ids(&out), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn weighted_interleaves_by_weight() {
+        let policy = WeightedQueues { weights: HashMap::from([("video".into(), 2), ("audio".into(), 1)]) };
+        let mut input = Vec::new();
+        for i in 0..4 {
+            input.push(cand(&format!("v{}", i), "video", i));
+            input.push(cand(&format!("a{}", i), "audio", i));
+        }
+        let out = policy.order(input);
+        assert_eq!(ids(&out), ["v0", "a0", "v1", "v2", "a1", "v3", "a2", "a3"]);
+    }
+
+    #[test]
+    fn weighted_zero_weight_queue_goes_last() {
+        let policy = WeightedQueues { weights: HashMap::from([("paused".into(), 0)]) };
+        let out = policy.order(vec![cand("p", "paused", 0), cand("x", "other", 5)]);
+        assert_eq!(ids(&out), ["x", "p"]);
+    }
+
+    #[test]
+    fn reward_per_second_prefers_best_rate() {
+        let mut slow = cand("slow", "q", 0);
+        slow.reward_wei = Some(1_000);
+        slow.expected_duration = Some(Duration::from_secs(100));
+        let mut fast = cand("fast", "q", 1);
+        fast.reward_wei = Some(500);
+        fast.expected_duration = Some(Duration::from_secs(10));
+        let unrated = cand("unrated", "q", 0);
+        let out = RewardPerSecond.order(vec![unrated, slow, fast]);
+        assert_eq!(ids(&out), ["fast", "slow", "unrated"]);
+    }
+}
@@ -0,0 +1,87 @@
+//! Profitability gate: skip claim jobs whose reward does not cover the gas of
+//! claiming, heartbeating and completing them at current fees.
+use subnet_wcp_config::ProfitabilityConfig;
+
+use crate::Candidate;
+
+pub struct ProfitGate {
+    cfg: ProfitabilityConfig,
+}
+
+impl ProfitGate {
+    pub fn new(cfg: ProfitabilityConfig) -> Self {
+        Self { cfg }
+    }
+
+    /// Gas for the whole lifecycle: claim, one heartbeat per started
+    /// `heartbeat_interval` of `expected_duration` (one if unknown), complete.
+    pub fn lifecycle_gas(&self, c: &Candidate) -> u64 {
+        let interval = self.cfg.heartbeat_interval.as_secs().max(1);
+        let heartbeats = c.expected_duration.map(|d| d.as_secs().div_ceil(interval)).unwrap_or(1);
+        self.cfg.claim_gas + self.cfg.complete_gas + heartbeats * self.cfg.heartbeat_gas
+    }
+
+    /// Why `c` should be skipped at `fee_per_gas` wei, or `None` to claim it.
+    pub fn check(&self, c: &Candidate, fee_per_gas: u128) -> Option<String> {
+        let Some(reward) = c.reward_wei else {
+            return self.cfg.require_reward.then(|| "reward unknown and profitability.require_reward is set".to_string());
+        };
+        let gas = self.lifecycle_gas(c);
+        let cost = gas as u128 * fee_per_gas;
+        let required = cost.saturating_mul(100 + self.cfg.min_margin_percent as u128) / 100;
+        (reward < required).then(|| {
+            format!(
+                "reward {} wei < {} wei required (gas {} @ {} wei/gas, margin {}%)",
+                reward, required, gas, fee_per_gas, self.cfg.min_margin_percent
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn gate(margin: u32, require_reward: bool) -> ProfitGate {
+        ProfitGate::new(ProfitabilityConfig {
+            enabled: true,
+            claim_gas: 100,
+            heartbeat_gas: 10,
+            complete_gas: 200,
+            heartbeat_interval: Duration::from_secs(10),
+            min_margin_percent: margin,
+            require_reward,
+        })
+    }
+
+    fn cand(reward_wei: Option<u128>, expected_secs: Option<u64>) -> Candidate {
+        Candidate {
+            activity_id: "a".into(),
+            queue: "q".into(),
+            created_at_ms: 0,
+            expires_at_ms: None,
+            reward_wei,
+            expected_duration: expected_secs.map(Duration::from_secs),
+        }
+    }
+
+    #[test]
+    fn counts_heartbeats_over_expected_duration() {
+        assert_eq!(gate(0, false).lifecycle_gas(&cand(None, Some(25))), 100 + 200 + 3 * 10);
+        assert_eq!(gate(0, false).lifecycle_gas(&cand(None, None)), 100 + 200 + 10);
+    }
+
+    #[test]
+    fn skips_when_reward_below_cost_plus_margin() {
+        // 310 gas @ 2 wei = 620; +50% = 930.
+        assert!(gate(50, false).check(&cand(Some(929), None), 2).is_some());
+        assert!(gate(50, false).check(&cand(Some(930), None), 2).is_none());
+    }
+
+    #[test]
+    fn unknown_reward_passes_unless_required() {
+        assert!(gate(0, false).check(&cand(None, None), 1_000).is_none());
+        assert!(gate(0, true).check(&cand(None, None), 1_000).is_some());
+    }
+}
@@ -0,0 +1,215 @@
+//! The worker wallet's transaction signer, loaded per `[signer]`: a plaintext
+//! key (dev only), an encrypted JSON keystore, a permission-checked key file,
+//! or a remote web3signer-style service.
+use std::path::Path;
+
+use alloy::network::{EthereumWallet, TxSigner};
+use alloy::primitives::Address;
+use alloy::signers::local::PrivateKeySigner;
+use anyhow::{bail, Context, Result};
+use subnet_wcp_config::{ConfigProblem, EthereumConfig, SignerConfig, SignerKind, WcpConfig};
+
+pub mod local;
+pub mod remote;
+
+pub use remote::RemoteSigner;
+
+/// The wallet to hand to the provider, and the address it signs as.
+#[derive(Clone)]
+pub struct WorkerSigner {
+    pub address: Address,
+    pub wallet: EthereumWallet,
+}
+
+/// Load the configured signer. A non-empty `ethereum.wallet_address` must match it.
+pub async fn load(eth: &EthereumConfig, cfg: &SignerConfig) -> Result<WorkerSigner> {
+    let expected = match eth.wallet_address.trim() {
+        "" => None,
+        a => Some(a.parse::<Address>().context("parsing ethereum.wallet_address")?),
+    };
+    let wallet = match cfg.kind {
+        SignerKind::PrivateKey => {
+            tracing::warn!("Signing with the plaintext ethereum.wallet_private_key; use signer.kind = keystore, key_file or remote outside development");
+            EthereumWallet::from(parse_key(&eth.wallet_private_key).context("parsing ethereum.wallet_private_key")?)
+        }
+        SignerKind::KeyFile => EthereumWallet::from(read_key_file(required_path(cfg)?)?),
+        SignerKind::Keystore => {
+            let path = required_path(cfg)?.to_path_buf();
+            let password = keystore_password(cfg)?;
+            // scrypt takes a noticeable fraction of a second by design
+            let key = tokio::task::spawn_blocking(move || {
+                PrivateKeySigner::decrypt_keystore(&path, password).with_context(|| format!("decrypting keystore {}", path.display()))
+            })
+            .await??;
+            EthereumWallet::from(key)
+        }
+        SignerKind::Remote => EthereumWallet::from(RemoteSigner::connect(&cfg.url, expected, cfg.timeout).await?),
+    };
+    let address = wallet.default_signer().address();
+    if let Some(expected) = expected {
+        if expected != address {
+            bail!("{} signer is for {}, but ethereum.wallet_address is {}", cfg.kind.as_str(), address, expected);
+        }
+    }
+    Ok(WorkerSigner { address, wallet })
+}
+
+/// [`load`] as one more config check: a signer that fails to load, or does not
+/// match `ethereum.wallet_address`, is added to `problems`. Skipped when the
+/// signer settings already have problems, which it would only repeat.
+pub async fn check(cfg: &WcpConfig, problems: &mut Vec<ConfigProblem>) -> Option<WorkerSigner> {
+    if problems.iter().any(|p| p.key.starts_with("signer") || p.key.starts_with("ethereum.wallet_")) {
+        return None;
+    }
+    match load(&cfg.ethereum, &cfg.signer).await {
+        Ok(signer) => Some(signer),
+        Err(e) => {
+            problems.push(ConfigProblem::new("signer", format!("{:#}", e)));
+            None
+        }
+    }
+}
+
+/// A hex private key (`0x` optional) from a file only its owner can read.
+pub fn read_key_file(path: &Path) -> Result<PrivateKeySigner> {
+    if !path.exists() {
+        bail!("key file {} does not exist; create it with `(umask 077 && echo 0x<hex_private_key> > {})`", path.display(), path.display());
+    }
+    check_private(path)?;
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    parse_key(&text).with_context(|| format!("parsing key in {}", path.display()))
+}
+
+fn parse_key(hex: &str) -> Result<PrivateKeySigner> {
+    // The parse error never echoes the key
+    Ok(hex.trim().parse::<PrivateKeySigner>()?)
+}
+
+fn required_path(cfg: &SignerConfig) -> Result<&Path> {
+    match &cfg.path {
+        Some(p) => Ok(p),
+        None => bail!("signer.path is required for signer.kind = {}", cfg.kind.as_str()),
+    }
+}
+
+/// `signer.password_file` if set (trailing newlines ignored), else the `signer.password_env` variable.
+fn keystore_password(cfg: &SignerConfig) -> Result<String> {
+    if let Some(path) = &cfg.password_file {
+        check_private(path)?;
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        return Ok(text.trim_end_matches(['\r', '\n']).to_string());
+    }
+    std::env::var(&cfg.password_env)
+        .with_context(|| format!("keystore password: set signer.password_file or the {} environment variable", cfg.password_env))
+}
+
+/// Refuse secrets that other local users can read or write.
+fn check_private(path: &Path) -> Result<()> {
+    let meta = std::fs::metadata(path).with_context(|| format!("reading {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = meta.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            bail!("{} is accessible by group or others (mode {:o}); chmod 600 it", path.display(), mode);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = meta;
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use alloy::consensus::{SignableTransaction, TxLegacy};
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    fn eth(wallet_address: &str) -> EthereumConfig {
+        EthereumConfig {
+            rpc_url: String::new(),
+            ws_url: None,
+            wallet_private_key: String::new(),
+            wallet_address: wallet_address.into(),
+            workflow_engine_address: String::new(),
+            task_queue_address: String::new(),
+            multicall3_address: String::new(),
+            subnet_control_plane_address: String::new(),
+        }
+    }
+
+    fn write_secret(dir: &Path, name: &str, contents: &str, mode: u32) -> std::path::PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+
+    /// Sign a legacy tx through the loaded wallet and recover its sender.
+    async fn tx_sender(signer: &WorkerSigner) -> Address {
+        let mut tx = TxLegacy { chain_id: Some(1), nonce: 7, gas_limit: 21_000, ..Default::default() };
+        let sig = signer.wallet.default_signer().sign_transaction(&mut tx).await.unwrap();
+        sig.recover_address_from_prehash(&tx.signature_hash()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn key_file_needs_owner_only_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = PrivateKeySigner::random();
+        let cfg = SignerConfig { kind: SignerKind::KeyFile, path: Some(write_secret(dir.path(), "key", &format!("{}\n", key.to_bytes()), 0o644)), ..Default::default() };
+        let err = load(&eth(""), &cfg).await.err().unwrap();
+        assert!(err.to_string().contains("chmod 600"), "{err}");
+
+        std::fs::set_permissions(cfg.path.as_ref().unwrap(), std::fs::Permissions::from_mode(0o600)).unwrap();
+        let signer = load(&eth(""), &cfg).await.unwrap();
+        assert_eq!(signer.address, key.address());
+        assert_eq!(tx_sender(&signer).await, key.address());
+    }
+
+    #[tokio::test]
+    async fn keystore_with_password_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (key, uuid) = PrivateKeySigner::new_keystore(dir.path(), &mut rand::thread_rng(), "hunter2", None).unwrap();
+        let cfg = SignerConfig {
+            kind: SignerKind::Keystore,
+            path: Some(dir.path().join(uuid)),
+            password_file: Some(write_secret(dir.path(), "password", "hunter2\n", 0o600)),
+            ..Default::default()
+        };
+        assert_eq!(load(&eth(""), &cfg).await.unwrap().address, key.address());
+
+        write_secret(dir.path(), "password", "wrong", 0o600);
+        assert!(load(&eth(""), &cfg).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn remote_signer_round_trip() {
+        let (a, b) = (PrivateKeySigner::random(), PrivateKeySigner::random());
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(local::DevSigner::new(vec![a.clone(), b.clone()]).serve(listener));
+        let cfg = SignerConfig { kind: SignerKind::Remote, url, timeout: Duration::from_secs(5), ..Default::default() };
+
+        // Two keys: the wallet address picks one
+        assert!(load(&eth(""), &cfg).await.is_err());
+        let signer = load(&eth(&b.address().to_string().to_lowercase()), &cfg).await.unwrap();
+        assert_eq!(signer.address, b.address());
+        assert_eq!(tx_sender(&signer).await, b.address());
+
+        let other = PrivateKeySigner::random().address().to_string();
+        assert!(load(&eth(&other), &cfg).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn wallet_address_must_match_signer() {
+        let key = PrivateKeySigner::random();
+        let mut eth = eth(&PrivateKeySigner::random().address().to_string());
+        eth.wallet_private_key = key.to_bytes().to_string();
+        let err = load(&eth, &SignerConfig::default()).await.err().unwrap();
+        assert!(err.to_string().contains("ethereum.wallet_address"), "{err}");
+
+        eth.wallet_address.clear();
+        assert_eq!(load(&eth, &SignerConfig::default()).await.unwrap().address, key.address());
+    }
+}
@@ -0,0 +1,62 @@
+//! In-process stand-in for a remote signer, for local development and tests.
+//!
+//! Serves the endpoints [`RemoteSigner`](crate::RemoteSigner) calls from keys
+//! held in memory, signing `keccak256(data)` as web3signer does.
+use std::net::TcpListener;
+use std::sync::Arc;
+
+use alloy::hex;
+use alloy::primitives::keccak256;
+use alloy::signers::local::PrivateKeySigner;
+use alloy::signers::SignerSync;
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+
+#[derive(Clone)]
+pub struct DevSigner {
+    keys: Arc<Vec<PrivateKeySigner>>,
+}
+
+#[derive(Deserialize)]
+struct SignRequest {
+    data: String,
+}
+
+impl DevSigner {
+    pub fn new(keys: Vec<PrivateKeySigner>) -> Self {
+        Self { keys: Arc::new(keys) }
+    }
+
+    /// Serve until the listener fails.
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        listener.set_nonblocking(true)?;
+        axum::Server::from_tcp(listener)?.serve(self.router().into_make_service()).await?;
+        Ok(())
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/api/v1/eth1/publicKeys", get(public_keys))
+            .route("/api/v1/eth1/sign/:public_key", post(sign))
+            .with_state(self.clone())
+    }
+
+    fn key(&self, public_key: &str) -> Option<&PrivateKeySigner> {
+        self.keys.iter().find(|k| k.public_key().to_string().eq_ignore_ascii_case(public_key))
+    }
+}
+
+async fn public_keys(State(dev): State<DevSigner>) -> Json<Vec<String>> {
+    Json(dev.keys.iter().map(|k| k.public_key().to_string()).collect())
+}
+
+async fn sign(State(dev): State<DevSigner>, Path(public_key): Path<String>, Json(req): Json<SignRequest>) -> Result<String, (StatusCode, String)> {
+    let key = dev.key(&public_key).ok_or((StatusCode::NOT_FOUND, format!("no key {public_key}")))?;
+    let data = hex::decode(&req.data).map_err(|_| (StatusCode::BAD_REQUEST, "data must be hex".to_string()))?;
+    let sig = key.sign_hash_sync(&keccak256(data)).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(hex::encode_prefixed(sig.as_bytes()))
+}
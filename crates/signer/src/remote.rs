@@ -0,0 +1,162 @@
+//! Client for a remote signer exposing web3signer's eth1 endpoints:
+//!
+//! - `GET {url}/api/v1/eth1/publicKeys`: JSON array of hex secp256k1 public keys
+//! - `POST {url}/api/v1/eth1/sign/{public key}` with `{"data": "0x…"}`: the hex
+//!   65-byte signature as the body
+//!
+//! The signer keccak256-hashes `data` before signing, so `data` is the unsigned
+//! transaction's signing payload (its RLP/typed encoding), not the hash. Each
+//! signature is recovered from that hash and checked against the key's address
+//! before use, so a misrouted or faulty signer cannot get a foreign tx broadcast.
+use std::time::Duration;
+
+use alloy::consensus::SignableTransaction;
+use alloy::hex;
+use alloy::network::TxSigner;
+use alloy::primitives::{keccak256, Address, Signature};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: String,
+    public_key: String,
+    address: Address,
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    data: String,
+}
+
+impl RemoteSigner {
+    /// Pick the key for `address`, or the only key the signer holds when `None`.
+    pub async fn connect(url: &str, address: Option<Address>, timeout: Duration) -> Result<Self> {
+        if url.trim().is_empty() {
+            bail!("signer.url is required for signer.kind = remote");
+        }
+        let url = url.trim_end_matches('/').to_string();
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+        let keys: Vec<String> = client
+            .get(format!("{url}/api/v1/eth1/publicKeys"))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("listing keys on remote signer {url}"))?
+            .json()
+            .await?;
+        let mut held = Vec::new();
+        for key in keys {
+            held.push((key_address(&key)?, key));
+        }
+        let (address, public_key) = match address {
+            Some(want) => match held.into_iter().find(|(a, _)| *a == want) {
+                Some(found) => found,
+                None => bail!("remote signer {url} holds no key for {want}"),
+            },
+            None if held.len() == 1 => held.remove(0),
+            None => bail!("remote signer {url} holds {} keys; set ethereum.wallet_address to pick one", held.len()),
+        };
+        tracing::info!(%url, %address, "Connected to remote signer");
+        Ok(Self { client, url, public_key, address })
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Sign `keccak256(payload)`; the hashing happens on the signer.
+    pub async fn sign_payload(&self, payload: &[u8]) -> Result<Signature> {
+        let body = self
+            .client
+            .post(format!("{}/api/v1/eth1/sign/{}", self.url, self.public_key))
+            .json(&SignRequest { data: hex::encode_prefixed(payload) })
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("signing on remote signer {}", self.url))?
+            .text()
+            .await?;
+        let bytes = hex::decode(body.trim().trim_matches('"')).context("remote signer returned a non-hex signature")?;
+        let sig = Signature::from_raw(&bytes).context("remote signer returned a malformed signature")?;
+        let signer = sig.recover_address_from_prehash(&keccak256(payload))?;
+        if signer != self.address {
+            bail!("remote signer {} signed as {}, expected {}", self.url, signer, self.address);
+        }
+        Ok(sig)
+    }
+}
+
+#[async_trait]
+impl TxSigner<Signature> for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(&self, tx: &mut dyn SignableTransaction<Signature>) -> alloy::signers::Result<Signature> {
+        self.sign_payload(&tx.encoded_for_signing()).await.map_err(|e| alloy::signers::Error::other(e.into_boxed_dyn_error()))
+    }
+}
+
+/// Address of an uncompressed public key, with or without the `04` prefix.
+fn key_address(key: &str) -> Result<Address> {
+    let bytes = hex::decode(key).with_context(|| format!("remote signer returned a malformed public key {key}"))?;
+    match bytes.len() {
+        64 => Ok(Address::from_raw_public_key(&bytes)),
+        65 if bytes[0] == 4 => Ok(Address::from_raw_public_key(&bytes[1..])),
+        _ => bail!("remote signer returned a malformed public key {key}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local::DevSigner;
+    use alloy::consensus::TxEip1559;
+    use alloy::primitives::U256;
+    use alloy::signers::local::PrivateKeySigner;
+
+    async fn dev_signer(keys: Vec<PrivateKeySigner>) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(DevSigner::new(keys).serve(listener));
+        url
+    }
+
+    #[tokio::test]
+    async fn sign_endpoint_signs_the_keccak_of_data() {
+        let key = PrivateKeySigner::random();
+        let url = dev_signer(vec![key.clone()]).await;
+        let data = b"not a hash, any length";
+        let body = reqwest::Client::new()
+            .post(format!("{url}/api/v1/eth1/sign/{}", key.public_key()))
+            .json(&SignRequest { data: hex::encode_prefixed(data) })
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let sig = Signature::from_raw(&hex::decode(body).unwrap()).unwrap();
+        assert_eq!(sig.recover_address_from_prehash(&keccak256(data)).unwrap(), key.address());
+    }
+
+    #[tokio::test]
+    async fn transactions_are_signed_over_their_signature_hash() {
+        let key = PrivateKeySigner::random();
+        let signer = RemoteSigner::connect(&dev_signer(vec![key.clone()]).await, None, Duration::from_secs(5)).await.unwrap();
+        let mut tx = TxEip1559 { chain_id: 1, nonce: 3, gas_limit: 21_000, max_fee_per_gas: 2, value: U256::from(5), ..Default::default() };
+        let sig = signer.sign_transaction(&mut tx).await.unwrap();
+        assert_eq!(keccak256(tx.encoded_for_signing()), tx.signature_hash());
+        assert_eq!(sig.recover_address_from_prehash(&tx.signature_hash()).unwrap(), key.address());
+    }
+
+    #[tokio::test]
+    async fn several_keys_need_an_address() {
+        let url = dev_signer(vec![PrivateKeySigner::random(), PrivateKeySigner::random()]).await;
+        let err = RemoteSigner::connect(&url, None, Duration::from_secs(5)).await.err().unwrap();
+        assert!(err.to_string().contains("holds 2 keys"), "{err}");
+    }
+}
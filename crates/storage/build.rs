@@ -0,0 +1,9 @@
+fn main() {
+    let proto = "../../proto/storage/v1/storage.proto";
+    tonic_build::configure()
+        .build_server(true)
+        .build_client(true)
+        .compile(&[proto], &["../../proto"])
+        .expect("failed to compile proto");
+    println!("cargo:rerun-if-changed={}", proto);
+}
@@ -1,17 +1,271 @@
-//! Subnet API storage broker skeleton
-use anyhow::Result;
+//! Subnet API storage broker: presigned download/upload URLs over gRPC.
+pub mod storage_v1 {
+    tonic::include_proto!("storage.v1");
+}
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use subnet_wcp_metrics as metrics;
+use tonic::transport::Channel;
+use storage_v1::storage_client::StorageClient;
+use storage_v1::{PresignDownloadRequest, PresignUploadRequest, StatObjectRequest};
+
+pub use storage_v1::{ObjectInfo, PresignedUrl};
 
+pub mod local;
+
+/// Presigned URLs by `(method, object key, activity id)`; shared by clones of
+/// the broker. A URL is only ever reused for the activity it was issued for.
+type UrlCache = Arc<Mutex<HashMap<(&'static str, String, String), PresignedUrl>>>;
+
+#[derive(Clone)]
 pub struct StorageBroker {
     pub endpoint: String,
+    client: StorageClient<Channel>,
+    ttl: Duration,
+    cache: UrlCache,
 }
 
 impl StorageBroker {
-    pub fn new(endpoint: String) -> Self { Self { endpoint } }
+    /// Connects lazily, so an unreachable subnet-api only fails the calls that need it.
+    pub fn new(endpoint: String, ttl: Duration) -> Result<Self> {
+        let channel = Channel::from_shared(endpoint.clone())?.connect_lazy();
+        Ok(Self { endpoint, client: StorageClient::new(channel), ttl, cache: UrlCache::default() })
+    }
 
-    pub async fn get_presigned_download(&self, _key: &str) -> Result<String> {
-        // TODO: gRPC call to subnet-api
-        Ok("https://example.com/presigned".to_string())
+    pub async fn get_presigned_download(&self, key: &str, activity_id: &str) -> Result<PresignedUrl> {
+        if let Some(url) = self.cached("GET", key, activity_id) {
+            return Ok(url);
+        }
+        let req = PresignDownloadRequest {
+            key: key.to_string(),
+            activity_id: activity_id.to_string(),
+            ttl_seconds: self.ttl.as_secs() as u32,
+        };
+        let url = counted("GET", self.client.clone().presign_download(req).await)?;
+        self.remember("GET", key, activity_id, &url);
+        Ok(url)
     }
+
+    /// PUT URL for `{upload_prefix}/{name}`; `name` may not escape the prefix.
+    pub async fn get_presigned_upload(&self, upload_prefix: &str, name: &str, activity_id: &str) -> Result<PresignedUrl> {
+        if !is_relative_name(name) {
+            bail!("upload name {:?} escapes upload prefix", name);
+        }
+        let prefix = upload_prefix.trim_end_matches('/');
+        let key = format!("{}/{}", prefix, name);
+        if let Some(url) = self.cached("PUT", &key, activity_id) {
+            return Ok(url);
+        }
+        let req = PresignUploadRequest {
+            prefix: prefix.to_string(),
+            name: name.to_string(),
+            activity_id: activity_id.to_string(),
+            ttl_seconds: self.ttl.as_secs() as u32,
+            content_type: String::new(),
+        };
+        let url = counted("PUT", self.client.clone().presign_upload(req).await)?;
+        self.remember("PUT", &key, activity_id, &url);
+        Ok(url)
+    }
+
+    pub async fn stat_object(&self, key: &str, activity_id: &str) -> Result<ObjectInfo> {
+        let req = StatObjectRequest { key: key.to_string(), activity_id: activity_id.to_string() };
+        Ok(self.client.clone().stat_object(req).await?.into_inner())
+    }
+
+    /// Why `result_ref` is not an acceptable result for an assignment under
+    /// `upload_prefix`, or `None` if it is. `size`/`sha256` are what the WEP
+    /// reported and are only compared when given. Errors mean the check could
+    /// not be made, not that the result is bad.
+    pub async fn verify_result(
+        &self,
+        activity_id: &str,
+        upload_prefix: &str,
+        result_ref: &str,
+        size: Option<u64>,
+        sha256: Option<&str>,
+    ) -> Result<Option<String>> {
+        let Some(key) = object_key(result_ref) else {
+            return Ok(Some(format!("result_ref {:?} is not an object key", result_ref)));
+        };
+        let prefix = upload_prefix.trim_end_matches('/');
+        if !key.starts_with(&format!("{}/", prefix)) {
+            return Ok(Some(format!("result_ref {} is outside upload prefix {}", key, prefix)));
+        }
+        let info = self.stat_object(&key, activity_id).await?;
+        if !info.exists {
+            return Ok(Some(format!("result object {} does not exist", key)));
+        }
+        if let Some(size) = size.filter(|s| *s != info.size) {
+            return Ok(Some(format!("result object {} is {} bytes, WEP reported {}", key, info.size, size)));
+        }
+        if let Some(sha256) = sha256.filter(|h| !info.sha256.is_empty() && !h.eq_ignore_ascii_case(&info.sha256)) {
+            return Ok(Some(format!("result object {} has sha256 {}, WEP reported {}", key, info.sha256, sha256)));
+        }
+        Ok(None)
+    }
+
+    /// Drop the URLs issued for `activity_id`, once it is finished.
+    pub fn forget_activity(&self, activity_id: &str) {
+        self.cache.lock().unwrap().retain(|(_, _, id), _| id != activity_id);
+    }
+
+    /// A cached URL with at least a quarter of the TTL left, so callers never
+    /// get one that is about to lapse.
+    fn cached(&self, method: &'static str, key: &str, activity_id: &str) -> Option<PresignedUrl> {
+        let min_left = (self.ttl.as_secs() / 4) as i64;
+        let cache = self.cache.lock().unwrap();
+        let url = cache.get(&(method, key.to_string(), activity_id.to_string())).filter(|u| u.expires_at_unix - now_unix() > min_left).cloned();
+        if url.is_some() {
+            metrics::PRESIGNS.with_label_values(&[method, "cache"]).inc();
+        }
+        url
+    }
+
+    fn remember(&self, method: &'static str, key: &str, activity_id: &str, url: &PresignedUrl) {
+        let now = now_unix();
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, u| u.expires_at_unix > now);
+        cache.insert((method, key.to_string(), activity_id.to_string()), url.clone());
+    }
+}
+
+/// Unwrap a broker presign response, counting it by outcome.
+fn counted(method: &str, resp: Result<tonic::Response<PresignedUrl>, tonic::Status>) -> Result<PresignedUrl> {
+    let source = if resp.is_ok() { "broker" } else { "error" };
+    metrics::PRESIGNS.with_label_values(&[method, source]).inc();
+    Ok(resp?.into_inner())
+}
+
+fn now_unix() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
+
+/// A non-empty relative object name with no `.`/`..` segments.
+pub fn is_relative_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('/') && name.split('/').all(|seg| !seg.is_empty() && seg != "." && seg != "..")
 }
 
+/// Object key for a bare key or a `scheme://bucket/key` ref; `None` if it
+/// is not a relative key.
+pub fn object_key(reference: &str) -> Option<String> {
+    let key = match reference.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?.1,
+        None => reference,
+    };
+    is_relative_name(key).then(|| key.to_string())
+}
+
+/// Whether an input `ref` is an object reference the broker must presign
+/// (as opposed to an already-usable URL or an empty/inline input).
+pub fn needs_presign(reference: &str) -> bool {
+    !reference.is_empty() && !reference.starts_with("http://") && !reference.starts_with("https://")
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use local::LocalStorage;
+    use sha2::Digest;
+    use storage_v1::storage_server::StorageServer;
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    /// A broker talking to a [`LocalStorage`] over gRPC on an ephemeral port.
+    async fn broker(root: &std::path::Path, ttl: Duration) -> StorageBroker {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let storage = LocalStorage::new(root.to_path_buf(), "http://127.0.0.1:1".into(), b"secret".to_vec());
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(StorageServer::new(storage))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        StorageBroker::new(format!("http://{}", addr), ttl).unwrap()
+    }
+
+    #[tokio::test]
+    async fn verify_result_checks_prefix_existence_size_and_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("out/a1")).unwrap();
+        std::fs::write(dir.path().join("out/a1/result.bin"), b"hello").unwrap();
+        let sha = hex::encode(sha2::Sha256::digest(b"hello"));
+        let b = broker(dir.path(), Duration::from_secs(60)).await;
+        let verify = |result_ref: &'static str, size, sha256: Option<&'static str>| {
+            let b = b.clone();
+            async move { b.verify_result("a1", "out/a1/", result_ref, size, sha256).await.unwrap() }
+        };
+
+        assert_eq!(verify("out/a1/result.bin", Some(5), None).await, None);
+        assert_eq!(b.verify_result("a1", "out/a1", "s3://bucket/out/a1/result.bin", Some(5), Some(&sha.to_uppercase())).await.unwrap(), None);
+        for (result_ref, size, sha256, reason) in [
+            ("out/a2/result.bin", None, None, "outside upload prefix"),
+            ("out/a1-x/result.bin", None, None, "outside upload prefix"),
+            ("../out/a1/result.bin", None, None, "not an object key"),
+            ("out/a1/missing.bin", None, None, "does not exist"),
+            ("out/a1/result.bin", Some(6), None, "is 5 bytes, WEP reported 6"),
+            ("out/a1/result.bin", Some(5), Some("00ff"), "has sha256"),
+        ] {
+            let got = verify(result_ref, size, sha256).await.unwrap_or_default();
+            assert!(got.contains(reason), "{result_ref}: {got:?}");
+        }
+    }
+
+    #[test]
+    fn relative_names_reject_escapes_and_absolute_paths() {
+        for (name, ok) in [
+            ("result.bin", true),
+            ("a/b/c.txt", true),
+            ("a/.hidden", true),
+            ("", false),
+            ("/etc/passwd", false),
+            ("..", false),
+            ("a/../../b", false),
+            ("./a", false),
+            ("a//b", false),
+            ("a/", false),
+        ] {
+            assert_eq!(is_relative_name(name), ok, "{name:?}");
+        }
+        assert_eq!(object_key("s3://bucket/a/b"), Some("a/b".into()));
+        assert_eq!(object_key("s3://bucket/../b"), None);
+        assert_eq!(object_key("/abs"), None);
+    }
+
+    #[tokio::test]
+    async fn cached_urls_are_reused_only_with_a_quarter_of_the_ttl_left() {
+        let b = StorageBroker::new("http://127.0.0.1:1".into(), Duration::from_secs(400)).unwrap();
+        let url = |key: &str, left: i64| PresignedUrl { url: format!("http://x/{key}"), key: key.into(), method: "GET".into(), expires_at_unix: now_unix() + left };
+
+        b.remember("GET", "fresh", "a1", &url("fresh", 300));
+        b.remember("GET", "stale", "a1", &url("stale", 100));
+        b.remember("GET", "gone", "a1", &url("gone", -1));
+        assert_eq!(b.cached("GET", "fresh", "a1").map(|u| u.key), Some("fresh".into()));
+        assert!(b.cached("PUT", "fresh", "a1").is_none());
+        // ttl/4 = 100s: a URL with only that much left is presigned afresh.
+        assert!(b.cached("GET", "stale", "a1").is_none());
+        assert!(b.cached("GET", "gone", "a1").is_none());
+        // Expired entries are pruned on the next insert.
+        b.remember("GET", "other", "a1", &url("other", 300));
+        assert!(!b.cache.lock().unwrap().contains_key(&("GET", "gone".to_string(), "a1".to_string())));
+    }
+
+    #[tokio::test]
+    async fn cached_urls_belong_to_one_activity() {
+        let b = StorageBroker::new("http://127.0.0.1:1".into(), Duration::from_secs(400)).unwrap();
+        let url = PresignedUrl { url: "http://x/in".into(), key: "in".into(), method: "GET".into(), expires_at_unix: now_unix() + 300 };
+
+        b.remember("GET", "in", "a1", &url);
+        b.remember("PUT", "out/a1/result.json", "a1", &url);
+        b.remember("GET", "in", "a2", &url);
+        assert!(b.cached("GET", "in", "a1").is_some());
+        assert!(b.cached("GET", "in", "a3").is_none());
+
+        b.forget_activity("a1");
+        assert!(b.cached("GET", "in", "a1").is_none());
+        assert!(b.cached("PUT", "out/a1/result.json", "a1").is_none());
+        assert!(b.cached("GET", "in", "a2").is_some());
+    }
+}
@@ -0,0 +1,155 @@
+//! Filesystem stand-in for the subnet-api storage service, for local development.
+//!
+//! Objects live at `{root}/{key}`. Presigned URLs point at an embedded HTTP
+//! server: `{base_url}/objects/{key}?expires={unix}&sig={hex}`, where `sig` is
+//! HMAC-SHA256 over `"{METHOD}\n{key}\n{expires}"` with the server secret.
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+
+use crate::storage_v1::storage_server::{Storage, StorageServer};
+use crate::storage_v1::{PresignDownloadRequest, PresignUploadRequest, StatObjectRequest};
+use crate::{is_relative_name, object_key, ObjectInfo, PresignedUrl};
+
+const DEFAULT_TTL_SECS: u32 = 3600;
+
+#[derive(Clone)]
+pub struct LocalStorage {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    root: PathBuf,
+    base_url: String,
+    secret: Vec<u8>,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf, base_url: String, secret: Vec<u8>) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Self { inner: Arc::new(Inner { root, base_url, secret }) }
+    }
+
+    /// Serve the storage gRPC service and the object HTTP server until either fails.
+    pub async fn serve(self, grpc_addr: SocketAddr, http_addr: SocketAddr) -> Result<()> {
+        info!(%grpc_addr, %http_addr, root = %self.inner.root.display(), "local storage listening");
+        let http = axum::Server::bind(&http_addr).serve(self.router().into_make_service());
+        let grpc = tonic::transport::Server::builder().add_service(StorageServer::new(self)).serve(grpc_addr);
+        tokio::try_join!(async { http.await.map_err(anyhow::Error::from) }, async { grpc.await.map_err(anyhow::Error::from) })?;
+        Ok(())
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new().route("/objects/*key", get(get_object).put(put_object)).with_state(self.clone())
+    }
+
+    pub fn presign(&self, method: &str, key: &str, ttl_seconds: u32) -> PresignedUrl {
+        let ttl = if ttl_seconds == 0 { DEFAULT_TTL_SECS } else { ttl_seconds };
+        let expires = now_unix() + ttl as i64;
+        PresignedUrl {
+            url: format!("{}/objects/{}?expires={}&sig={}", self.inner.base_url, key, expires, self.sign(method, key, expires)),
+            key: key.to_string(),
+            method: method.to_string(),
+            expires_at_unix: expires,
+        }
+    }
+
+    fn mac(&self, method: &str, key: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.inner.secret).expect("hmac accepts any key length");
+        mac.update(format!("{}\n{}\n{}", method, key, expires).as_bytes());
+        mac
+    }
+
+    fn sign(&self, method: &str, key: &str, expires: i64) -> String {
+        hex::encode(self.mac(method, key, expires).finalize().into_bytes())
+    }
+
+    /// Valid, unexpired signature for `method` on `key`.
+    fn verify(&self, method: &str, key: &str, q: &SignedQuery) -> bool {
+        let Ok(sig) = hex::decode(&q.sig) else { return false };
+        q.expires >= now_unix() && self.mac(method, key, q.expires).verify_slice(&sig).is_ok()
+    }
+}
+
+#[tonic::async_trait]
+impl Storage for LocalStorage {
+    async fn presign_download(&self, request: Request<PresignDownloadRequest>) -> Result<Response<PresignedUrl>, Status> {
+        let req = request.into_inner();
+        let key = object_key(&req.key).ok_or_else(|| Status::invalid_argument(format!("invalid object key {:?}", req.key)))?;
+        info!(activity_id = %req.activity_id, %key, "presigned download");
+        Ok(Response::new(self.presign("GET", &key, req.ttl_seconds)))
+    }
+
+    async fn presign_upload(&self, request: Request<PresignUploadRequest>) -> Result<Response<PresignedUrl>, Status> {
+        let req = request.into_inner();
+        if !is_relative_name(&req.prefix) || !is_relative_name(&req.name) {
+            return Err(Status::invalid_argument(format!("invalid upload target {:?}/{:?}", req.prefix, req.name)));
+        }
+        let key = format!("{}/{}", req.prefix, req.name);
+        info!(activity_id = %req.activity_id, %key, "presigned upload");
+        Ok(Response::new(self.presign("PUT", &key, req.ttl_seconds)))
+    }
+
+    async fn stat_object(&self, request: Request<StatObjectRequest>) -> Result<Response<ObjectInfo>, Status> {
+        let req = request.into_inner();
+        let key = object_key(&req.key).ok_or_else(|| Status::invalid_argument(format!("invalid object key {:?}", req.key)))?;
+        let info = match tokio::fs::read(self.inner.root.join(&key)).await {
+            Ok(bytes) => ObjectInfo { key, exists: true, size: bytes.len() as u64, sha256: hex::encode(Sha256::digest(&bytes)) },
+            Err(_) => ObjectInfo { key, exists: false, size: 0, sha256: String::new() },
+        };
+        Ok(Response::new(info))
+    }
+}
+
+#[derive(Deserialize)]
+struct SignedQuery {
+    expires: i64,
+    sig: String,
+}
+
+async fn get_object(State(s): State<LocalStorage>, Path(key): Path<String>, Query(q): Query<SignedQuery>) -> Result<Vec<u8>, StatusCode> {
+    if !is_relative_name(&key) || !s.verify("GET", &key, &q) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    tokio::fs::read(s.inner.root.join(&key)).await.map_err(|_| StatusCode::NOT_FOUND)
+}
+
+async fn put_object(State(s): State<LocalStorage>, Path(key): Path<String>, Query(q): Query<SignedQuery>, body: Bytes) -> StatusCode {
+    if !is_relative_name(&key) || !s.verify("PUT", &key, &q) {
+        return StatusCode::FORBIDDEN;
+    }
+    let path = s.inner.root.join(&key);
+    let write = async {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(&path, &body).await
+    };
+    match write.await {
+        Ok(()) => {
+            info!(%key, bytes = body.len(), "stored object");
+            StatusCode::OK
+        }
+        Err(e) => {
+            warn!(%key, error = %e, "failed to store object");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+fn now_unix() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
@@ -0,0 +1,111 @@
+//! In-process stand-in for an OTLP collector, for local development and tests.
+//!
+//! Accepts OTLP/HTTP protobuf trace exports on `POST /v1/traces`, logs each
+//! span and keeps them for [`DevCollector::spans`].
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::post;
+use axum::Router;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::common::v1::any_value::Value;
+use opentelemetry_proto::tonic::common::v1::{AnyValue, KeyValue};
+use prost::Message;
+use serde::Serialize;
+
+/// One received span, ids as lowercase hex.
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectedSpan {
+    pub service: String,
+    pub trace_id: String,
+    pub span_id: String,
+    /// Empty for a root span.
+    pub parent_span_id: String,
+    pub name: String,
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Default)]
+pub struct DevCollector {
+    spans: Arc<Mutex<Vec<CollectedSpan>>>,
+}
+
+impl DevCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve until the listener fails.
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        listener.set_nonblocking(true)?;
+        axum::Server::from_tcp(listener)?.serve(self.router().into_make_service()).await?;
+        Ok(())
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new().route("/v1/traces", post(traces)).with_state(self.clone())
+    }
+
+    /// Every span received so far, in arrival order.
+    pub fn spans(&self) -> Vec<CollectedSpan> {
+        self.spans.lock().expect("collector lock").clone()
+    }
+}
+
+async fn traces(State(dev): State<DevCollector>, body: Bytes) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let req = ExportTraceServiceRequest::decode(body).map_err(|e| (StatusCode::BAD_REQUEST, format!("not an OTLP trace export: {e}")))?;
+    let mut received = Vec::new();
+    for resource_spans in req.resource_spans {
+        let service = resource_spans
+            .resource
+            .iter()
+            .flat_map(|r| &r.attributes)
+            .find(|kv| kv.key == "service.name")
+            .map(attribute_value)
+            .unwrap_or_default();
+        for span in resource_spans.scope_spans.into_iter().flat_map(|s| s.spans) {
+            let span = CollectedSpan {
+                service: service.clone(),
+                trace_id: hex(&span.trace_id),
+                span_id: hex(&span.span_id),
+                parent_span_id: hex(&span.parent_span_id),
+                name: span.name,
+                attributes: span.attributes.iter().map(|kv| (kv.key.clone(), attribute_value(kv))).collect(),
+            };
+            tracing::info!(
+                service = %span.service,
+                trace_id = %span.trace_id,
+                span_id = %span.span_id,
+                parent_span_id = %span.parent_span_id,
+                name = %span.name,
+                attributes = ?span.attributes,
+                "span"
+            );
+            received.push(span);
+        }
+    }
+    dev.spans.lock().expect("collector lock").extend(received);
+    // An empty ExportTraceServiceResponse encodes to no bytes.
+    Ok(([(header::CONTENT_TYPE, "application/x-protobuf")], Vec::new()))
+}
+
+fn attribute_value(kv: &KeyValue) -> String {
+    match kv.value.as_ref().and_then(|v: &AnyValue| v.value.as_ref()) {
+        Some(Value::StringValue(s)) => s.clone(),
+        Some(Value::BoolValue(b)) => b.to_string(),
+        Some(Value::IntValue(i)) => i.to_string(),
+        Some(Value::DoubleValue(d)) => d.to_string(),
+        Some(other) => format!("{other:?}"),
+        None => String::new(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
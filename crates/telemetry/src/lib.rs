@@ -0,0 +1,196 @@
+//! Log output, OpenTelemetry export and the per-activity span every component
+//! logs under.
+//!
+//! An activity is handled by one component at a time (Poller, Scheduler,
+//! Broadcaster, Assigner, Reconciler), so its lifecycle is a sequence of
+//! `activity` spans sharing `activity_id`, each tagged with its `stage`.
+//! Filtering JSON logs on `activity_id` follows it end to end. Resuming the
+//! workflow afterwards is per instance, under a `workflow` span.
+//!
+//! With `[telemetry].otlp_endpoint` set, spans are also exported over
+//! OTLP/HTTP. Every span of an activity is parented to a root derived from
+//! its `activity_id` (never exported itself), so stages recorded by different
+//! components, and by the WEP through [`trace_context`], land in one trace.
+pub mod collector;
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, TracerProvider};
+use opentelemetry::Context;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use sha2::{Digest, Sha256};
+use subnet_wcp_config::{LogFormat, LoggingConfig, TelemetryConfig};
+use tracing::field::Empty;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// `[telemetry].sample_ratio`, set once export is on.
+static SAMPLE_RATIO: OnceLock<f64> = OnceLock::new();
+
+/// Flushes exported spans when dropped.
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("flushing trace export failed: {e}");
+            }
+        }
+    }
+}
+
+/// Install the global subscriber; `RUST_LOG` overrides `logging.level`.
+pub fn init(logging: &LoggingConfig, telemetry: &TelemetryConfig) -> Result<Telemetry> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&logging.level));
+    let provider = match telemetry.otlp_endpoint.trim() {
+        "" => None,
+        _ => Some(tracer_provider(telemetry)?),
+    };
+    let otel = provider.as_ref().map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer("subnet-wcp")));
+    let registry = tracing_subscriber::registry().with(filter).with(otel);
+    match logging.format {
+        LogFormat::Text => registry.with(fmt::layer()).init(),
+        LogFormat::Json => registry
+            .with(fmt::layer().json().flatten_event(true).with_current_span(false).with_span_list(true))
+            .init(),
+    }
+    if provider.is_some() {
+        tracing::info!(endpoint = %telemetry.otlp_endpoint, sample_ratio = telemetry.sample_ratio, "Exporting traces over OTLP");
+    }
+    Ok(Telemetry { provider })
+}
+
+fn tracer_provider(cfg: &TelemetryConfig) -> Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", cfg.otlp_endpoint.trim().trim_end_matches('/')))
+        .with_timeout(cfg.timeout)
+        .build()?;
+    let _ = SAMPLE_RATIO.set(cfg.sample_ratio);
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(cfg.sample_ratio))))
+        .with_resource(Resource::builder().with_service_name(cfg.service_name.clone()).build())
+        .build())
+}
+
+/// Span for one step (`stage`: poll, schedule, claim, assign, complete,
+/// reconcile, admin) of an activity. `queue`, `instance_id` and `run_id` start
+/// empty; record them once known.
+pub fn activity_span(stage: &'static str, activity_id: &str) -> Span {
+    let span = tracing::info_span!(
+        "activity",
+        "otel.name" = stage,
+        stage,
+        activity_id = %activity_id,
+        queue = Empty,
+        instance_id = Empty,
+        run_id = Empty
+    );
+    join_lifecycle(&span, "activity", activity_id);
+    span
+}
+
+/// Span for one step (`stage`: resume) of a workflow instance.
+pub fn workflow_span(stage: &'static str, instance_id: &str) -> Span {
+    let span = tracing::info_span!("workflow", "otel.name" = stage, stage, instance_id = %instance_id);
+    join_lifecycle(&span, "workflow", instance_id);
+    span
+}
+
+/// W3C `traceparent` (and `tracestate`) headers for the current span, to hand
+/// to the WEP. Empty when trace export is off.
+pub fn trace_context() -> Vec<(String, String)> {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&Span::current().context(), &mut carrier);
+    carrier.into_iter().collect()
+}
+
+/// Parent `span` to the lifecycle root derived from `kind` and `id`. Whether
+/// it is sampled follows from the trace id alone, so every component (and
+/// process) makes the same call for an activity.
+fn join_lifecycle(span: &Span, kind: &str, id: &str) {
+    let Some(ratio) = SAMPLE_RATIO.get() else { return };
+    let digest = Sha256::digest(format!("{kind}:{id}").as_bytes());
+    let trace_id = TraceId::from_bytes(digest[..16].try_into().expect("16 bytes"));
+    let span_id = SpanId::from_bytes(digest[16..24].try_into().expect("8 bytes"));
+    let flags = if sampled(trace_id, *ratio) { TraceFlags::SAMPLED } else { TraceFlags::default() };
+    let root = SpanContext::new(trace_id, span_id, flags, true, TraceState::default());
+    span.set_parent(Context::new().with_remote_span_context(root));
+}
+
+/// Same rule as the SDK's `TraceIdRatioBased` sampler.
+fn sampled(trace_id: TraceId, ratio: f64) -> bool {
+    let low = u64::from_be_bytes(trace_id.to_bytes()[8..].try_into().expect("8 bytes"));
+    (low >> 1) < (ratio.max(0.0) * (1u64 << 63) as f64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::DevCollector;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn activity_stages_share_one_trace() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let collector = DevCollector::new();
+        tokio::spawn(collector.clone().serve(listener));
+
+        let cfg = TelemetryConfig { otlp_endpoint: endpoint, service_name: "wcp-test".into(), ..Default::default() };
+        let provider = tracer_provider(&cfg).unwrap();
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let headers = tracing::subscriber::with_default(subscriber, || {
+            activity_span("poll", "0xab").in_scope(|| tracing::info!("polled"));
+            let assign = activity_span("assign", "0xab");
+            assign.record("queue", "video");
+            assign.in_scope(trace_context)
+        });
+        tokio::task::spawn_blocking(move || provider.shutdown()).await.unwrap().unwrap();
+
+        let mut spans = collector.spans();
+        for _ in 0..20 {
+            if spans.len() >= 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            spans = collector.spans();
+        }
+        let names: Vec<_> = spans.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["poll", "assign"]);
+        let (poll, assign) = (&spans[0], &spans[1]);
+        assert_eq!(poll.service, "wcp-test");
+        assert_eq!(poll.trace_id, assign.trace_id);
+        assert!(!poll.parent_span_id.is_empty());
+        assert_eq!(poll.parent_span_id, assign.parent_span_id);
+        assert_eq!(assign.attributes["activity_id"], "0xab");
+        assert_eq!(assign.attributes["queue"], "video");
+
+        // The WEP gets the assign span as its parent.
+        let traceparent = &headers.iter().find(|(k, _)| k == "traceparent").expect("traceparent").1;
+        assert_eq!(traceparent, &format!("00-{}-{}-01", assign.trace_id, assign.span_id));
+    }
+
+    #[test]
+    fn sampling_follows_the_ratio() {
+        let ids: Vec<_> = (0..1000u128).map(|i| TraceId::from(i.wrapping_mul(0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835))).collect();
+        assert!(ids.iter().all(|id| sampled(*id, 1.0)));
+        assert!(ids.iter().all(|id| !sampled(*id, 0.0)));
+        let half = ids.iter().filter(|id| sampled(**id, 0.5)).count();
+        assert!((400..600).contains(&half), "{half} of 1000 sampled at 0.5");
+    }
+}
@@ -6,20 +6,31 @@ import asyncio
 import os
 import time
 import base64
+import hashlib
+import json
+import urllib.error
+import urllib.request
 from typing import Dict, Optional
 from contextlib import asynccontextmanager
 import uvicorn
-from fastapi import FastAPI, HTTPException, BackgroundTasks
+from fastapi import FastAPI, HTTPException, BackgroundTasks, Request
 from pydantic import BaseModel, Field, field_validator
 
 # Task registry and handlers
 from poseidon_wep_sdk_pkg.registry import task, bind_spec_from_yaml
 from poseidon_wep_sdk_pkg.types import TaskAssignment as SDKTaskAssignment, Completion as SDKCompletion
+from poseidon_wep_sdk_pkg.tracing import span
 
 # In-memory task storage (use Redis in production)
 tasks: Dict[str, "TaskStatus"] = {}
 running_tasks: Dict[str, asyncio.Task] = {}
 
+# Concurrent tasks this WEP advertises to the WCP scheduler via /health
+MAX_CONCURRENT = int(os.environ.get("WEP_MAX_CONCURRENT", "4"))
+
+# WCP HTTP API, used to refresh presigned URLs that expired mid-task
+WCP_API = os.environ.get("WCP_API", "http://127.0.0.1:9100")
+
 
 class InputDescriptor(BaseModel):
     name: str
@@ -48,12 +59,15 @@ class TaskAssignment(BaseModel):
     upload_prefix: str
     soft_deadline_unix: int = 0
     heartbeat_interval_s: int = 30
+    upload_url: str = ""  # presigned PUT for {upload_prefix}/result.json (empty without a storage broker)
 
 
 class TaskStatus(BaseModel):
     status: str = "pending"  # pending, running, completed, failed
     progress: int = Field(default=0, ge=0, le=100)
     result_ref: Optional[str] = None
+    result_size: Optional[int] = None    # size/sha256 of the uploaded result, verified by the WCP
+    result_sha256: Optional[str] = None
     error: Optional[str] = None
     started_at: Optional[float] = None
     completed_at: Optional[float] = None
@@ -99,7 +113,8 @@ app = FastAPI(
 async def assign_task(
     task_id: str,
     assignment: TaskAssignment,
-    background_tasks: BackgroundTasks
+    background_tasks: BackgroundTasks,
+    request: Request
 ):
     """Assign a new task to the WEP"""
     if task_id in tasks:
@@ -116,7 +131,8 @@ async def assign_task(
     tasks[task_id] = TaskStatus(status="pending")
     
     # Start processing in background
-    task = asyncio.create_task(process_task(task_id, assignment))
+    # Process under the WCP's assign span (W3C trace context headers)
+    task = asyncio.create_task(process_task(task_id, assignment, dict(request.headers)))
     running_tasks[task_id] = task
     
     print(f"WEP: Accepted task {task_id} ({assignment.task_kind}:{assignment.task_version})")
@@ -143,6 +159,7 @@ async def health_check():
     running = sum(1 for t in tasks.values() if t.status == "running")
     completed = sum(1 for t in tasks.values() if t.status == "completed")
     failed = sum(1 for t in tasks.values() if t.status == "failed")
+    active = sum(1 for t in tasks.values() if t.status in ("pending", "running"))
     
     return {
         "status": "healthy",
@@ -151,18 +168,77 @@ async def health_check():
             "running": running,
             "completed": completed,
             "failed": failed
+        },
+        "capacity": {
+            "max": MAX_CONCURRENT,
+            "available": max(MAX_CONCURRENT - active, 0)
         }
     }
 
 
-async def process_task(task_id: str, assignment: TaskAssignment):
+def refresh_url(activity_id: str, **target: str) -> str:
+    """Ask the WCP for a fresh presigned URL: `input=<name>` or `upload=<name>`."""
+    body = json.dumps({"activity_id": activity_id, **target}).encode()
+    req = urllib.request.Request(f"{WCP_API}/storage/refresh", data=body, method="POST", headers={"Content-Type": "application/json"})
+    with urllib.request.urlopen(req, timeout=30) as resp:
+        return json.loads(resp.read())["url"]
+
+
+def with_refresh(request, url: str, activity_id: str, **target: str) -> bytes:
+    """Run `request(url)`, refreshing the URL once if storage rejects it as expired (403)."""
+    try:
+        return request(url)
+    except urllib.error.HTTPError as e:
+        if e.code != 403:
+            raise
+    return request(refresh_url(activity_id, **target))
+
+
+def fetch_input(activity_id: str, name: str, ref: str) -> Optional[bytes]:
+    """Download an input whose ref the WCP presigned; other refs are left to the handler."""
+    if not ref.startswith(("http://", "https://")):
+        return None
+
+    def get(url: str) -> bytes:
+        with urllib.request.urlopen(url, timeout=30) as resp:
+            return resp.read()
+
+    return with_refresh(get, ref, activity_id, input=name)
+
+
+def upload_result(activity_id: str, url: str, document: dict) -> bytes:
+    """PUT the result document to the presigned upload URL; returns the uploaded bytes."""
+    body = json.dumps(document).encode()
+
+    def put(url: str) -> bytes:
+        req = urllib.request.Request(url, data=body, method="PUT", headers={"Content-Type": "application/json"})
+        with urllib.request.urlopen(req, timeout=30) as resp:
+            return resp.read()
+
+    with_refresh(put, url, activity_id, upload="result.json")
+    return body
+
+
+async def process_task(task_id: str, assignment: TaskAssignment, trace_carrier: Dict[str, str]):
     """Process a task asynchronously"""
+    with span("wep.task", trace_carrier, activity_id=assignment.activity_id, run_id=assignment.run_id, task_kind=assignment.task_kind):
+        await run_task(task_id, assignment)
+
+
+async def run_task(task_id: str, assignment: TaskAssignment):
     try:
         # Update status to running
         tasks[task_id].status = "running"
         tasks[task_id].started_at = time.time()
         
         print(f"WEP: Processing task {task_id}")
+
+        # Pull presigned inputs up front so a bad or expired URL fails the task early
+        input_sizes = {}
+        for i in assignment.inputs:
+            data = await asyncio.to_thread(fetch_input, assignment.activity_id, i.name, i.ref)
+            if data is not None:
+                input_sizes[i.name] = len(data)
         
         # Convert to SDK types
         sdk_assignment = SDKTaskAssignment(
@@ -195,11 +271,27 @@ async def process_task(task_id: str, assignment: TaskAssignment):
         try:
             # Run handler (blocking call in thread)
             result: SDKCompletion = await asyncio.to_thread(handler, sdk_assignment)
+
+            result_ref = result.result_ref
+            result_size = result_sha256 = None
+            if assignment.upload_url:
+                document = {
+                    "activity_id": assignment.activity_id,
+                    "task_kind": assignment.task_kind,
+                    "handler_result_ref": result.result_ref,
+                    "input_sizes": input_sizes,
+                }
+                body = await asyncio.to_thread(upload_result, assignment.activity_id, assignment.upload_url, document)
+                result_ref = f"{assignment.upload_prefix}/result.json"
+                result_size = len(body)
+                result_sha256 = hashlib.sha256(body).hexdigest()
             
             # Update task status with result
             tasks[task_id].status = "completed"
             tasks[task_id].progress = 100
-            tasks[task_id].result_ref = result.result_ref
+            tasks[task_id].result_ref = result_ref
+            tasks[task_id].result_size = result_size
+            tasks[task_id].result_sha256 = result_sha256
             tasks[task_id].completed_at = time.time()
             
             print(f"WEP: Task {task_id} completed successfully")
@@ -5,6 +5,7 @@ from typing import AsyncIterator, Optional, List, Any
 import grpc
 
 from .registry import get_handler, get_spec
+from .tracing import span
 from .config import load_wep_config
 from .types import TaskAssignment as SdkTaskAssignment, InputDescriptor as SdkInput, Completion as SdkCompletion
 from .generated.execution.v1 import execution_pb2 as pb
@@ -43,6 +44,8 @@ class ExecutionServiceServicer(pbg.ExecutionServicer):
     async def TaskStream(self, request_iterator: AsyncIterator[PBEnvelope], context: grpc.aio.ServicerContext):  # type: ignore
         # Simple state: after receiving Assign, run handler and yield Completion
         print("WEP: TaskStream opened")
+        # W3C trace context the WCP put in the stream's metadata
+        trace_carrier = dict(context.invocation_metadata() or ())
         async for env in request_iterator:
             which = env.WhichOneof("msg")
             print(f"WEP: envelope received oneof={which}")
@@ -76,7 +79,8 @@ class ExecutionServiceServicer(pbg.ExecutionServicer):
                             continue
                     sdk_assign = _pb_to_sdk_assignment(assign)
                     try:
-                        sdk_comp: SdkCompletion = await asyncio.to_thread(handler, sdk_assign)
+                        with span("wep.task", trace_carrier, activity_id=assign.activity_id, run_id=assign.run_id, task_kind=assign.task_kind):
+                            sdk_comp: SdkCompletion = await asyncio.to_thread(handler, sdk_assign)
                         # Optional output validation placeholder: check presence of result_ref
                         if spec and spec.get("outputs"):
                             if not (sdk_comp.result_ref or sdk_comp.result_inline):
@@ -0,0 +1,50 @@
+"""Join the WCP's trace for an activity.
+
+The WCP sends W3C trace context (`traceparent`/`tracestate`) with every
+assignment: as HTTP headers in REST mode, as gRPC metadata on TaskStream.
+`span()` opens a span that is a child of the WCP's `assign` span, so work
+done here shows up in the activity's trace.
+
+Everything is optional: without `opentelemetry-api` installed `span()` is a
+no-op. With `opentelemetry-sdk` and `opentelemetry-exporter-otlp-proto-http`
+installed and `OTEL_EXPORTER_OTLP_ENDPOINT` set, spans are exported there
+(service name from `OTEL_SERVICE_NAME`, default `wep`).
+"""
+from __future__ import annotations
+import os
+from contextlib import nullcontext
+from typing import Any, ContextManager, Mapping, Optional
+
+try:
+    from opentelemetry import propagate, trace
+except ImportError:  # tracing is optional
+    propagate = trace = None  # type: ignore[assignment]
+
+_tracer: Optional[Any] = None
+
+
+def _get_tracer() -> Any:
+    global _tracer
+    if _tracer is None:
+        if os.environ.get("OTEL_EXPORTER_OTLP_ENDPOINT"):
+            try:
+                from opentelemetry.exporter.otlp.proto.http.trace_exporter import OTLPSpanExporter
+                from opentelemetry.sdk.resources import Resource
+                from opentelemetry.sdk.trace import TracerProvider
+                from opentelemetry.sdk.trace.export import BatchSpanProcessor
+
+                provider = TracerProvider(resource=Resource.create({"service.name": os.environ.get("OTEL_SERVICE_NAME", "wep")}))
+                provider.add_span_processor(BatchSpanProcessor(OTLPSpanExporter()))
+                trace.set_tracer_provider(provider)
+            except ImportError:
+                print("WEP: OTEL_EXPORTER_OTLP_ENDPOINT set but opentelemetry-sdk/exporter not installed; not exporting spans")
+        _tracer = trace.get_tracer("poseidon_wep_sdk")
+    return _tracer
+
+
+def span(name: str, carrier: Mapping[str, str], **attributes: Any) -> ContextManager[Any]:
+    """Span parented to the trace context in `carrier` (headers or gRPC metadata)."""
+    if trace is None:
+        return nullcontext()
+    ctx = propagate.extract({k.lower(): v for k, v in carrier.items()})
+    return _get_tracer().start_as_current_span(name, context=ctx, attributes={k: str(v) for k, v in attributes.items()})
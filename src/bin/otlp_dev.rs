@@ -0,0 +1,22 @@
+//! Local stand-in for an OpenTelemetry collector.
+//!
+//! Accepts the OTLP/HTTP trace exports the WCP sends with
+//! `[telemetry] otlp_endpoint = "http://127.0.0.1:4318"` and logs every span
+//! (trace id, parent, name, attributes).
+//!
+//! Env: `OTLP_DEV_ADDR` (default `127.0.0.1:4318`).
+use anyhow::Result;
+use std::env;
+use std::net::TcpListener;
+use subnet_wcp_telemetry::collector::DevCollector;
+use tracing_subscriber::{fmt, EnvFilter};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    fmt().with_env_filter(filter).init();
+
+    let addr = env::var("OTLP_DEV_ADDR").unwrap_or_else(|_| "127.0.0.1:4318".into());
+    tracing::info!(%addr, "Serving dev OTLP collector");
+    DevCollector::new().serve(TcpListener::bind(&addr)?).await
+}
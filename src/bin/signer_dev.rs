@@ -0,0 +1,28 @@
+//! Local stand-in for a remote (web3signer-style) signer.
+//!
+//! Serves the eth1 `publicKeys`/`sign` endpoints the WCP's `signer.kind =
+//! "remote"` uses, from one key held in memory.
+//!
+//! Env: `SIGNER_DEV_ADDR` (default `127.0.0.1:9200`), `SIGNER_DEV_KEY_FILE`
+//! (hex key file, mode 600; a random key per run if unset).
+use alloy_signer_local::PrivateKeySigner;
+use anyhow::Result;
+use std::env;
+use std::net::TcpListener;
+use subnet_wcp_signer::local::DevSigner;
+use tracing_subscriber::{fmt, EnvFilter};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    fmt().with_env_filter(filter).init();
+
+    let addr = env::var("SIGNER_DEV_ADDR").unwrap_or_else(|_| "127.0.0.1:9200".into());
+    let key = match env::var("SIGNER_DEV_KEY_FILE") {
+        Ok(path) => subnet_wcp_signer::read_key_file(path.as_ref())?,
+        Err(_) => PrivateKeySigner::random(),
+    };
+
+    tracing::info!(%addr, address = %key.address(), "Serving dev signer");
+    DevSigner::new(vec![key]).serve(TcpListener::bind(&addr)?).await
+}
@@ -0,0 +1,35 @@
+//! Local stand-in for the subnet-api storage service.
+//!
+//! Serves `Storage.PresignDownload`/`PresignUpload` over gRPC and the presigned
+//! URLs over HTTP, backed by a directory.
+//!
+//! Env: `STORAGE_DEV_ROOT` (default `./storage-dev`), `STORAGE_DEV_GRPC_ADDR`
+//! (default `127.0.0.1:50051`, matching `configs/local.toml`),
+//! `STORAGE_DEV_HTTP_ADDR` (default `127.0.0.1:9000`), `STORAGE_DEV_BASE_URL`
+//! (default `http://{STORAGE_DEV_HTTP_ADDR}`), `STORAGE_DEV_SECRET` (URL
+//! signing key; random per run if unset).
+use alloy_signer_local::PrivateKeySigner;
+use anyhow::Result;
+use std::env;
+use std::net::SocketAddr;
+use subnet_wcp_storage::local::LocalStorage;
+use tracing_subscriber::{fmt, EnvFilter};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    fmt().with_env_filter(filter).init();
+
+    let root = env::var("STORAGE_DEV_ROOT").unwrap_or_else(|_| "./storage-dev".into());
+    let grpc_addr: SocketAddr = env::var("STORAGE_DEV_GRPC_ADDR").unwrap_or_else(|_| "127.0.0.1:50051".into()).parse()?;
+    let http_addr: SocketAddr = env::var("STORAGE_DEV_HTTP_ADDR").unwrap_or_else(|_| "127.0.0.1:9000".into()).parse()?;
+    let base_url = env::var("STORAGE_DEV_BASE_URL").unwrap_or_else(|_| format!("http://{}", http_addr));
+    let secret = match env::var("STORAGE_DEV_SECRET") {
+        Ok(s) => s.into_bytes(),
+        // Any 32 random bytes will do; a throwaway key is the handiest source.
+        Err(_) => PrivateKeySigner::random().to_bytes().to_vec(),
+    };
+
+    std::fs::create_dir_all(&root)?;
+    LocalStorage::new(root.into(), base_url, secret).serve(grpc_addr, http_addr).await
+}
@@ -0,0 +1,579 @@
+//! Operator CLI for the WCP.
+//!
+//! `state`/`tx` read from the DB (opened read-only, so the WCP can keep
+//! running) or, with `--api`, from the admin API. The DB is `--db`, else
+//! `storage.data_dir/wcp.db` from the config, opened with its `[storage]` tuning. `db import|compact` and
+//! `simulate` open the DB as primary and need the WCP stopped. `config` and
+//! `chain` use the same config (`ENVIRONMENT`, `WCP__*`) as the WCP.
+use alloy::primitives::Address;
+use alloy::providers::{Provider, ProviderBuilder};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use subnet_wcp_chain::{control_plane as scp, task_queue};
+use subnet_wcp_config::{StorageConfig, WcpConfig};
+use subnet_wcp_persistence::records::{
+    AssignmentStatus, BroadcastJob, DoneOutcome, DoneRecord, InflightRecord, TxKind, TxRecord, TxStatus,
+};
+use subnet_wcp_persistence::view::{self, ActivityDetail, ActivityState, ActivitySummary};
+use subnet_wcp_persistence::{keys, migrations, Batch, KvStore};
+
+#[derive(Parser)]
+#[command(name = "wcpctl", about = "Inspect and operate a Subnet Worker Client Process")]
+struct Cli {
+    /// WCP RocksDB directory; `storage.data_dir/wcp.db` from the config if unset.
+    #[arg(long, global = true, env = "WCPCTL_DB")]
+    db: Option<PathBuf>,
+    /// Admin API base URL (e.g. http://127.0.0.1:9100); `state` and `tx` read from it instead of the DB.
+    #[arg(long, global = true, env = "WCPCTL_API")]
+    api: Option<String>,
+    /// Admin API bearer token (`api.admin_token`).
+    #[arg(long, global = true, env = "WCP__API__ADMIN_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    #[arg(long, short, global = true, value_enum, default_value_t = Output::Table)]
+    output: Output,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    Table,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Activities by local pipeline state.
+    #[command(subcommand)]
+    State(StateCmd),
+    /// Latest transaction per activity.
+    #[command(subcommand)]
+    Tx(TxCmd),
+    /// Raw DB maintenance.
+    #[command(subcommand)]
+    Db(DbCmd),
+    /// The config the WCP would load.
+    #[command(subcommand)]
+    Config(ConfigCmd),
+    /// This worker as seen on-chain.
+    #[command(subcommand)]
+    Chain(ChainCmd),
+    /// Dev only: write the records the claim/assign pipeline would.
+    #[command(subcommand)]
+    Simulate(SimulateCmd),
+}
+
+#[derive(Subcommand)]
+enum StateCmd {
+    List {
+        /// queued | claiming | inflight | done
+        #[arg(long, value_parser = parse_lower::<ActivityState>)]
+        state: Option<ActivityState>,
+    },
+    Show { activity_id: String },
+}
+
+#[derive(Subcommand)]
+enum TxCmd {
+    List {
+        /// pending | submitted | confirmed | dropped | replaced
+        #[arg(long, value_parser = parse_lower::<TxStatus>)]
+        status: Option<TxStatus>,
+    },
+    Show { activity_id: String },
+}
+
+#[derive(Subcommand)]
+enum DbCmd {
+    /// Write every key (optionally under `--prefix`) as JSON lines: `{"key", "value"}`.
+    Export {
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// Output file; stdout if unset.
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// Load an export (`-` for stdin), then run migrations.
+    Import {
+        file: String,
+        /// Allow importing into a DB that already has data; matching keys are overwritten.
+        #[arg(long)]
+        merge: bool,
+    },
+    Compact,
+}
+
+#[derive(Subcommand)]
+enum ConfigCmd {
+    /// Load and validate the config and signer the WCP would use (like `subnet-wcp --check-config`) and print a summary.
+    Check,
+}
+
+#[derive(Subcommand)]
+enum ChainCmd {
+    /// RPC, protocol version, worker registration and wallet as seen on-chain.
+    Status,
+}
+
+#[derive(Subcommand)]
+enum SimulateCmd {
+    /// Claim confirmed: `tx:{id}` plus a pending `inflight:{id}`.
+    Claim {
+        activity_id: String,
+        #[arg(long, default_value = "video/1.0.0/processing")]
+        queue: String,
+    },
+    /// WEP finished: `done:{id}` as ok plus the complete/resume broadcasts, like the Assigner.
+    Complete {
+        activity_id: String,
+        #[arg(long)]
+        result_ref: Option<String>,
+    },
+}
+
+/// Parse a lowercase serde name (`inflight`, `submitted`) into the enum it names.
+fn parse_lower<T: DeserializeOwned>(s: &str) -> Result<T, String> {
+    serde_json::from_value(Value::String(s.to_string())).map_err(|_| format!("unknown value {:?}", s))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match &cli.command {
+        Command::State(StateCmd::List { state }) => {
+            let rows: Vec<ActivitySummary> = match cli.admin()? {
+                Some(api) => api.get("/admin/activities", state.map(|s| ("state", s.as_str()))).await?,
+                None => view::activities(&cli.db()?.open_read()?, *state)?,
+            };
+            emit(cli.output, &rows, || summary_table(&rows))
+        }
+        Command::State(StateCmd::Show { activity_id }) => {
+            let detail = cli.activity(activity_id).await?;
+            emit(cli.output, &detail, || detail_table(&detail))
+        }
+        Command::Tx(TxCmd::List { status }) => {
+            let txs: Vec<TxRecord> = match cli.admin()? {
+                Some(api) => {
+                    let status = status.map(|s| lower(&s));
+                    api.get("/admin/txs", status.as_deref().map(|s| ("status", s))).await?
+                }
+                None => view::txs(&cli.db()?.open_read()?, *status)?,
+            };
+            emit(cli.output, &txs, || tx_table(&txs))
+        }
+        Command::Tx(TxCmd::Show { activity_id }) => {
+            let tx = cli.activity(activity_id).await?.tx.ok_or_else(|| anyhow!("activity {} has no tx record", activity_id))?;
+            let txs = [tx];
+            emit(cli.output, &txs[0], || tx_table(&txs))
+        }
+        Command::Db(DbCmd::Export { prefix, out }) => export(&cli.db()?, prefix, out.as_deref()),
+        Command::Db(DbCmd::Import { file, merge }) => import(&cli.db()?, file, *merge),
+        Command::Db(DbCmd::Compact) => {
+            let db = cli.db()?;
+            db.open_primary()?.compact()?;
+            eprintln!("compacted {}", db.path.display());
+            Ok(())
+        }
+        Command::Config(ConfigCmd::Check) => {
+            let (cfg, mut problems) = WcpConfig::load()?;
+            let signer = match subnet_wcp_signer::check(&cfg, &mut problems).await {
+                Some(signer) if problems.is_empty() => signer,
+                _ => bail!("{}", subnet_wcp_config::report(&problems)),
+            };
+            let summary = config_summary(&cfg, signer.address);
+            emit(cli.output, &summary, || key_value_table(&summary))
+        }
+        Command::Chain(ChainCmd::Status) => {
+            let status = chain_status(&WcpConfig::from_env()?).await?;
+            emit(cli.output, &status, || key_value_table(&status))
+        }
+        Command::Simulate(SimulateCmd::Claim { activity_id, queue }) => simulate_claim(&cli.db()?, activity_id, queue),
+        Command::Simulate(SimulateCmd::Complete { activity_id, result_ref }) => simulate_complete(&cli.db()?, activity_id, result_ref.clone()),
+    }
+}
+
+impl Cli {
+    fn db(&self) -> Result<Db> {
+        let cfg = StorageConfig::from_env()?;
+        let path = self.db.clone().unwrap_or_else(|| cfg.db_path());
+        Ok(Db { path, cfg })
+    }
+
+    fn admin(&self) -> Result<Option<AdminClient>> {
+        let Some(base) = &self.api else { return Ok(None) };
+        let token = self.token.clone().ok_or_else(|| anyhow!("--api needs --token (or WCP__API__ADMIN_TOKEN)"))?;
+        Ok(Some(AdminClient { base: base.trim_end_matches('/').to_string(), token, client: reqwest::Client::new() }))
+    }
+
+    async fn activity(&self, activity_id: &str) -> Result<ActivityDetail> {
+        match self.admin()? {
+            Some(api) => api.get(&format!("/admin/activities/{}", activity_id), None).await,
+            None => view::activity(&self.db()?.open_read()?, activity_id)?.ok_or_else(|| anyhow!("no local state for activity {}", activity_id)),
+        }
+    }
+}
+
+struct AdminClient {
+    base: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl AdminClient {
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: Option<(&str, &str)>) -> Result<T> {
+        let mut req = self.client.get(format!("{}{}", self.base, path)).bearer_auth(&self.token);
+        if let Some(q) = query {
+            req = req.query(&[q]);
+        }
+        let resp = req.send().await.with_context(|| format!("admin API at {} unreachable", self.base))?;
+        let status = resp.status();
+        if !status.is_success() {
+            bail!("admin API {}: {}", status, resp.text().await.unwrap_or_default());
+        }
+        Ok(resp.json().await?)
+    }
+}
+
+struct Db {
+    path: PathBuf,
+    cfg: StorageConfig,
+}
+
+impl Db {
+    /// Read-only: does not take the DB lock, so this works while the WCP is running.
+    fn open_read(&self) -> Result<KvStore> {
+        let store = KvStore::open_read_only(&self.path, &self.cfg).with_context(|| format!("opening {} read-only", self.path.display()))?;
+        let version = migrations::schema_version(&store)?;
+        if version < migrations::CURRENT_VERSION {
+            eprintln!(
+                "warning: {} is at schema v{} (current v{}); start the WCP once to migrate before inspecting",
+                self.path.display(),
+                version,
+                migrations::CURRENT_VERSION
+            );
+        }
+        Ok(store)
+    }
+
+    fn open_primary(&self) -> Result<KvStore> {
+        KvStore::open(&self.path, &self.cfg).with_context(|| format!("opening {} (is the WCP still running?)", self.path.display()))
+    }
+}
+
+fn export(db: &Db, prefix: &str, out: Option<&str>) -> Result<()> {
+    let store = db.open_read()?;
+    let mut w: Box<dyn Write> = match out {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut n = 0;
+    for (k, v) in store.scan_prefix(prefix)? {
+        let key = String::from_utf8_lossy(&k);
+        let line = match serde_json::from_slice::<Value>(&v) {
+            Ok(value) => serde_json::json!({ "key": key, "value": value }),
+            Err(_) => serde_json::json!({ "key": key, "value_hex": hex::encode(&v) }),
+        };
+        writeln!(w, "{}", line)?;
+        n += 1;
+    }
+    w.flush()?;
+    eprintln!("exported {} keys", n);
+    Ok(())
+}
+
+fn import(db: &Db, file: &str, merge: bool) -> Result<()> {
+    let reader: Box<dyn BufRead> = match file {
+        "-" => Box::new(std::io::stdin().lock()),
+        path => Box::new(std::io::BufReader::new(std::fs::File::open(path)?)),
+    };
+    let store = db.open_primary()?;
+    if !merge && !store.scan_prefix("")?.is_empty() {
+        bail!("{} already has data; pass --merge to overwrite matching keys", db.path.display());
+    }
+    let mut batch = Batch::new();
+    let mut n = 0;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Value = serde_json::from_str(&line).with_context(|| format!("line {}", i + 1))?;
+        let key = entry["key"].as_str().ok_or_else(|| anyhow!("line {}: missing key", i + 1))?;
+        let value = match (&entry.get("value"), entry["value_hex"].as_str()) {
+            (Some(value), _) => serde_json::to_vec(value)?,
+            (None, Some(raw)) => hex::decode(raw).with_context(|| format!("line {}: value_hex", i + 1))?,
+            (None, None) => bail!("line {}: missing value", i + 1),
+        };
+        batch.put(key, value);
+        n += 1;
+    }
+    // One batch: an import either lands whole or not at all.
+    store.write(batch)?;
+    for step in migrations::run(&store)? {
+        eprintln!("applied schema migration v{}: {}", step.version, step.description);
+    }
+    eprintln!("imported {} keys into {}", n, db.path.display());
+    Ok(())
+}
+
+fn simulate_claim(db: &Db, activity_id: &str, queue: &str) -> Result<()> {
+    let store = db.open_primary()?;
+    migrations::run(&store)?;
+    let now = Utc::now().timestamp_millis();
+    let tx = TxRecord {
+        activity_id: activity_id.to_string(),
+        kind: TxKind::Claim,
+        status: TxStatus::Submitted,
+        tx_hash: Some("0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef".into()),
+        submitted_at_ms: now,
+        last_bump_at_ms: None,
+        nonce: None,
+    };
+    let inflight = InflightRecord {
+        activity_id: activity_id.to_string(),
+        queue: queue.to_string(),
+        claimed_at_ms: now,
+        assignment_status: AssignmentStatus::Pending,
+        instance_id: None,
+        expires_at_ms: None,
+        inputs: Vec::new(),
+        upload_prefix: None,
+    };
+    let mut batch = Batch::new();
+    batch
+        .put_record(keys::tx(activity_id), &tx)?
+        .put_record(keys::inflight(activity_id), &inflight)?
+        .delete(keys::claim_job(activity_id))
+        .delete(keys::broadcast_claim(activity_id));
+    store.write(batch)?;
+    eprintln!("wrote {} and {}", keys::tx(activity_id), keys::inflight(activity_id));
+    Ok(())
+}
+
+fn simulate_complete(db: &Db, activity_id: &str, result_ref: Option<String>) -> Result<()> {
+    let store = db.open_primary()?;
+    migrations::run(&store)?;
+    let inflight: InflightRecord = store
+        .get_record(keys::inflight(activity_id))?
+        .ok_or_else(|| anyhow!("activity {} is not inflight", activity_id))?;
+    let now = Utc::now().timestamp_millis();
+    let done = DoneRecord {
+        activity_id: activity_id.to_string(),
+        outcome: DoneOutcome::Ok,
+        result_ref,
+        error: None,
+        finished_at_ms: now,
+        instance_id: inflight.instance_id.clone(),
+        queue: Some(inflight.queue.clone()).filter(|q| !q.is_empty()),
+    };
+    let mut batch = Batch::new();
+    batch
+        .put_record(keys::broadcast_complete(activity_id), &BroadcastJob { id: activity_id.to_string(), created_at_ms: now })?
+        .put_record(keys::done(activity_id), &done)?
+        .delete(keys::inflight(activity_id));
+    if let Some(instance_id) = &inflight.instance_id {
+        batch.put_record(keys::broadcast_resume(instance_id), &BroadcastJob { id: instance_id.clone(), created_at_ms: now })?;
+    }
+    store.write(batch)?;
+    eprintln!("wrote {} and {}", keys::done(activity_id), keys::broadcast_complete(activity_id));
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ConfigSummary {
+    environment: String,
+    rpc_url: String,
+    ws_url: Option<String>,
+    signer: String,
+    wallet_address: String,
+    task_queue_address: String,
+    workflow_engine_address: String,
+    subnet_control_plane_address: String,
+    queues: Vec<String>,
+    policy: String,
+    wep_endpoint: Option<String>,
+    storage_endpoint: String,
+    api_listen: String,
+    admin_api: bool,
+    events: bool,
+    protocol_range: String,
+    db_path: String,
+}
+
+fn config_summary(cfg: &WcpConfig, wallet_address: Address) -> ConfigSummary {
+    ConfigSummary {
+        environment: std::env::var("ENVIRONMENT").unwrap_or_else(|_| "local".to_string()),
+        rpc_url: cfg.ethereum.rpc_url.clone(),
+        ws_url: cfg.ethereum.ws_url.clone(),
+        signer: cfg.signer.kind.as_str().to_string(),
+        wallet_address: wallet_address.to_string(),
+        task_queue_address: cfg.ethereum.task_queue_address.clone(),
+        workflow_engine_address: cfg.ethereum.workflow_engine_address.clone(),
+        subnet_control_plane_address: cfg.ethereum.subnet_control_plane_address.clone(),
+        queues: cfg.scheduler.queues().into_iter().map(|q| q.name).collect(),
+        policy: format!("{:?}", cfg.scheduler.policy),
+        wep_endpoint: cfg.wep_endpoint.clone(),
+        storage_endpoint: cfg.subnet_api.grpc_endpoint.clone(),
+        api_listen: cfg.api.listen.clone(),
+        admin_api: !cfg.api.admin_token.is_empty(),
+        events: cfg.events.enabled,
+        protocol_range: format!("{}..={}", cfg.protocol.contract_min, cfg.protocol.contract_max),
+        db_path: cfg.storage.db_path().display().to_string(),
+    }
+}
+
+#[derive(Serialize)]
+struct ChainStatus {
+    rpc_url: String,
+    chain_id: u64,
+    block: u64,
+    protocol_version: String,
+    protocol_supported: bool,
+    worker: String,
+    worker_active: bool,
+    balance_wei: String,
+    worker_activities: usize,
+}
+
+async fn chain_status(cfg: &WcpConfig) -> Result<ChainStatus> {
+    let provider = ProviderBuilder::new().connect_http(cfg.ethereum.rpc_url.parse()?);
+    let scp_addr: Address = cfg.ethereum.subnet_control_plane_address.parse()?;
+    let task_queue_addr: Address = cfg.ethereum.task_queue_address.parse()?;
+    let worker = subnet_wcp_signer::load(&cfg.ethereum, &cfg.signer).await?.address;
+    let protocol_version = scp::get_protocol_version(&provider, scp_addr).await?;
+    let protocol_supported = match (
+        semver::Version::parse(&protocol_version),
+        semver::Version::parse(&cfg.protocol.contract_min),
+        semver::Version::parse(&cfg.protocol.contract_max),
+    ) {
+        (Ok(v), Ok(lo), Ok(hi)) => v >= lo && v <= hi,
+        _ => false,
+    };
+    Ok(ChainStatus {
+        rpc_url: cfg.ethereum.rpc_url.clone(),
+        chain_id: provider.get_chain_id().await?,
+        block: provider.get_block_number().await?,
+        protocol_version,
+        protocol_supported,
+        worker: worker.to_string(),
+        worker_active: scp::is_worker_active(&provider, scp_addr, worker).await?,
+        balance_wei: provider.get_balance(worker).await?.to_string(),
+        worker_activities: task_queue::get_worker_activities(&provider, task_queue_addr, worker).await?.len(),
+    })
+}
+
+type Table = (Vec<&'static str>, Vec<Vec<String>>);
+
+fn emit<T: Serialize>(output: Output, data: &T, table: impl FnOnce() -> Table) -> Result<()> {
+    match output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(data)?),
+        Output::Table => {
+            let (headers, rows) = table();
+            print_table(&headers, &rows);
+        }
+    }
+    Ok(())
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.len());
+        }
+    }
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells.iter().zip(&widths).map(|(c, w)| format!("{:<w$}", c, w = w)).collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(headers.to_vec());
+    for row in rows {
+        line(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn opt<T: ToString>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+fn lower<T: Serialize>(v: &T) -> String {
+    serde_json::to_value(v).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+fn time_ms(ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ms).map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_else(|| ms.to_string())
+}
+
+fn summary_table(rows: &[ActivitySummary]) -> Table {
+    (
+        vec!["ACTIVITY", "STATE", "QUEUE", "OUTCOME", "SINCE"],
+        rows.iter()
+            .map(|r| vec![r.activity_id.clone(), r.state.as_str().to_string(), opt(r.queue.clone()), opt(r.outcome.map(|o| lower(&o))), time_ms(r.since_ms)])
+            .collect(),
+    )
+}
+
+fn tx_table(txs: &[TxRecord]) -> Table {
+    (
+        vec!["ACTIVITY", "KIND", "STATUS", "TX_HASH", "NONCE", "SUBMITTED", "LAST_BUMP"],
+        txs.iter()
+            .map(|t| {
+                vec![
+                    t.activity_id.clone(),
+                    lower(&t.kind),
+                    lower(&t.status),
+                    opt(t.tx_hash.clone()),
+                    opt(t.nonce),
+                    time_ms(t.submitted_at_ms),
+                    opt(t.last_bump_at_ms.map(time_ms)),
+                ]
+            })
+            .collect(),
+    )
+}
+
+/// One row per record family, each value as compact JSON.
+fn detail_table(d: &ActivityDetail) -> Table {
+    let json = |v: Value| match v {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s,
+        other => other.to_string(),
+    };
+    let rows = [
+        ("activity_id", Value::from(d.activity_id.clone())),
+        ("state", Value::from(d.state.as_str())),
+        ("claim_job", serde_json::to_value(&d.claim_job).unwrap_or_default()),
+        ("claim_broadcast", serde_json::to_value(&d.claim_broadcast).unwrap_or_default()),
+        ("inflight", serde_json::to_value(&d.inflight).unwrap_or_default()),
+        ("done", serde_json::to_value(&d.done).unwrap_or_default()),
+        ("complete_broadcast", serde_json::to_value(&d.complete_broadcast).unwrap_or_default()),
+        ("tx", serde_json::to_value(&d.tx).unwrap_or_default()),
+    ];
+    (vec!["RECORD", "VALUE"], rows.into_iter().map(|(k, v)| vec![k.to_string(), json(v)]).collect())
+}
+
+/// A struct's fields as `FIELD  VALUE` rows.
+fn key_value_table<T: Serialize>(data: &T) -> Table {
+    let rows = match serde_json::to_value(data) {
+        Ok(Value::Object(map)) => map
+            .into_iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    Value::String(s) => s,
+                    Value::Null => "-".to_string(),
+                    Value::Array(items) => items.iter().map(|i| i.as_str().map(str::to_string).unwrap_or_else(|| i.to_string())).collect::<Vec<_>>().join(", "),
+                    other => other.to_string(),
+                };
+                vec![k, v]
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    (vec!["FIELD", "VALUE"], rows)
+}
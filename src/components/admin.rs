@@ -0,0 +1,452 @@
+//! Operator admin API, served under `/admin` on `api.listen` when
+//! `api.admin_token` is set. Every request needs `Authorization: Bearer {token}`.
+//!
+//! - `GET /admin/activities[?state=queued|claiming|inflight|done]`: one summary per known activity.
+//! - `GET /admin/activities/{id}`: every local record for the activity, including its `tx:` record.
+//! - `GET /admin/txs[?status=pending|submitted|confirmed|dropped|replaced]`: `tx:*` records.
+//! - `POST /admin/activities/{id}/cancel`: drop an activity that is not claimed yet (`done` as cancelled).
+//! - `POST /admin/activities/{id}/retry`: hand a failed or timed-out activity to the Assigner again,
+//!   or forget a cancelled one so the Poller may pick it up again (answers `null`).
+//! - `POST /admin/activities/{id}/fail` (optional `{"reason"}`): retire an inflight activity as failed.
+//! - `GET /admin/queues`, `POST /admin/queues/{name}/pause|resume`: polling per queue; pauses persist.
+//! - `POST /admin/reconcile`: run a reconcile pass now and return its repairs.
+//!
+//! Mutations answer with the activity's records afterwards; 404 for unknown
+//! activities or queues, 409 when the activity is in the wrong state.
+use anyhow::Result;
+use axum::extract::{Json, Path, Query, State};
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use subnet_wcp_config::SchedulerConfig;
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
+use subnet_wcp_persistence::records::{AssignmentStatus, DoneOutcome, DoneRecord, InflightRecord, QueuePause, TxRecord, TxStatus};
+use subnet_wcp_persistence::view::{self, ActivityDetail, ActivityState, ActivitySummary};
+use subnet_wcp_persistence::{keys, Batch, KvStore};
+use subnet_wcp_telemetry::activity_span;
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::info;
+
+use super::reconciler::ReconcileRequest;
+
+type ApiError = (StatusCode, String);
+
+#[derive(Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    state: Option<ActivityState>,
+}
+
+#[derive(Deserialize)]
+struct TxQuery {
+    #[serde(default)]
+    status: Option<TxStatus>,
+}
+
+#[derive(Deserialize, Default)]
+struct FailRequest {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct QueueStatus {
+    name: String,
+    paused: bool,
+    paused_at_ms: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct RepairEntry {
+    activity_id: String,
+    repair: &'static str,
+}
+
+#[derive(Serialize)]
+struct ReconcileReport {
+    repairs: Vec<RepairEntry>,
+}
+
+pub struct Admin<B: StorageBackend = RocksBackend> {
+    store: KvStore<B>,
+    token: String,
+    /// Current `[scheduler]`; only its enabled queues can be paused.
+    scheduler: watch::Receiver<SchedulerConfig>,
+    reconcile: mpsc::Sender<ReconcileRequest>,
+}
+
+impl<B: StorageBackend> Admin<B> {
+    pub fn new(store: KvStore<B>, token: String, scheduler: watch::Receiver<SchedulerConfig>, reconcile: mpsc::Sender<ReconcileRequest>) -> Self {
+        Self { store, token, scheduler, reconcile }
+    }
+
+    fn queue_names(&self) -> Vec<String> {
+        self.scheduler.borrow().queues().into_iter().map(|q| q.name).collect()
+    }
+
+    pub fn router(self) -> Router {
+        let token: Arc<str> = self.token.as_str().into();
+        Router::new()
+            .route("/admin/activities", get(list::<B>))
+            .route("/admin/activities/:id", get(show::<B>))
+            .route("/admin/activities/:id/cancel", post(cancel::<B>))
+            .route("/admin/activities/:id/retry", post(retry::<B>))
+            .route("/admin/activities/:id/fail", post(fail::<B>))
+            .route("/admin/txs", get(txs::<B>))
+            .route("/admin/queues", get(queues::<B>))
+            .route("/admin/queues/:name/pause", post(pause::<B>))
+            .route("/admin/queues/:name/resume", post(resume::<B>))
+            .route("/admin/reconcile", post(reconcile::<B>))
+            .route_layer(middleware::from_fn_with_state(token, authorize))
+            .with_state(Arc::new(self))
+    }
+
+    fn existing(&self, activity_id: &str) -> Result<ActivityDetail, ApiError> {
+        view::activity(&self.store, activity_id)
+            .map_err(internal)?
+            .ok_or((StatusCode::NOT_FOUND, format!("no local state for activity {}", activity_id)))
+    }
+
+    fn cancel(&self, activity_id: &str) -> Result<ActivityDetail, ApiError> {
+        let _span = activity_span("admin", activity_id).entered();
+        let d = self.existing(activity_id)?;
+        match d.state {
+            ActivityState::Queued | ActivityState::Claiming if !d.claim_submitted() => {}
+            ActivityState::Queued | ActivityState::Claiming => {
+                return Err(conflict(activity_id, "has a claim tx in flight; fail it once it is inflight"));
+            }
+            ActivityState::Inflight => return Err(conflict(activity_id, "is claimed on-chain; use fail instead")),
+            ActivityState::Done => return Err(conflict(activity_id, "is already done")),
+        }
+        let done = DoneRecord {
+            activity_id: activity_id.to_string(),
+            outcome: DoneOutcome::Cancelled,
+            result_ref: None,
+            error: Some("cancelled by operator".to_string()),
+            finished_at_ms: Utc::now().timestamp_millis(),
+            instance_id: None,
+            queue: d.claim_job.as_ref().map(|j| j.queue_name.clone()),
+        };
+        let mut batch = Batch::new();
+        batch
+            .put_record(keys::done(activity_id), &done)
+            .map_err(internal)?
+            .delete(keys::claim_job(activity_id))
+            .delete(keys::broadcast_claim(activity_id));
+        self.store.write(batch).map_err(internal)?;
+        info!(%activity_id, "admin: cancelled activity");
+        self.existing(activity_id)
+    }
+
+    /// `None` once a cancelled activity is forgotten: nothing is left locally until the Poller sees it again.
+    fn retry(&self, activity_id: &str) -> Result<Option<ActivityDetail>, ApiError> {
+        let _span = activity_span("admin", activity_id).entered();
+        let d = self.existing(activity_id)?;
+        let Some(done) = d.done else { return Err(conflict(activity_id, "has not finished")) };
+        let mut batch = Batch::new();
+        match done.outcome {
+            DoneOutcome::Failed | DoneOutcome::Timeout => {
+                // Still ours on-chain unless the reconciler says otherwise; it backfills expiry too.
+                let rec = InflightRecord {
+                    activity_id: activity_id.to_string(),
+                    queue: done.queue.clone().unwrap_or_default(),
+                    claimed_at_ms: Utc::now().timestamp_millis(),
+                    assignment_status: AssignmentStatus::Pending,
+                    instance_id: done.instance_id,
+                    expires_at_ms: None,
+                    inputs: Vec::new(),
+                    upload_prefix: None,
+                };
+                batch
+                    .put_record(keys::inflight(activity_id), &rec)
+                    .map_err(internal)?
+                    .delete(keys::done(activity_id))
+                    .delete(keys::broadcast_complete(activity_id));
+            }
+            DoneOutcome::Cancelled => {
+                batch.delete(keys::done(activity_id));
+            }
+            DoneOutcome::Ok | DoneOutcome::Expired => {
+                return Err(conflict(activity_id, "finished ok or expired on-chain; nothing to retry"));
+            }
+        }
+        self.store.write(batch).map_err(internal)?;
+        info!(%activity_id, previous = ?done.outcome, "admin: retrying activity");
+        view::activity(&self.store, activity_id).map_err(internal)
+    }
+
+    fn fail(&self, activity_id: &str, reason: Option<String>) -> Result<ActivityDetail, ApiError> {
+        let _span = activity_span("admin", activity_id).entered();
+        let d = self.existing(activity_id)?;
+        let Some(inflight) = d.inflight.filter(|_| d.state == ActivityState::Inflight) else {
+            return Err(conflict(activity_id, "is not inflight"));
+        };
+        let done = DoneRecord {
+            activity_id: activity_id.to_string(),
+            outcome: DoneOutcome::Failed,
+            result_ref: None,
+            error: Some(reason.unwrap_or_else(|| "failed by operator".to_string())),
+            finished_at_ms: Utc::now().timestamp_millis(),
+            instance_id: inflight.instance_id,
+            queue: Some(inflight.queue).filter(|q| !q.is_empty()),
+        };
+        let mut batch = Batch::new();
+        batch
+            .put_record(keys::done(activity_id), &done)
+            .map_err(internal)?
+            .delete(keys::inflight(activity_id));
+        // The Assigner may have finished it since we looked; whoever writes `done:` first wins.
+        if !self.store.write_if_absent(keys::done(activity_id), batch).map_err(internal)? {
+            return Err(conflict(activity_id, "finished while being failed"));
+        }
+        info!(%activity_id, error = ?done.error, "admin: failed activity");
+        self.existing(activity_id)
+    }
+
+    fn queue_statuses(&self) -> Result<Vec<QueueStatus>> {
+        self.queue_names()
+            .into_iter()
+            .map(|name| {
+                let pause: Option<QueuePause> = self.store.get_record(keys::paused_queue(&name))?;
+                Ok(QueueStatus { name, paused: pause.is_some(), paused_at_ms: pause.map(|p| p.paused_at_ms) })
+            })
+            .collect()
+    }
+
+    fn set_paused(&self, queue: &str, paused: bool) -> Result<Vec<QueueStatus>, ApiError> {
+        if !self.queue_names().iter().any(|q| q == queue) {
+            return Err((StatusCode::NOT_FOUND, format!("queue {:?} is not configured", queue)));
+        }
+        let key = keys::paused_queue(queue);
+        if paused {
+            if self.store.get(&key).map_err(internal)?.is_none() {
+                let pause = QueuePause { queue: queue.to_string(), paused_at_ms: Utc::now().timestamp_millis() };
+                self.store.put_record(&key, &pause).map_err(internal)?;
+            }
+        } else {
+            self.store.delete(&key).map_err(internal)?;
+        }
+        info!(%queue, paused, "admin: queue polling updated");
+        self.queue_statuses().map_err(internal)
+    }
+
+    async fn reconcile(&self) -> Result<ReconcileReport, ApiError> {
+        let unavailable = || (StatusCode::SERVICE_UNAVAILABLE, "reconciler is not running".to_string());
+        let (tx, rx) = oneshot::channel();
+        self.reconcile.send(tx).await.map_err(|_| unavailable())?;
+        let repairs = rx.await.map_err(|_| unavailable())?.map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+        Ok(ReconcileReport {
+            repairs: repairs.into_iter().map(|(activity_id, r)| RepairEntry { activity_id, repair: r.as_str() }).collect(),
+        })
+    }
+}
+
+fn internal(e: anyhow::Error) -> ApiError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn conflict(activity_id: &str, why: &str) -> ApiError {
+    (StatusCode::CONFLICT, format!("activity {} {}", activity_id, why))
+}
+
+/// Length check first, then a comparison that does not stop at the first mismatch.
+fn token_matches(presented: &[u8], token: &[u8]) -> bool {
+    presented.len() == token.len() && presented.iter().zip(token).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+async fn authorize<B>(State(token): State<Arc<str>>, req: Request<B>, next: Next<B>) -> Response {
+    let presented = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match presented {
+        Some(t) if token_matches(t.as_bytes(), token.as_bytes()) => next.run(req).await,
+        _ => (StatusCode::UNAUTHORIZED, "missing or invalid admin token").into_response(),
+    }
+}
+
+async fn list<B: StorageBackend>(State(a): State<Arc<Admin<B>>>, Query(q): Query<ListQuery>) -> Result<Json<Vec<ActivitySummary>>, ApiError> {
+    Ok(Json(view::activities(&a.store, q.state).map_err(internal)?))
+}
+
+async fn txs<B: StorageBackend>(State(a): State<Arc<Admin<B>>>, Query(q): Query<TxQuery>) -> Result<Json<Vec<TxRecord>>, ApiError> {
+    Ok(Json(view::txs(&a.store, q.status).map_err(internal)?))
+}
+
+async fn show<B: StorageBackend>(State(a): State<Arc<Admin<B>>>, Path(id): Path<String>) -> Result<Json<ActivityDetail>, ApiError> {
+    Ok(Json(a.existing(&id)?))
+}
+
+async fn cancel<B: StorageBackend>(State(a): State<Arc<Admin<B>>>, Path(id): Path<String>) -> Result<Json<ActivityDetail>, ApiError> {
+    Ok(Json(a.cancel(&id)?))
+}
+
+async fn retry<B: StorageBackend>(State(a): State<Arc<Admin<B>>>, Path(id): Path<String>) -> Result<Json<Option<ActivityDetail>>, ApiError> {
+    Ok(Json(a.retry(&id)?))
+}
+
+async fn fail<B: StorageBackend>(
+    State(a): State<Arc<Admin<B>>>,
+    Path(id): Path<String>,
+    req: Option<Json<FailRequest>>,
+) -> Result<Json<ActivityDetail>, ApiError> {
+    let Json(req) = req.unwrap_or_default();
+    Ok(Json(a.fail(&id, req.reason)?))
+}
+
+async fn queues<B: StorageBackend>(State(a): State<Arc<Admin<B>>>) -> Result<Json<Vec<QueueStatus>>, ApiError> {
+    Ok(Json(a.queue_statuses().map_err(internal)?))
+}
+
+async fn pause<B: StorageBackend>(State(a): State<Arc<Admin<B>>>, Path(name): Path<String>) -> Result<Json<Vec<QueueStatus>>, ApiError> {
+    Ok(Json(a.set_paused(&name, true)?))
+}
+
+async fn resume<B: StorageBackend>(State(a): State<Arc<Admin<B>>>, Path(name): Path<String>) -> Result<Json<Vec<QueueStatus>>, ApiError> {
+    Ok(Json(a.set_paused(&name, false)?))
+}
+
+async fn reconcile<B: StorageBackend>(State(a): State<Arc<Admin<B>>>) -> Result<Json<ReconcileReport>, ApiError> {
+    Ok(Json(a.reconcile().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use std::time::Duration;
+    use subnet_wcp_persistence::backend::MemBackend;
+    use subnet_wcp_persistence::records::ClaimJob;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "s3cret";
+
+    fn router(store: &KvStore<MemBackend>) -> Router {
+        let scheduler = SchedulerConfig {
+            poll_interval: Duration::from_secs(1),
+            max_inflight: 4,
+            queue_name: Some("video".into()),
+            queues: Vec::new(),
+            policy: Default::default(),
+        };
+        let (_, settings) = watch::channel(scheduler);
+        let (reconcile, _) = mpsc::channel(1);
+        Admin::new(store.clone(), TOKEN.into(), settings, reconcile).router()
+    }
+
+    async fn call(router: &Router, method: &str, uri: &str, token: Option<&str>) -> (StatusCode, serde_json::Value) {
+        let mut req = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let resp = router.clone().oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+    }
+
+    fn done(store: &KvStore<MemBackend>, activity_id: &str, outcome: DoneOutcome) {
+        let rec = DoneRecord {
+            activity_id: activity_id.into(),
+            outcome,
+            result_ref: None,
+            error: None,
+            finished_at_ms: 0,
+            instance_id: Some("0xaa".into()),
+            queue: Some("video".into()),
+        };
+        store.put_record(keys::done(activity_id), &rec).unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_without_the_token_are_rejected() {
+        let store = KvStore::in_memory();
+        let r = router(&store);
+        assert_eq!(call(&r, "GET", "/admin/activities", None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(call(&r, "GET", "/admin/activities", Some("wrong")).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(call(&r, "GET", "/admin/activities", Some("s3cre")).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(call(&r, "POST", "/admin/queues/video/pause", None).await.0, StatusCode::UNAUTHORIZED);
+        assert!(store.get(keys::paused_queue("video")).unwrap().is_none());
+        assert_eq!(call(&r, "GET", "/admin/activities", Some(TOKEN)).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn retry_restores_the_queue_and_refuses_finished_activities() {
+        let store = KvStore::in_memory();
+        let r = router(&store);
+        done(&store, "0x01", DoneOutcome::Failed);
+        done(&store, "0x02", DoneOutcome::Ok);
+        done(&store, "0x03", DoneOutcome::Expired);
+
+        let (status, body) = call(&r, "POST", "/admin/activities/0x01/retry", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["state"], "inflight");
+        let rec: InflightRecord = store.get_record(keys::inflight("0x01")).unwrap().unwrap();
+        assert_eq!((rec.queue.as_str(), rec.assignment_status), ("video", AssignmentStatus::Pending));
+
+        for id in ["0x02", "0x03"] {
+            assert_eq!(call(&r, "POST", &format!("/admin/activities/{}/retry", id), Some(TOKEN)).await.0, StatusCode::CONFLICT);
+            assert!(store.get(keys::done(id)).unwrap().is_some());
+        }
+        assert_eq!(call(&r, "POST", "/admin/activities/0x09/retry", Some(TOKEN)).await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn failed_activities_keep_the_operator_outcome() {
+        let store = KvStore::in_memory();
+        let r = router(&store);
+        let rec = InflightRecord {
+            activity_id: "0x01".into(),
+            queue: "video".into(),
+            claimed_at_ms: 0,
+            assignment_status: AssignmentStatus::Running,
+            instance_id: None,
+            expires_at_ms: None,
+            inputs: Vec::new(),
+            upload_prefix: None,
+        };
+        store.put_record(keys::inflight("0x01"), &rec).unwrap();
+
+        let (status, body) = call(&r, "POST", "/admin/activities/0x01/fail", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((body["done"]["outcome"].as_str(), body["done"]["queue"].as_str()), (Some("failed"), Some("video")));
+
+        // The Assigner retires through the same check, so its later Ok does not overwrite the operator's.
+        let failed: DoneRecord = store.get_record(keys::done("0x01")).unwrap().unwrap();
+        let mut batch = Batch::new();
+        batch.put_record(keys::done("0x01"), &DoneRecord { outcome: DoneOutcome::Ok, ..failed.clone() }).unwrap();
+        assert!(!store.write_if_absent(keys::done("0x01"), batch).unwrap());
+        assert_eq!(store.get_record::<DoneRecord>(keys::done("0x01")).unwrap(), Some(failed));
+        assert_eq!(call(&r, "POST", "/admin/activities/0x01/fail", Some(TOKEN)).await.0, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn pauses_persist_and_only_configured_queues_can_be_paused() {
+        let store = KvStore::in_memory();
+        let job = ClaimJob { activity_id: "0x01".into(), queue_name: "video".into(), created_at_ms: 0, partition: 0, expires_at_ms: None };
+        store.put_record(keys::claim_job("0x01"), &job).unwrap();
+
+        let (status, body) = call(&router(&store), "POST", "/admin/queues/video/pause", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["paused"], true);
+        // A fresh admin API over the same store (as after a restart) still sees the pause.
+        let r = router(&store);
+        let (_, body) = call(&r, "GET", "/admin/queues", Some(TOKEN)).await;
+        assert_eq!(body[0]["paused"], true);
+        let paused_at = body[0]["paused_at_ms"].clone();
+        // Pausing again keeps the original timestamp.
+        let (_, body) = call(&r, "POST", "/admin/queues/video/pause", Some(TOKEN)).await;
+        assert_eq!(body[0]["paused_at_ms"], paused_at);
+
+        let (_, body) = call(&r, "POST", "/admin/queues/video/resume", Some(TOKEN)).await;
+        assert_eq!(body[0]["paused"], false);
+        assert!(store.get(keys::paused_queue("video")).unwrap().is_none());
+        assert_eq!(call(&r, "POST", "/admin/queues/audio/pause", Some(TOKEN)).await.0, StatusCode::NOT_FOUND);
+    }
+}
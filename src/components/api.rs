@@ -0,0 +1,137 @@
+//! WCP HTTP API.
+//!
+//! `GET /metrics` serves the Prometheus registry.
+//!
+//! `POST /storage/refresh` lets the WEP re-presign a URL for an activity it is
+//! running: `{"activity_id", "input": name}` for one of the assignment's inputs
+//! or `{"activity_id", "upload": name}` for an object under its `upload_prefix`.
+//! It is unauthenticated: the WEP runs on the same host, so `api.listen` must
+//! stay on loopback (config validation refuses anything else, even with an
+//! `api.admin_token`, which only guards `/admin/*`).
+use anyhow::Result;
+use axum::extract::{Json, State};
+use axum::http::StatusCode;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
+use subnet_wcp_persistence::records::InflightRecord;
+use subnet_wcp_persistence::{keys, KvStore};
+use subnet_wcp_storage::{PresignedUrl, StorageBroker};
+use tracing::{info, warn};
+
+#[derive(Deserialize)]
+struct RefreshRequest {
+    activity_id: String,
+    #[serde(default)]
+    input: Option<String>,
+    #[serde(default)]
+    upload: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RefreshedUrl {
+    url: String,
+    key: String,
+    method: String,
+    expires_at_unix: i64,
+}
+
+impl From<PresignedUrl> for RefreshedUrl {
+    fn from(u: PresignedUrl) -> Self {
+        Self { url: u.url, key: u.key, method: u.method, expires_at_unix: u.expires_at_unix }
+    }
+}
+
+type ApiError = (StatusCode, String);
+
+pub struct Api<B: StorageBackend = RocksBackend> {
+    state: ApiState<B>,
+    /// Routes served alongside the API's own (e.g. health probes).
+    extra: Router,
+}
+
+/// What the API's own handlers need.
+struct ApiState<B: StorageBackend> {
+    store: KvStore<B>,
+    storage: Option<StorageBroker>,
+}
+
+impl<B: StorageBackend> Clone for ApiState<B> {
+    fn clone(&self) -> Self {
+        Self { store: self.store.clone(), storage: self.storage.clone() }
+    }
+}
+
+impl<B: StorageBackend> Api<B> {
+    pub fn new(store: KvStore<B>, storage: Option<StorageBroker>) -> Self {
+        Self { state: ApiState { store, storage }, extra: Router::new() }
+    }
+
+    pub fn with_routes(mut self, routes: Router) -> Self {
+        self.extra = self.extra.merge(routes);
+        self
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/metrics", get(metrics))
+            .route("/storage/refresh", post(refresh::<B>))
+            .with_state(self.state.clone())
+            .merge(self.extra.clone())
+    }
+
+    pub async fn run(self, addr: SocketAddr) -> Result<()> {
+        info!(%addr, "api listening");
+        axum::Server::bind(&addr).serve(self.router().into_make_service()).await?;
+        Ok(())
+    }
+}
+
+impl<B: StorageBackend> ApiState<B> {
+    async fn refresh(&self, req: RefreshRequest) -> Result<PresignedUrl, ApiError> {
+        let storage = self.storage.as_ref().ok_or((StatusCode::SERVICE_UNAVAILABLE, "no storage broker configured".to_string()))?;
+        let rec = self
+            .store
+            .get_record::<InflightRecord>(keys::inflight(&req.activity_id))
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, format!("activity {} is not inflight", req.activity_id)))?;
+
+        let presigned = match (req.input.as_deref(), req.upload.as_deref()) {
+            (Some(name), None) => {
+                let input = rec
+                    .inputs
+                    .iter()
+                    .find(|i| i.name == name && subnet_wcp_storage::needs_presign(&i.reference))
+                    .ok_or((StatusCode::BAD_REQUEST, format!("no presignable input {:?} in the assignment", name)))?;
+                storage.get_presigned_download(&input.reference, &req.activity_id).await
+            }
+            (None, Some(name)) => {
+                let prefix = rec.upload_prefix.as_deref().ok_or((StatusCode::BAD_REQUEST, "activity has no upload prefix".to_string()))?;
+                if !subnet_wcp_storage::is_relative_name(name) {
+                    return Err((StatusCode::BAD_REQUEST, format!("upload name {:?} escapes the upload prefix", name)));
+                }
+                storage.get_presigned_upload(prefix, name, &req.activity_id).await
+            }
+            _ => return Err((StatusCode::BAD_REQUEST, "set exactly one of `input` or `upload`".to_string())),
+        };
+        presigned.map_err(|e| {
+            warn!(activity_id = %req.activity_id, error = %e, "presign refresh failed");
+            (StatusCode::BAD_GATEWAY, e.to_string())
+        })
+    }
+}
+
+async fn metrics() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], subnet_wcp_metrics::gather())
+}
+
+async fn refresh<B: StorageBackend>(State(api): State<ApiState<B>>, Json(req): Json<RefreshRequest>) -> Result<Json<RefreshedUrl>, ApiError> {
+    let activity_id = req.activity_id.clone();
+    let url = api.refresh(req).await?;
+    info!(%activity_id, key = %url.key, method = %url.method, "refreshed presigned URL");
+    Ok(Json(url.into()))
+}
@@ -1,12 +1,22 @@
 use anyhow::Result;
-use tracing::{info, error};
-use subnet_wcp_persistence::{KvStore, keys};
+use tracing::{info, error, warn, Instrument, Span};
+use subnet_wcp_persistence::{Batch, KvStore, keys};
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
+use subnet_wcp_persistence::records::{AssignedInput, AssignmentStatus, BroadcastJob, DoneOutcome, DoneRecord, InflightRecord};
 use serde::{Serialize, Deserialize};
-use reqwest::Client;
-use std::time::Duration;
+use reqwest::{Client, RequestBuilder};
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use subnet_wcp_storage::StorageBroker;
+use subnet_wcp_metrics as metrics;
+use subnet_wcp_telemetry::activity_span;
+use subnet_wcp_config::SchedulerConfig;
+use tokio::sync::watch;
 
 const DEFAULT_TASK_KIND: &str = "video.preprocess";
 const DEFAULT_TASK_VERSION: &str = "1.0.0";
+/// Object the WEP uploads its result to, under the assignment's `upload_prefix`.
+const RESULT_OBJECT: &str = "result.json";
 
 #[derive(Serialize)]
 struct InputDescriptor {
@@ -43,6 +53,8 @@ struct TaskAssignment {
     upload_prefix: String,
     soft_deadline_unix: i64,
     heartbeat_interval_s: i32,
+    /// Presigned PUT for `{upload_prefix}/result.json`; empty without a storage broker.
+    upload_url: String,
 }
 
 #[derive(Deserialize)]
@@ -57,17 +69,24 @@ struct TaskStatus {
     progress: u32,
     result_ref: Option<String>,
     error: Option<String>,
+    /// Optional integrity claims about `result_ref`, checked against storage.
+    #[serde(default)]
+    result_size: Option<u64>,
+    #[serde(default)]
+    result_sha256: Option<String>,
 }
 
-pub struct Assigner {
-    store: KvStore,
+pub struct Assigner<B: StorageBackend = RocksBackend> {
+    store: KvStore<B>,
     wep_endpoint: String,
     max_inflight: usize,
     client: Client,
+    storage: Option<StorageBroker>,
+    reload: Option<watch::Receiver<SchedulerConfig>>,
 }
 
-impl Assigner {
-    pub fn new(store: KvStore, wep_endpoint: String, max_inflight: usize) -> Self {
+impl<B: StorageBackend> Assigner<B> {
+    pub fn new(store: KvStore<B>, wep_endpoint: String, max_inflight: usize) -> Self {
         let client = Client::builder()
             .timeout(Duration::from_secs(30))
             .build()
@@ -78,20 +97,33 @@ impl Assigner {
             wep_endpoint, 
             max_inflight,
             client,
+            storage: None,
+            reload: None,
         }
     }
 
+    /// Resolve input refs and issue upload URLs through the subnet-api storage broker.
+    pub fn with_storage(mut self, storage: StorageBroker) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Follow `scheduler.max_inflight` across config reloads.
+    pub fn with_reload(mut self, settings: watch::Receiver<SchedulerConfig>) -> Self {
+        self.reload = Some(settings);
+        self
+    }
+
     pub async fn run(&self) -> Result<()> {
         loop {
+            let max_inflight = self.reload.as_ref().map_or(self.max_inflight, |s| s.borrow().max_inflight);
             let jobs = self.store.scan_prefix("inflight:")?;
-            let mut in_progress = 0usize;
-            for (k, _v) in jobs {
-                if in_progress >= self.max_inflight { break; }
+            for (k, _v) in jobs.into_iter().take(max_inflight) {
                 let key = String::from_utf8_lossy(&k).to_string();
-                if let Err(e) = self.handle_one_job(key).await {
-                    error!(error=%e, "Error handling job");
+                let span = activity_span("assign", key.trim_start_matches("inflight:"));
+                if let Err(e) = self.handle_one_job(key).instrument(span.clone()).await {
+                    span.in_scope(|| error!(error=%e, "Error handling job"));
                 }
-                in_progress += 1;
             }
             tokio::time::sleep(Duration::from_millis(500)).await;
         }
@@ -104,22 +136,26 @@ impl Assigner {
         if std::env::var("DEV_MOCK_ASSIGNER").is_ok() {
             let activity_id = key.replacen("inflight:", "", 1);
             info!(activity_id=%activity_id, "dev-mode: direct SUCCESS completion without WEP");
-            let claim_key = keys::broadcast_complete(&activity_id);
-            self.store.put(claim_key, b"1")?;
-            let done_key = keys::done(&activity_id);
-            self.store.put(done_key, b"ok")?;
-            self.store.delete(keys::inflight(&activity_id).as_bytes())?;
+            let queue = self.store.get_record::<InflightRecord>(&key)?.map(|r| r.queue).unwrap_or_default();
+            let mut batch = Batch::new();
+            batch
+                .put_record(keys::broadcast_complete(&activity_id), &broadcast_job(&activity_id))?
+                .put_record(keys::done(&activity_id), &done_record(&activity_id, &queue, DoneOutcome::Ok))?
+                .delete(keys::inflight(&activity_id));
+            self.retire(&activity_id, batch)?;
             return Ok(());
         }
 
         let activity_id = key.replacen("inflight:", "", 1);
         let instance_id = "0xdeadbeef".to_string();
+        let run_id = "run-1".to_string();
+        Span::current().record("instance_id", instance_id.as_str()).record("run_id", run_id.as_str());
         
         // Create task assignment
-        let assignment = TaskAssignment {
+        let mut assignment = TaskAssignment {
             activity_id: activity_id.clone(),
             workflow_instance_id: instance_id.clone(),
-            run_id: "run-1".into(),
+            run_id,
             task_kind: DEFAULT_TASK_KIND.into(),
             task_version: DEFAULT_TASK_VERSION.into(),
             inputs: vec![InputDescriptor { 
@@ -129,15 +165,40 @@ impl Assigner {
                 inline_json: String::new(), 
                 inline_bytes: Vec::new() 
             }],
-            upload_prefix: "workflows/demo/preprocess".into(),
+            upload_prefix: format!("workflows/{}/{}", instance_id, activity_id),
             soft_deadline_unix: 0,
             heartbeat_interval_s: 10,
+            upload_url: String::new(),
         };
+
+        // Record what the WEP gets before it can ask for URL refreshes, keeping what the Broadcaster recorded at claim time
+        let inflight_key = keys::inflight(&activity_id);
+        let mut inflight = self.store.get_record::<InflightRecord>(&inflight_key)?.unwrap_or_else(|| InflightRecord {
+            activity_id: activity_id.clone(),
+            queue: String::new(),
+            claimed_at_ms: Utc::now().timestamp_millis(),
+            assignment_status: AssignmentStatus::Pending,
+            instance_id: None,
+            expires_at_ms: None,
+            inputs: Vec::new(),
+            upload_prefix: None,
+        });
+        Span::current().record("queue", inflight.queue.as_str());
+        inflight.inputs = assignment
+            .inputs
+            .iter()
+            .map(|i| AssignedInput { name: i.name.clone(), reference: i.reference.clone() })
+            .collect();
+        inflight.upload_prefix = Some(assignment.upload_prefix.clone());
+        if !self.update_inflight(&inflight)? {
+            return Ok(());
+        }
+
+        self.presign(&mut assignment).await?;
         
         // Send task assignment to WEP
         let assign_url = format!("{}/tasks/{}/assign", self.wep_endpoint, activity_id);
-        let response = self.client
-            .post(&assign_url)
+        let response = traced(self.client.post(&assign_url))
             .json(&assignment)
             .send()
             .await?;
@@ -151,30 +212,35 @@ impl Assigner {
                 error=%error_text,
                 "Failed to assign task to WEP"
             );
+            metrics::ASSIGNMENTS.with_label_values(&["error"]).inc();
             return Ok(());
         }
         
-        let _task_response: TaskResponse = response.json().await?;
-        info!(activity_id=%activity_id, "Task assigned to WEP");
+        let task_response: TaskResponse = response.json().await?;
+        let assigned_at = Instant::now();
+        info!(
+            activity_id=%activity_id,
+            task_id=%task_response.task_id,
+            message=%task_response.message,
+            "Task assigned to WEP"
+        );
         
         // Update inflight status
-        let inflight_key = keys::inflight(&activity_id);
-        let inflight_val = format!(
-            "{{\"activity_id\":\"{}\",\"instance_id\":\"{}\",\"wep_status\":\"Running\"}}", 
-            activity_id, 
-            instance_id
-        );
-        self.store.put(inflight_key.as_bytes(), inflight_val.as_bytes())?;
+        inflight.assignment_status = AssignmentStatus::Running;
+        inflight.instance_id = Some(instance_id.clone());
+        if !self.update_inflight(&inflight)? {
+            return Ok(());
+        }
         
         // Poll for completion
         let status_url = format!("{}/tasks/{}/status", self.wep_endpoint, activity_id);
         let mut completed = false;
-        let deadline = std::time::Instant::now() + Duration::from_secs(60);
+        let deadline = Instant::now() + Duration::from_secs(60);
         
-        while !completed && std::time::Instant::now() < deadline {
+        while !completed && Instant::now() < deadline {
             tokio::time::sleep(Duration::from_secs(2)).await;
             
-            let response = self.client.get(&status_url).send().await?;
+            let response = traced(self.client.get(&status_url)).send().await?;
             if !response.status().is_success() {
                 error!(activity_id=%activity_id, "Failed to get task status");
                 break;
@@ -190,33 +256,54 @@ impl Assigner {
             
             match status.status.as_str() {
                 "completed" => {
-                    // Task completed successfully
-                    let claim_key = keys::broadcast_complete(&activity_id);
-                    self.store.put(claim_key, b"1")?;
-                    let resume_key = keys::broadcast_resume(&instance_id);
-                    self.store.put(resume_key, b"1")?;
-                    let done_key = keys::done(&activity_id);
-                    self.store.put(done_key, b"ok")?;
-                    self.store.delete(keys::inflight(&activity_id).as_bytes())?;
-                    
-                    info!(
-                        activity_id=%activity_id,
-                        result_ref=%status.result_ref.as_deref().unwrap_or(""),
-                        "Task completed successfully"
-                    );
+                    // Never post a reference to an artifact that is not in storage
+                    let rejected = match self.check_result(&activity_id, inflight.upload_prefix.as_deref(), &status).await {
+                        Ok(rejected) => rejected,
+                        Err(e) => {
+                            warn!(activity_id=%activity_id, error=%e, "Could not verify task result; retrying");
+                            continue;
+                        }
+                    };
+                    if let Some(reason) = rejected {
+                        self.fail(&activity_id, &inflight.queue, &instance_id, Some(format!("result verification failed: {}", reason)))?;
+                        error!(activity_id=%activity_id, reason=%reason, "Task result rejected");
+                        observe_assignment("rejected", assigned_at);
+                        completed = true;
+                        continue;
+                    }
+
+                    // Task completed successfully: queue chain follow-ups and retire inflight atomically
+                    let done = DoneRecord {
+                        result_ref: status.result_ref.clone(),
+                        instance_id: Some(instance_id.clone()),
+                        ..done_record(&activity_id, &inflight.queue, DoneOutcome::Ok)
+                    };
+                    let mut batch = Batch::new();
+                    batch
+                        .put_record(keys::broadcast_complete(&activity_id), &broadcast_job(&activity_id))?
+                        .put_record(keys::broadcast_resume(&instance_id), &broadcast_job(&instance_id))?
+                        .put_record(keys::done(&activity_id), &done)?
+                        .delete(keys::inflight(&activity_id));
+                    if self.retire(&activity_id, batch)? {
+                        info!(
+                            activity_id=%activity_id,
+                            result_ref=%status.result_ref.as_deref().unwrap_or(""),
+                            "Task completed successfully"
+                        );
+                        observe_assignment("ok", assigned_at);
+                    }
                     completed = true;
                 }
                 "failed" => {
                     // Task failed
-                    let done_key = keys::done(&activity_id);
-                    self.store.put(done_key, b"failed")?;
-                    self.store.delete(keys::inflight(&activity_id).as_bytes())?;
+                    self.fail(&activity_id, &inflight.queue, &instance_id, status.error.clone())?;
                     
                     error!(
                         activity_id=%activity_id,
                         error=%status.error.as_deref().unwrap_or(""),
                         "Task failed"
                     );
+                    observe_assignment("failed", assigned_at);
                     completed = true;
                 }
                 _ => {
@@ -228,11 +315,211 @@ impl Assigner {
         if !completed {
             // Timeout - mark as failed
             error!(activity_id=%activity_id, "Task timed out");
-            let done_key = keys::done(&activity_id);
-            self.store.put(done_key, b"timeout")?;
-            self.store.delete(keys::inflight(&activity_id).as_bytes())?;
+            let done = DoneRecord { instance_id: Some(instance_id.clone()), ..done_record(&activity_id, &inflight.queue, DoneOutcome::Timeout) };
+            let mut batch = Batch::new();
+            batch
+                .put_record(keys::done(&activity_id), &done)?
+                .delete(keys::inflight(&activity_id));
+            if self.retire(&activity_id, batch)? {
+                observe_assignment("timeout", assigned_at);
+            }
         }
         
         Ok(())
     }
+
+    /// Retire the activity as failed; no completion is broadcast.
+    fn fail(&self, activity_id: &str, queue: &str, instance_id: &str, error: Option<String>) -> Result<()> {
+        let done = DoneRecord {
+            error,
+            instance_id: Some(instance_id.to_string()),
+            ..done_record(activity_id, queue, DoneOutcome::Failed)
+        };
+        let mut batch = Batch::new();
+        batch
+            .put_record(keys::done(activity_id), &done)?
+            .delete(keys::inflight(activity_id));
+        self.retire(activity_id, batch).map(|_| ())
+    }
+
+    /// Write `batch` (which adds `done:{activity_id}`) unless the activity is
+    /// already done, e.g. failed by an operator while the WEP was running.
+    /// `false` if the earlier outcome stands; its `inflight:` record is still removed.
+    fn retire(&self, activity_id: &str, batch: Batch) -> Result<bool> {
+        let written = self.store.write_if_absent(keys::done(activity_id), batch)?;
+        if let Some(storage) = &self.storage {
+            storage.forget_activity(activity_id);
+        }
+        if !written {
+            warn!(activity_id=%activity_id, "Activity already finished locally; dropping WEP outcome");
+            self.drop_finished(activity_id)?;
+        }
+        Ok(written)
+    }
+
+    /// Save `rec` unless the activity finished meanwhile (operator fail/cancel,
+    /// reconciler expiry), which would otherwise bring `inflight:` back.
+    /// `false` if it did; the caller stops handling it.
+    fn update_inflight(&self, rec: &InflightRecord) -> Result<bool> {
+        let mut batch = Batch::new();
+        batch.put_record(keys::inflight(&rec.activity_id), rec)?;
+        let written = self.store.write_if_absent(keys::done(&rec.activity_id), batch)?;
+        if !written {
+            warn!(activity_id=%rec.activity_id, "Activity finished while being assigned; leaving it");
+            if let Some(storage) = &self.storage {
+                storage.forget_activity(&rec.activity_id);
+            }
+            self.drop_finished(&rec.activity_id)?;
+        }
+        Ok(written)
+    }
+
+    /// Delete `inflight:{activity_id}` if `done:` is there, so a finished
+    /// activity is not picked up again. An `inflight:` recreated by a retry
+    /// (which removes `done:`) is kept.
+    fn drop_finished(&self, activity_id: &str) -> Result<()> {
+        let mut batch = Batch::new();
+        batch.delete(keys::inflight(activity_id));
+        self.store.write_if_present(keys::done(activity_id), batch).map(|_| ())
+    }
+
+    /// Why the WEP's reported result is unacceptable, or `None`. Without a
+    /// storage broker results are trusted as before.
+    async fn check_result(&self, activity_id: &str, upload_prefix: Option<&str>, status: &TaskStatus) -> Result<Option<String>> {
+        let Some(storage) = &self.storage else { return Ok(None) };
+        let Some(result_ref) = status.result_ref.as_deref().filter(|r| !r.is_empty()) else {
+            return Ok(Some("WEP reported no result_ref".to_string()));
+        };
+        let Some(upload_prefix) = upload_prefix else {
+            return Ok(Some("assignment has no upload prefix".to_string()));
+        };
+        storage
+            .verify_result(activity_id, upload_prefix, result_ref, status.result_size, status.result_sha256.as_deref())
+            .await
+    }
+
+    /// Swap object refs for presigned GET URLs and add the result upload URL.
+    /// Without a broker the assignment goes out unchanged.
+    async fn presign(&self, assignment: &mut TaskAssignment) -> Result<()> {
+        let Some(storage) = &self.storage else { return Ok(()) };
+        for input in &mut assignment.inputs {
+            if subnet_wcp_storage::needs_presign(&input.reference) {
+                let presigned = storage.get_presigned_download(&input.reference, &assignment.activity_id).await?;
+                input.reference = presigned.url;
+            }
+        }
+        let upload = storage.get_presigned_upload(&assignment.upload_prefix, RESULT_OBJECT, &assignment.activity_id).await?;
+        assignment.upload_url = upload.url;
+        Ok(())
+    }
+}
+
+fn observe_assignment(outcome: &str, assigned_at: Instant) {
+    metrics::ASSIGNMENTS.with_label_values(&[outcome]).inc();
+    metrics::ASSIGNMENT_SECONDS.with_label_values(&[outcome]).observe(assigned_at.elapsed().as_secs_f64());
+}
+
+fn done_record(activity_id: &str, queue: &str, outcome: DoneOutcome) -> DoneRecord {
+    DoneRecord {
+        activity_id: activity_id.to_string(),
+        outcome,
+        result_ref: None,
+        error: None,
+        finished_at_ms: Utc::now().timestamp_millis(),
+        instance_id: None,
+        queue: Some(queue.to_string()).filter(|q| !q.is_empty()),
+    }
+}
+
+/// Carry the current span's W3C trace context, so WEP spans join the activity's trace.
+fn traced(req: RequestBuilder) -> RequestBuilder {
+    subnet_wcp_telemetry::trace_context().into_iter().fold(req, |req, (k, v)| req.header(k, v))
+}
+
+fn broadcast_job(id: &str) -> BroadcastJob {
+    BroadcastJob { id: id.to_string(), created_at_ms: Utc::now().timestamp_millis() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Path, State};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use subnet_wcp_persistence::backend::MemBackend;
+
+    /// A WEP whose assign call lets an operator fail the activity before it answers.
+    fn wep_failed_by_operator_during_assign(store: KvStore<MemBackend>) -> String {
+        async fn assign(State(store): State<KvStore<MemBackend>>, Path(id): Path<String>) -> Json<serde_json::Value> {
+            let done = DoneRecord { error: Some("operator".into()), ..done_record(&id, "video", DoneOutcome::Failed) };
+            let mut batch = Batch::new();
+            batch.put_record(keys::done(&id), &done).unwrap().delete(keys::inflight(&id));
+            store.write(batch).unwrap();
+            Json(serde_json::json!({ "message": "accepted", "task_id": id }))
+        }
+        async fn status() -> Json<serde_json::Value> {
+            Json(serde_json::json!({ "status": "completed", "progress": 100, "result_ref": "r2://bucket/out" }))
+        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new()
+            .route("/tasks/:id/assign", post(assign))
+            .route("/tasks/:id/status", get(status))
+            .with_state(store);
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        url
+    }
+
+    fn inflight(activity_id: &str) -> InflightRecord {
+        InflightRecord {
+            activity_id: activity_id.into(),
+            queue: "video".into(),
+            claimed_at_ms: 0,
+            assignment_status: AssignmentStatus::Pending,
+            instance_id: None,
+            expires_at_ms: None,
+            inputs: Vec::new(),
+            upload_prefix: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_an_activity_during_assign_retires_it() {
+        let store = KvStore::in_memory();
+        store.put_record(keys::inflight("a1"), &inflight("a1")).unwrap();
+        let assigner = Assigner::new(store.clone(), wep_failed_by_operator_during_assign(store.clone()), 4);
+
+        assigner.handle_one_job(keys::inflight("a1")).await.unwrap();
+
+        assert!(store.get(keys::inflight("a1")).unwrap().is_none(), "the Running update must not bring inflight back");
+        let done = store.get_record::<DoneRecord>(keys::done("a1")).unwrap().unwrap();
+        assert_eq!((done.outcome, done.error.as_deref()), (DoneOutcome::Failed, Some("operator")));
+        assert!(store.get(keys::broadcast_complete("a1")).unwrap().is_none());
+    }
+
+    #[test]
+    fn a_finished_activity_loses_its_inflight_record_but_keeps_its_outcome() {
+        let store = KvStore::in_memory();
+        let assigner = Assigner::new(store.clone(), String::new(), 4);
+        let done = DoneRecord { error: Some("operator".into()), ..done_record("a1", "video", DoneOutcome::Failed) };
+        store.put_record(keys::done("a1"), &done).unwrap();
+        // What the stale Running write used to leave behind
+        store.put_record(keys::inflight("a1"), &inflight("a1")).unwrap();
+
+        let mut batch = Batch::new();
+        batch
+            .put_record(keys::broadcast_complete("a1"), &broadcast_job("a1"))
+            .unwrap()
+            .put_record(keys::done("a1"), &done_record("a1", "video", DoneOutcome::Ok))
+            .unwrap()
+            .delete(keys::inflight("a1"));
+        assert!(!assigner.retire("a1", batch).unwrap());
+        assert!(store.get(keys::inflight("a1")).unwrap().is_none());
+        assert!(store.get(keys::broadcast_complete("a1")).unwrap().is_none());
+        assert_eq!(store.get_record::<DoneRecord>(keys::done("a1")).unwrap().unwrap().outcome, DoneOutcome::Failed);
+
+        // Nothing to update once done
+        assert!(!assigner.update_inflight(&inflight("a1")).unwrap());
+        assert!(store.get(keys::inflight("a1")).unwrap().is_none());
+    }
 }
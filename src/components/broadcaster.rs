@@ -1,24 +1,32 @@
 use anyhow::Result;
-use tracing::info;
-use subnet_wcp_persistence::KvStore;
+use tracing::{info, warn, Instrument, Span};
+use subnet_wcp_metrics as metrics;
+use subnet_wcp_telemetry::{activity_span, workflow_span};
+use subnet_wcp_persistence::{Batch, KvStore};
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
 use subnet_wcp_persistence::keys;
+use subnet_wcp_persistence::records::{AssignmentStatus, ClaimJob, InflightRecord, TxKind, TxRecord, TxStatus};
 use alloy::providers::Provider;
 use alloy::primitives::{Address, B256, hex};
 use alloy_sol_types::sol;
-use serde::{Deserialize, Serialize};
 use chrono::Utc;
+use subnet_wcp_config::TxPolicyConfig;
+use tokio::sync::watch;
 
 #[derive(Clone)]
-pub struct Broadcaster<P: Provider + Clone + Send + Sync + 'static> {
-    store: KvStore,
+pub struct Broadcaster<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend = RocksBackend> {
+    store: KvStore<B>,
     provider: P,
     task_queue: Address,
+    #[allow(dead_code)] // used once resumeWorkflow is wired
     workflow_engine: Address,
+    /// Current `[tx_policy]`, following config reloads.
+    tx_policy: watch::Receiver<TxPolicyConfig>,
 }
 
-impl<P: Provider + Clone + Send + Sync + 'static> Broadcaster<P> {
-    pub fn new(store: KvStore, provider: P, task_queue: Address, workflow_engine: Address) -> Self {
-        Self { store, provider, task_queue, workflow_engine }
+impl<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend> Broadcaster<P, B> {
+    pub fn new(store: KvStore<B>, provider: P, task_queue: Address, workflow_engine: Address, tx_policy: watch::Receiver<TxPolicyConfig>) -> Self {
+        Self { store, provider, task_queue, workflow_engine, tx_policy }
     }
 
     pub async fn run(&self) -> Result<()> {
@@ -40,88 +48,127 @@ impl<P: Provider + Clone + Send + Sync + 'static> Broadcaster<P> {
         let _tq = TaskQueue::new(self.task_queue, self.provider.clone());
 
         // Dev: record placeholder tx and assume instant confirmation
-        let tx_rec = serde_json::json!({
-            "activity_id": activity_id,
-            "kind": "claim",
-            "tx_hash": "0xdevmock",
-            "submitted_at_ms": Utc::now().timestamp_millis(),
-        });
-        self.store.put(keys::tx(activity_id), serde_json::to_vec(&tx_rec)?)?;
-        info!(%activity_id, "claim submitted (dev-mock)");
+        let now = Utc::now().timestamp_millis();
+        let tx_rec = TxRecord {
+            activity_id: activity_id.to_string(),
+            kind: TxKind::Claim,
+            status: TxStatus::Confirmed,
+            tx_hash: Some("0xdevmock".into()),
+            submitted_at_ms: now,
+            last_bump_at_ms: None,
+            nonce: None,
+        };
 
         // On confirm: move to inflight and remove claim job/tx entry as needed
-        let inflight = serde_json::json!({
-            "activity_id": activity_id,
-            "queue": "", // unknown here; poller/assigner can enrich
-            "claimed_at_ms": Utc::now().timestamp_millis(),
-            "assignment_status": "Pending",
-        });
-        self.store.put(keys::inflight(activity_id), serde_json::to_vec(&inflight)?)?;
-        self.store.delete(keys::broadcast_claim(activity_id).as_bytes())?;
+        let queue = self.store.get_record::<ClaimJob>(keys::claim_job(activity_id))?.map(|j| j.queue_name).unwrap_or_default();
+        Span::current().record("queue", queue.as_str());
+        let inflight = InflightRecord {
+            activity_id: activity_id.to_string(),
+            queue,
+            claimed_at_ms: now,
+            assignment_status: AssignmentStatus::Pending,
+            instance_id: None,
+            expires_at_ms: None,
+            inputs: Vec::new(),
+            upload_prefix: None,
+        };
+        // tx record, inflight and claim intent removal land together
+        let mut batch = Batch::new();
+        batch
+            .put_record(keys::tx(activity_id), &tx_rec)?
+            .put_record(keys::inflight(activity_id), &inflight)?
+            .delete(keys::broadcast_claim(activity_id))
+            .delete(keys::claim_job(activity_id));
         // keep tx record for audit; optionally remove/comment below
-        // self.store.delete(keys::tx(activity_id).as_bytes())?;
-
-        Ok(())
-    }
-
-    async fn send_complete(&self, activity_id: &str) -> Result<()> {
-        info!(%activity_id, "broadcast complete (stub)");
-        // TODO: build and submit completeActivity tx via provider + wallet
-        self.store.delete(keys::broadcast_complete(activity_id).as_bytes())?;
-        Ok(())
-    }
+        // batch.delete(keys::tx(activity_id));
+        self.store.write(batch)?;
+        metrics::CLAIMS.with_label_values(&["won"]).inc();
+        metrics::tx_confirmed("claim", tx_rec.submitted_at_ms, now, None);
+        info!(%activity_id, "claim submitted (dev-mock)");
 
-    async fn send_resume(&self, instance_id: &str) -> Result<()> {
-        info!(%instance_id, "broadcast resume (stub)");
-        // TODO: build and submit resumeWorkflow tx via provider + wallet
-        self.store.delete(keys::broadcast_resume(instance_id).as_bytes())?;
         Ok(())
     }
 
     async fn submit_claims_loop(&self) -> Result<()> {
+        let mut tx_policy = self.tx_policy.clone();
         loop {
+            if tx_policy.has_changed().unwrap_or(false) {
+                let policy = tx_policy.borrow_and_update();
+                info!(gas_bump_percent = policy.gas_bump_percent, "broadcaster using reloaded [tx_policy]");
+            }
             for (k, _v) in self.store.scan_prefix("broadcast:claim:")? {
                 let key = String::from_utf8_lossy(&k).to_string();
                 if let Some(activity_id) = key.strip_prefix("broadcast:claim:") {
                     // Skip if tx already submitted for this activity
                     if let Some(existing) = self.load_tx(activity_id)? {
-                        if existing.status == "submitted" || existing.status == "confirmed" {
+                        if matches!(existing.status, TxStatus::Submitted | TxStatus::Confirmed) {
                             continue;
                         }
                     }
-                    let _ = self.send_claim(activity_id).await;
+                    let span = activity_span("claim", activity_id);
+                    if let Err(e) = self.send_claim(activity_id).instrument(span.clone()).await {
+                        metrics::CLAIMS.with_label_values(&["error"]).inc();
+                        span.in_scope(|| warn!(%activity_id, error = %e, "claim failed"));
+                    }
                 }
             }
             tokio::time::sleep(std::time::Duration::from_millis(300)).await;
         }
     }
 
+    fn load_tx(&self, activity_id: &str) -> Result<Option<TxRecord>> {
+        self.store.get_record(keys::tx(activity_id))
+    }
+}
+
+/// Complete/resume drains and tx confirmation/bumping: not yet wired into `run()`.
+#[allow(dead_code)]
+impl<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend> Broadcaster<P, B> {
+    async fn send_complete(&self, activity_id: &str) -> Result<()> {
+        let _span = activity_span("complete", activity_id).entered();
+        info!(%activity_id, "broadcast complete (stub)");
+        // TODO: build and submit completeActivity tx via provider + wallet
+        self.store.delete(keys::broadcast_complete(activity_id).as_bytes())?;
+        Ok(())
+    }
+
+    async fn send_resume(&self, instance_id: &str) -> Result<()> {
+        let _span = workflow_span("resume", instance_id).entered();
+        info!(%instance_id, "broadcast resume (stub)");
+        // TODO: build and submit resumeWorkflow tx via provider + wallet
+        self.store.delete(keys::broadcast_resume(instance_id).as_bytes())?;
+        Ok(())
+    }
+
     async fn confirm_txs_loop(&self) -> Result<()> { Ok(()) }
 
     async fn bump_txs_loop(&self) -> Result<()> { Ok(()) }
 
-    fn load_tx(&self, activity_id: &str) -> Result<Option<TxRecord>> {
-        if let Some(v) = self.store.get(keys::tx(activity_id))? {
-            let rec: TxRecord = serde_json::from_slice(&v)?;
-            Ok(Some(rec))
-        } else {
-            Ok(None)
-        }
+    /// `fee` raised by the current `gas_bump_percent`, for replacing a stuck tx.
+    fn bump_fee(&self, fee: u128) -> u128 {
+        let percent = self.tx_policy.borrow().gas_bump_percent as u128;
+        fee.saturating_add(fee.saturating_mul(percent) / 100)
     }
 
     fn save_tx(&self, rec: &TxRecord) -> Result<()> {
-        self.store.put(keys::tx(&rec.activity_id), serde_json::to_vec(rec)?)
+        self.store.put_record(keys::tx(&rec.activity_id), rec)
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct TxRecord {
-    activity_id: String,
-    kind: String,
-    status: String, // pending|submitted|confirmed|dropped|replaced
-    tx_hash: Option<String>,
-    submitted_at_ms: i64,
-    last_bump_at_ms: Option<i64>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::ProviderBuilder;
+
+    #[test]
+    fn fee_bumps_follow_the_reloaded_tx_policy() {
+        let (tx, rx) = watch::channel(TxPolicyConfig { gas_bump_percent: 10 });
+        let provider = ProviderBuilder::new().connect_http("http://127.0.0.1:1".parse().unwrap());
+        let bc = Broadcaster::new(KvStore::in_memory(), provider, Address::ZERO, Address::ZERO, rx);
+        assert_eq!(bc.bump_fee(1_000), 1_100);
+
+        tx.send_replace(TxPolicyConfig { gas_bump_percent: 25 });
+        assert_eq!(bc.bump_fee(1_000), 1_250);
+        assert_eq!(bc.bump_fee(u128::MAX), u128::MAX);
+    }
 }
-
-
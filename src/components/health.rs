@@ -0,0 +1,176 @@
+//! Liveness and readiness probes.
+//!
+//! `GET /healthz`: every spawned component task is still running.
+//! `GET /readyz`: RPC reachable, worker active, contract protocol version in
+//! range, WEP `/health` responding and DB writable. Both answer 200 or 503 with
+//! `{"status": "ok"|"fail", "checks": [{"name", "ok", "detail"}]}`.
+//!
+//! The worker/protocol checks are the same gates `main` refuses to start without.
+use alloy::primitives::Address;
+use alloy::providers::Provider;
+use anyhow::{bail, Result};
+use axum::extract::{Json, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use subnet_wcp_chain::control_plane as scp;
+use subnet_wcp_config::ProtocolConfig;
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
+use subnet_wcp_persistence::{keys, KvStore};
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// Per-check timeout so a hung dependency fails the probe instead of stalling it.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Contract protocol version, if it lies within `[contract_min, contract_max]`.
+pub async fn protocol_gate<P: Provider + Clone + Send + Sync + 'static>(provider: &P, scp_addr: Address, protocol: &ProtocolConfig) -> Result<String> {
+    let ver = scp::get_protocol_version(provider, scp_addr).await?;
+    if !semver_in_range(&ver, &protocol.contract_min, &protocol.contract_max) {
+        bail!("contract protocol version {} outside supported range {}..={}", ver, protocol.contract_min, protocol.contract_max);
+    }
+    Ok(ver)
+}
+
+/// Fails unless `worker` is active on the SubnetControlPlane.
+pub async fn worker_gate<P: Provider + Clone + Send + Sync + 'static>(provider: &P, scp_addr: Address, worker: Address) -> Result<()> {
+    if !scp::is_worker_active(provider, scp_addr, worker).await? {
+        bail!("worker {} not active on SubnetControlPlane", worker);
+    }
+    Ok(())
+}
+
+fn semver_in_range(ver: &str, min: &str, max: &str) -> bool {
+    use semver::Version;
+    match (Version::parse(ver), Version::parse(min), Version::parse(max)) {
+        (Ok(v), Ok(lo), Ok(hi)) => v >= lo && v <= hi,
+        _ => false,
+    }
+}
+
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    ok: bool,
+    detail: String,
+}
+
+#[derive(Serialize)]
+struct Report {
+    status: &'static str,
+    checks: Vec<Check>,
+}
+
+impl Report {
+    fn into_response(checks: Vec<Check>) -> (StatusCode, Json<Report>) {
+        let ok = checks.iter().all(|c| c.ok);
+        let status = if ok { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+        (status, Json(Report { status: if ok { "ok" } else { "fail" }, checks }))
+    }
+}
+
+async fn check<F: Future<Output = Result<String>>>(name: &'static str, f: F) -> Check {
+    match tokio::time::timeout(CHECK_TIMEOUT, f).await {
+        Ok(Ok(detail)) => Check { name, ok: true, detail },
+        Ok(Err(e)) => Check { name, ok: false, detail: e.to_string() },
+        Err(_) => Check { name, ok: false, detail: format!("timed out after {:?}", CHECK_TIMEOUT) },
+    }
+}
+
+pub struct Health<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend = RocksBackend> {
+    store: KvStore<B>,
+    provider: P,
+    scp_addr: Address,
+    worker: Address,
+    protocol: ProtocolConfig,
+    /// `GET {wep_endpoint}/health`; `None` when there is no WEP (dev mock).
+    wep_health_url: Option<String>,
+    client: reqwest::Client,
+    components: Vec<(&'static str, AbortHandle)>,
+}
+
+impl<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend> Health<P, B> {
+    pub fn new(
+        store: KvStore<B>,
+        provider: P,
+        scp_addr: Address,
+        worker: Address,
+        protocol: ProtocolConfig,
+        wep_endpoint: Option<String>,
+    ) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(CHECK_TIMEOUT)
+            .build()
+            .expect("Failed to create HTTP client");
+        Self {
+            store,
+            provider,
+            scp_addr,
+            worker,
+            protocol,
+            wep_health_url: wep_endpoint.map(|e| format!("{}/health", e.trim_end_matches('/'))),
+            client,
+            components: Vec::new(),
+        }
+    }
+
+    /// Report `task` under `name` in `/healthz`.
+    pub fn watch<T>(mut self, name: &'static str, task: &JoinHandle<T>) -> Self {
+        self.components.push((name, task.abort_handle()));
+        self
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/healthz", get(healthz::<P, B>))
+            .route("/readyz", get(readyz::<P, B>))
+            .with_state(Arc::new(self))
+    }
+
+    fn liveness(&self) -> Vec<Check> {
+        self.components
+            .iter()
+            .map(|(name, task)| {
+                let running = !task.is_finished();
+                Check { name, ok: running, detail: if running { "running" } else { "stopped" }.to_string() }
+            })
+            .collect()
+    }
+
+    async fn readiness(&self) -> Vec<Check> {
+        let (rpc, worker, protocol, wep, db) = tokio::join!(
+            check("rpc", async { Ok(format!("block {}", self.provider.get_block_number().await?)) }),
+            check("worker_active", async {
+                worker_gate(&self.provider, self.scp_addr, self.worker).await?;
+                Ok(format!("{} active", self.worker))
+            }),
+            check("protocol_version", async {
+                let ver = protocol_gate(&self.provider, self.scp_addr, &self.protocol).await?;
+                Ok(format!("{} within {}..={}", ver, self.protocol.contract_min, self.protocol.contract_max))
+            }),
+            check("wep", self.wep()),
+            check("db_writable", async {
+                self.store.put(keys::HEALTH_PROBE, chrono::Utc::now().timestamp_millis().to_string())?;
+                Ok("write ok".to_string())
+            }),
+        );
+        vec![rpc, worker, protocol, wep, db]
+    }
+
+    async fn wep(&self) -> Result<String> {
+        let Some(url) = &self.wep_health_url else { return Ok("dev mock; no WEP".to_string()) };
+        let status = self.client.get(url).send().await?.error_for_status()?.status();
+        Ok(format!("{} {}", url, status))
+    }
+}
+
+async fn healthz<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend>(State(h): State<Arc<Health<P, B>>>) -> (StatusCode, Json<Report>) {
+    Report::into_response(h.liveness())
+}
+
+async fn readyz<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend>(State(h): State<Arc<Health<P, B>>>) -> (StatusCode, Json<Report>) {
+    Report::into_response(h.readiness().await)
+}
@@ -1,3 +1,9 @@
 pub mod poller;
 pub mod assigner;
-pub mod broadcaster;
\ No newline at end of file
+pub mod broadcaster;
+pub mod reconciler;
+pub mod api;
+pub mod sampler;
+pub mod health;
+pub mod admin;
+pub mod reload;
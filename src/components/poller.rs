@@ -1,53 +1,315 @@
 use anyhow::Result;
+use subnet_wcp_config::{QueueConfig, SchedulerConfig};
 use subnet_wcp_persistence::{KvStore, keys};
-use serde::{Serialize, Deserialize};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tracing::info;
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
+use subnet_wcp_persistence::records::{ClaimJob, InflightRecord, QueuePause};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 use alloy::providers::Provider;
 use alloy::primitives::Address;
 use hex::ToHex;
+use chrono::Utc;
+use tokio::sync::{mpsc, watch};
 use subnet_wcp_chain as chain;
+use subnet_wcp_chain::task_queue::Activity;
+use subnet_wcp_event::{ChainEvent, EventKind};
+use subnet_wcp_metrics as metrics;
+use subnet_wcp_telemetry::activity_span;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ClaimJob {
-    pub activity_id: String,
-    pub queue_name: String,
-    pub created_at_ms: u128,
+/// Poll bookkeeping for one queue subscription.
+struct QueueState {
+    cfg: QueueConfig,
+    /// Smooth weighted round-robin credit.
+    current: i64,
+    next_partition: usize,
 }
 
-pub struct Poller<P: Provider + Clone + Send + Sync + 'static> {
-    store: KvStore,
+impl QueueState {
+    /// The queue's next partition in turn; `None` if it lists none.
+    fn take_partition(&mut self) -> Option<u16> {
+        let partition = *self.cfg.partitions.get(self.next_partition % self.cfg.partitions.len().max(1))?;
+        self.next_partition = self.next_partition.wrapping_add(1);
+        Some(partition)
+    }
+}
+
+pub struct Poller<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend = RocksBackend> {
+    store: KvStore<B>,
     poll_interval: Duration,
-    queue_name: String,
+    queues: Vec<QueueState>,
+    max_inflight: usize,
     provider: P,
     task_queue_addr: Address,
+    events: Option<mpsc::Receiver<ChainEvent>>,
+    reload: Option<watch::Receiver<SchedulerConfig>>,
 }
 
-impl<P: Provider + Clone + Send + Sync + 'static> Poller<P> {
-    pub fn new(store: KvStore, poll_interval: Duration, queue_name: String, provider: P, task_queue_addr: Address) -> Self {
-        Self { store, poll_interval, queue_name, provider, task_queue_addr }
+impl<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend> Poller<P, B> {
+    /// `max_inflight` is the per-queue limit for queues that do not set their own.
+    pub fn new(store: KvStore<B>, poll_interval: Duration, queues: Vec<QueueConfig>, max_inflight: usize, provider: P, task_queue_addr: Address) -> Self {
+        let queues = queues.into_iter().map(|cfg| QueueState { cfg, current: 0, next_partition: 0 }).collect();
+        Self { store, poll_interval, queues, max_inflight, provider, task_queue_addr, events: None, reload: None }
+    }
+
+    /// Also enqueue claim jobs as soon as the EventPoller sees `ActivityEnqueued` for one of our queues.
+    pub fn with_events(mut self, events: mpsc::Receiver<ChainEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Follow `[scheduler]` reloads: queue list, `max_inflight` and `poll_interval` apply from the next tick.
+    pub fn with_reload(mut self, settings: watch::Receiver<SchedulerConfig>) -> Self {
+        self.reload = Some(settings);
+        self
     }
 
-    pub async fn run(&self) -> Result<()> {
+    pub async fn run(mut self) -> Result<()> {
         loop {
-            if let Some(id) = poll_once(&self.provider, self.task_queue_addr, &self.queue_name).await? {
-                let job = ClaimJob {
-                    activity_id: format!("0x{}", id.encode_hex::<String>()),
-                    queue_name: self.queue_name.clone(),
-                    created_at_ms: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis(),
+            self.apply_reload();
+            let load = self.load()?;
+            let paused = self.paused()?;
+            if let Some((queue, partition)) = self.next_slot(&load, &paused) {
+                let started = Instant::now();
+                let polled = poll_once(&self.provider, self.task_queue_addr, &queue, partition).await;
+                metrics::POLL_SECONDS.with_label_values(&[&queue]).observe(started.elapsed().as_secs_f64());
+                let result = match &polled {
+                    Ok(Some(_)) => "found",
+                    Ok(None) => "empty",
+                    Err(_) => "error",
                 };
-                let key = keys::claim_job(&job.activity_id);
-                let val = serde_json::to_vec(&job)?;
-                self.store.put(key, val)?;
-                info!(activity_id = %job.activity_id, "enqueued claim job");
+                metrics::POLLS.with_label_values(&[&queue, result]).inc();
+                match polled {
+                    Ok(Some(activity)) => {
+                        let id = format!("0x{}", activity.activityId.encode_hex::<String>());
+                        self.enqueue(id, &queue, partition, expires_at_ms(&activity))?;
+                    }
+                    Ok(None) => {}
+                    // Transient RPC trouble; the slot comes round again next tick.
+                    Err(e) => warn!(queue = %queue, partition, error = %e, "pollActivity failed"),
+                }
+            }
+            let Some(events) = self.events.as_mut() else {
+                tokio::time::sleep(self.poll_interval).await;
+                continue;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(self.poll_interval) => {}
+                ev = events.recv() => match ev {
+                    Some(ChainEvent { activity_id, kind: EventKind::Enqueued { queue }, .. }) => {
+                        let load = self.load()?;
+                        let paused = self.paused()?;
+                        let Some(partition) = self.event_slot(&queue, &load, &paused) else { continue };
+                        let expires_at = match chain::task_queue::get_activity(&self.provider, self.task_queue_addr, activity_id).await {
+                            Ok(activity) => expires_at_ms(&activity),
+                            Err(e) => {
+                                warn!(activity_id = %activity_id, error = %e, "getActivity failed; enqueuing without expiry");
+                                None
+                            }
+                        };
+                        self.enqueue(format!("0x{}", activity_id.encode_hex::<String>()), &queue, partition, expires_at)?;
+                    }
+                    Some(_) => {}
+                    None => self.events = None,
+                },
+            }
+        }
+    }
+
+    /// Swap in reloaded settings; queues that stay keep their round-robin position.
+    fn apply_reload(&mut self) {
+        let Some(settings) = self.reload.as_mut().filter(|s| s.has_changed().unwrap_or(false)) else { return };
+        let cfg = settings.borrow_and_update().clone();
+        let mut old: HashMap<String, QueueState> = self.queues.drain(..).map(|q| (q.cfg.name.clone(), q)).collect();
+        self.queues = cfg
+            .queues()
+            .into_iter()
+            .map(|cfg| match old.remove(&cfg.name) {
+                Some(q) => QueueState { cfg, ..q },
+                None => QueueState { cfg, current: 0, next_partition: 0 },
+            })
+            .collect();
+        self.max_inflight = cfg.max_inflight;
+        self.poll_interval = cfg.poll_interval;
+        info!(queues = self.queues.len(), max_inflight = self.max_inflight, poll_interval = ?self.poll_interval, "poller settings reloaded");
+    }
+
+    /// Claim jobs plus inflight activities per queue name.
+    fn load(&self) -> Result<HashMap<String, usize>> {
+        let mut load = HashMap::new();
+        for (_, job) in self.store.scan_records::<ClaimJob>("claim_job:")? {
+            *load.entry(job.queue_name).or_default() += 1;
+        }
+        for (_, rec) in self.store.scan_records::<InflightRecord>("inflight:")? {
+            *load.entry(rec.queue).or_default() += 1;
+        }
+        Ok(load)
+    }
+
+    /// Queues an operator paused through the admin API.
+    fn paused(&self) -> Result<HashSet<String>> {
+        Ok(self.store.scan_records::<QueuePause>("meta:paused_queue:")?.into_iter().map(|(_, p)| p.queue).collect())
+    }
+
+    /// Next `(queue, partition)` to poll: smooth weighted round-robin across
+    /// unpaused queues with room, cycling through each queue's partitions in turn.
+    fn next_slot(&mut self, load: &HashMap<String, usize>, paused: &HashSet<String>) -> Option<(String, u16)> {
+        let max_inflight = self.max_inflight;
+        let mut eligible: Vec<&mut QueueState> = self
+            .queues
+            .iter_mut()
+            .filter(|q| is_open(&q.cfg, max_inflight, load, paused))
+            .collect();
+        let total: i64 = eligible.iter().map(|q| q.cfg.weight as i64).sum();
+        for q in eligible.iter_mut() {
+            q.current += q.cfg.weight as i64;
+        }
+        let chosen = eligible.into_iter().max_by_key(|q| q.current)?;
+        chosen.current -= total;
+        let partition = chosen.take_partition()?;
+        Some((chosen.cfg.name.clone(), partition))
+    }
+
+    /// Partition for an `ActivityEnqueued` on `queue`, if polling would serve
+    /// that queue now. The event does not say which partition; take the queue's next one.
+    fn event_slot(&mut self, queue: &str, load: &HashMap<String, usize>, paused: &HashSet<String>) -> Option<u16> {
+        let max_inflight = self.max_inflight;
+        let q = self.queues.iter_mut().find(|q| q.cfg.name == queue && is_open(&q.cfg, max_inflight, load, paused))?;
+        q.take_partition()
+    }
+
+    fn enqueue(&self, activity_id: String, queue: &str, partition: u16, expires_at_ms: Option<i64>) -> Result<()> {
+        let span = activity_span("poll", &activity_id);
+        span.record("queue", queue);
+        let _span = span.enter();
+        // Events replay after a restart; skip work we already know about.
+        for key in [keys::claim_job(&activity_id), keys::inflight(&activity_id), keys::done(&activity_id)] {
+            if self.store.get(key)?.is_some() {
+                return Ok(());
             }
-            tokio::time::sleep(self.poll_interval).await;
         }
+        let job = ClaimJob {
+            activity_id,
+            queue_name: queue.to_string(),
+            created_at_ms: Utc::now().timestamp_millis(),
+            partition,
+            expires_at_ms,
+        };
+        self.store.put_record(keys::claim_job(&job.activity_id), &job)?;
+        metrics::CLAIM_JOBS_ENQUEUED.with_label_values(&[queue]).inc();
+        info!(activity_id = %job.activity_id, queue = %job.queue_name, partition, "enqueued claim job");
+        Ok(())
     }
 }
 
-async fn poll_once<P: Provider + Clone + Send + Sync + 'static>(provider: &P, task_queue_addr: Address, queue: &str) -> Result<Option<[u8;32]>> {
-    chain::task_queue::poll_activity(provider, task_queue_addr, queue).await
+/// On-chain expiry in ms; `None` when unset.
+fn expires_at_ms(activity: &Activity) -> Option<i64> {
+    i64::try_from(activity.expiresAt).ok().filter(|s| *s > 0).map(|s| s.saturating_mul(1000))
+}
+
+/// Whether `q` takes new claim jobs, from polling or events: weighted, with
+/// partitions, not paused and below its limit.
+fn is_open(q: &QueueConfig, default_max: usize, load: &HashMap<String, usize>, paused: &HashSet<String>) -> bool {
+    q.weight > 0 && !q.partitions.is_empty() && !paused.contains(&q.name) && has_room(q, default_max, load)
+}
+
+fn has_room(q: &QueueConfig, default_max: usize, load: &HashMap<String, usize>) -> bool {
+    load.get(&q.name).copied().unwrap_or(0) < q.max_inflight.unwrap_or(default_max)
+}
+
+async fn poll_once<P: Provider + Clone + Send + Sync + 'static>(provider: &P, task_queue_addr: Address, queue: &str, partition: u16) -> Result<Option<Activity>> {
+    chain::task_queue::poll_activity(provider, task_queue_addr, queue, partition).await
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::ProviderBuilder;
+    use subnet_wcp_persistence::backend::MemBackend;
+    use subnet_wcp_persistence::records::AssignmentStatus;
+
+    fn queue(name: &str, weight: u32, partitions: Vec<u16>, max_inflight: Option<usize>) -> QueueConfig {
+        QueueConfig { name: name.into(), partitions, weight, max_inflight, enabled: true, reward_wei: None, expected_duration: None }
+    }
+
+    fn poller(store: KvStore<MemBackend>, queues: Vec<QueueConfig>, max_inflight: usize) -> Poller<impl Provider + Clone, MemBackend> {
+        let provider = ProviderBuilder::new().connect_http("http://127.0.0.1:1".parse().unwrap());
+        Poller::new(store, Duration::from_secs(1), queues, max_inflight, provider, Address::ZERO)
+    }
+
+    fn slots(p: &mut Poller<impl Provider + Clone, MemBackend>, n: usize) -> Vec<(String, u16)> {
+        let (load, paused) = (p.load().unwrap(), p.paused().unwrap());
+        (0..n).filter_map(|_| p.next_slot(&load, &paused)).collect()
+    }
+
+    #[test]
+    fn slots_follow_weights_and_cycle_partitions() {
+        let mut p = poller(KvStore::in_memory(), vec![queue("a", 2, vec![0, 1], None), queue("b", 1, vec![7], None)], 10);
+        let got = slots(&mut p, 6);
+        assert_eq!(got.iter().filter(|(q, _)| q == "a").count(), 4);
+        assert_eq!(got.iter().filter(|(q, _)| q == "b").count(), 2);
+        // Smooth round-robin never gives the light queue two slots in a row.
+        assert!(got.windows(2).all(|w| !(w[0].0 == "b" && w[1].0 == "b")));
+        let a: Vec<u16> = got.iter().filter(|(q, _)| q == "a").map(|(_, p)| *p).collect();
+        assert_eq!(a, vec![0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn full_paused_and_zero_weight_queues_get_no_slots() {
+        let store = KvStore::in_memory();
+        let job = ClaimJob { activity_id: "0x01".into(), queue_name: "a".into(), created_at_ms: 0, partition: 0, expires_at_ms: None };
+        store.put_record(keys::claim_job("0x01"), &job).unwrap();
+        let rec = InflightRecord {
+            activity_id: "0x02".into(),
+            queue: "b".into(),
+            claimed_at_ms: 0,
+            assignment_status: AssignmentStatus::Running,
+            instance_id: None,
+            expires_at_ms: None,
+            inputs: Vec::new(),
+            upload_prefix: None,
+        };
+        store.put_record(keys::inflight("0x02"), &rec).unwrap();
+        store.put_record(keys::paused_queue("c"), &QueuePause { queue: "c".into(), paused_at_ms: 0 }).unwrap();
+        let queues = vec![
+            queue("a", 1, vec![0], Some(1)),
+            queue("b", 1, vec![0], None),
+            queue("c", 1, vec![0], None),
+            queue("d", 0, vec![0], None),
+            queue("e", 1, vec![0], None),
+        ];
+        let mut p = poller(store, queues, 1);
+
+        let load = p.load().unwrap();
+        assert_eq!((load.get("a"), load.get("b")), (Some(&1), Some(&1)));
+        assert!(!has_room(&p.queues[0].cfg, 1, &load));
+        assert!(has_room(&p.queues[4].cfg, 1, &load));
+        assert!(slots(&mut p, 4).iter().all(|(q, _)| q == "e"));
+
+        // Events for those queues are turned away the same way.
+        let paused = p.paused().unwrap();
+        for name in ["a", "b", "c", "d", "unknown"] {
+            assert_eq!(p.event_slot(name, &load, &paused), None, "{name}");
+        }
+        assert_eq!(p.event_slot("e", &load, &paused), Some(0));
+    }
+
+    #[test]
+    fn take_partition_cycles_and_handles_empty_lists() {
+        let mut q = QueueState { cfg: queue("a", 1, vec![4, 5], None), current: 0, next_partition: 0 };
+        assert_eq!((q.take_partition(), q.take_partition(), q.take_partition()), (Some(4), Some(5), Some(4)));
+        let mut empty = QueueState { cfg: queue("b", 1, Vec::new(), None), current: 0, next_partition: 0 };
+        assert_eq!(empty.take_partition(), None);
+    }
 
+    #[test]
+    fn enqueue_skips_known_activities() {
+        let store = KvStore::in_memory();
+        let p = poller(store.clone(), vec![queue("a", 1, vec![3], None)], 10);
+        p.enqueue("0x01".into(), "a", 3, Some(5_000)).unwrap();
+        p.enqueue("0x01".into(), "a", 0, None).unwrap();
+        let jobs = store.scan_records::<ClaimJob>("claim_job:").unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!((jobs[0].1.partition, jobs[0].1.expires_at_ms), (3, Some(5_000)));
+    }
+}
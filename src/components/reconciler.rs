@@ -0,0 +1,522 @@
+use anyhow::Result;
+use subnet_wcp_persistence::{Batch, KvStore, keys};
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
+use subnet_wcp_persistence::records::{AssignmentStatus, DoneOutcome, DoneRecord, InflightRecord};
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::{info, warn, error};
+use alloy::providers::Provider;
+use alloy::primitives::{Address, B256, U256};
+use hex::ToHex;
+use chrono::Utc;
+use subnet_wcp_chain::task_queue::{self, Activity};
+use subnet_wcp_event::{ChainEvent, EventKind};
+use subnet_wcp_metrics as metrics;
+use subnet_wcp_telemetry::activity_span;
+use tokio::sync::{mpsc, oneshot};
+
+/// What a reconcile pass changed for one activity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+    /// Claimed on-chain but no local `inflight:*`; recreated so the Assigner picks it up.
+    RebuiltInflight,
+    /// Local `inflight:*` was missing the instance id or expiry; filled in from chain.
+    Backfilled,
+    /// Completed on-chain; `done:*` written and local work dropped.
+    MarkedDone,
+    /// Lease expired on-chain; `done:*` written as expired and local work dropped.
+    Expired,
+    /// Local `inflight:*` no longer assigned to us (or unknown past the window);
+    /// `done:*` written as expired and local work dropped.
+    DroppedStale,
+}
+
+impl Repair {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Repair::RebuiltInflight => "rebuilt_inflight",
+            Repair::Backfilled => "backfilled",
+            Repair::MarkedDone => "marked_done",
+            Repair::Expired => "expired",
+            Repair::DroppedStale => "dropped_stale",
+        }
+    }
+}
+
+/// Why a local `inflight:*` is being dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stale {
+    /// The chain assigns the activity to another worker: our claim lost.
+    Reassigned,
+    /// The chain assigns the activity to no one (released or never claimed).
+    Unassigned,
+    /// The id does not parse, or the chain could not be read for a record past the window.
+    Unknown,
+}
+
+impl Stale {
+    fn as_error(&self) -> &'static str {
+        match self {
+            Stale::Reassigned => "reassigned to another worker on-chain",
+            Stale::Unassigned => "no longer assigned to this worker on-chain",
+            Stale::Unknown => "not found on-chain within the reconcile window",
+        }
+    }
+}
+
+/// An on-demand reconcile pass; the repairs (or the error) go back on the sender.
+pub type ReconcileRequest = oneshot::Sender<Result<Vec<(String, Repair)>>>;
+
+/// Periodically repairs local state from the TaskQueue's view of this worker,
+/// so a lost `wcp.db` or a crash mid-transition does not strand claimed work.
+pub struct Reconciler<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend = RocksBackend> {
+    store: KvStore<B>,
+    provider: P,
+    task_queue_addr: Address,
+    worker: Address,
+    interval: Duration,
+    window: Duration,
+    events: Option<mpsc::Receiver<ChainEvent>>,
+    triggers: Option<mpsc::Receiver<ReconcileRequest>>,
+}
+
+impl<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend> Reconciler<P, B> {
+    pub fn new(store: KvStore<B>, provider: P, task_queue_addr: Address, worker: Address, interval: Duration, window: Duration) -> Self {
+        Self { store, provider, task_queue_addr, worker, interval, window, events: None, triggers: None }
+    }
+
+    /// Also reconcile an activity as soon as a claim/complete/fail event for this worker arrives.
+    pub fn with_events(mut self, events: mpsc::Receiver<ChainEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Also run a full pass whenever a request arrives (admin API), replying with its repairs.
+    pub fn with_triggers(mut self, triggers: mpsc::Receiver<ReconcileRequest>) -> Self {
+        self.triggers = Some(triggers);
+        self
+    }
+
+    pub async fn run(mut self) -> Result<()> {
+        let mut next_pass = tokio::time::Instant::now();
+        loop {
+            if tokio::time::Instant::now() >= next_pass {
+                match self.reconcile_once().await {
+                    Ok(repairs) if !repairs.is_empty() => info!(repairs = repairs.len(), "reconcile pass repaired local state"),
+                    Ok(_) => {}
+                    Err(e) => error!(error = %e, "reconcile pass failed"),
+                }
+                next_pass = tokio::time::Instant::now() + self.interval;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep_until(next_pass) => {}
+                ev = recv(&mut self.events) => match ev {
+                    Some(ev) if !matches!(ev.kind, EventKind::Heartbeat { .. }) => {
+                        if let Err(e) = self.reconcile_id(ev.activity_id).await {
+                            error!(activity_id = %ev.activity_id, error = %e, "event-driven reconcile failed");
+                        }
+                    }
+                    Some(_) => {}
+                    None => self.events = None,
+                },
+                req = recv(&mut self.triggers) => match req {
+                    Some(reply) => {
+                        let result = self.reconcile_once().await;
+                        match &result {
+                            Ok(repairs) => info!(repairs = repairs.len(), "requested reconcile pass finished"),
+                            Err(e) => error!(error = %e, "requested reconcile pass failed"),
+                        }
+                        let _ = reply.send(result);
+                    }
+                    None => self.triggers = None,
+                },
+            }
+        }
+    }
+
+    async fn reconcile_id(&self, id: B256) -> Result<Option<Repair>> {
+        let activity = task_queue::get_activity(&self.provider, self.task_queue_addr, id).await?;
+        self.reconcile_activity(&format!("0x{}", id.encode_hex::<String>()), &activity, Utc::now().timestamp_millis())
+    }
+
+    /// One pass over the chain's activities for this worker plus any local
+    /// `inflight:*` the chain no longer lists. Returns every repair made.
+    pub async fn reconcile_once(&self) -> Result<Vec<(String, Repair)>> {
+        let now_ms = Utc::now().timestamp_millis();
+        let mut repairs = Vec::new();
+        let mut seen = HashSet::new();
+
+        for id in task_queue::get_worker_activities(&self.provider, self.task_queue_addr, self.worker).await? {
+            let activity_id = format!("0x{}", id.encode_hex::<String>());
+            match task_queue::get_activity(&self.provider, self.task_queue_addr, id).await {
+                Ok(activity) => {
+                    if let Some(repair) = self.reconcile_activity(&activity_id, &activity, now_ms)? {
+                        repairs.push((activity_id.clone(), repair));
+                    }
+                }
+                Err(e) => skipped(&activity_id, &e),
+            }
+            seen.insert(activity_id);
+        }
+
+        let window_start_ms = now_ms - self.window.as_millis() as i64;
+        for (_, rec) in self.store.scan_records::<InflightRecord>("inflight:")? {
+            if seen.contains(&rec.activity_id) {
+                continue;
+            }
+            let repair = match rec.activity_id.parse::<B256>() {
+                Ok(id) => match task_queue::get_activity(&self.provider, self.task_queue_addr, id).await {
+                    Ok(activity) => self.reconcile_activity(&rec.activity_id, &activity, now_ms)?,
+                    Err(e) if rec.claimed_at_ms < window_start_ms => {
+                        let _span = activity_span("reconcile", &rec.activity_id).entered();
+                        warn!(activity_id = %rec.activity_id, error = %e, "getActivity failed for record older than reconcile window");
+                        self.drop_stale(&rec, Stale::Unknown, now_ms)?
+                    }
+                    Err(e) => {
+                        skipped(&rec.activity_id, &e);
+                        continue;
+                    }
+                },
+                Err(_) => activity_span("reconcile", &rec.activity_id).in_scope(|| self.drop_stale(&rec, Stale::Unknown, now_ms))?,
+            };
+            if let Some(repair) = repair {
+                repairs.push((rec.activity_id, repair));
+            }
+        }
+
+        Ok(repairs)
+    }
+
+    fn reconcile_activity(&self, activity_id: &str, activity: &Activity, now_ms: i64) -> Result<Option<Repair>> {
+        let span = activity_span("reconcile", activity_id);
+        let _span = span.enter();
+        let inflight: Option<InflightRecord> = self.store.get_record(keys::inflight(activity_id))?;
+        let done: Option<DoneRecord> = self.store.get_record(keys::done(activity_id))?;
+
+        if activity.assignedWorker != self.worker {
+            let reason = if activity.assignedWorker.is_zero() { Stale::Unassigned } else { Stale::Reassigned };
+            return match inflight {
+                Some(rec) => self.drop_stale(&rec, reason, now_ms),
+                None => Ok(None),
+            };
+        }
+
+        let instance_id = (!activity.workflowInstanceId.is_zero())
+            .then(|| format!("0x{}", activity.workflowInstanceId.encode_hex::<String>()));
+        if let Some(id) = &instance_id {
+            span.record("instance_id", id.as_str());
+        }
+        let expires_at_ms = secs_to_ms(activity.expiresAt);
+
+        let mut batch = Batch::new();
+        let repair = if activity.isCompleted || expires_at_ms.is_some_and(|ts| ts <= now_ms) {
+            if done.is_none() {
+                let rec = DoneRecord {
+                    activity_id: activity_id.to_string(),
+                    outcome: match (activity.isCompleted, activity.success) {
+                        (true, true) => DoneOutcome::Ok,
+                        (true, false) => DoneOutcome::Failed,
+                        (false, _) => DoneOutcome::Expired,
+                    },
+                    result_ref: None,
+                    error: None,
+                    finished_at_ms: now_ms,
+                    instance_id,
+                    queue: inflight.as_ref().map(|r| r.queue.clone()).filter(|q| !q.is_empty()),
+                };
+                batch.put_record(keys::done(activity_id), &rec)?;
+            } else if inflight.is_none() {
+                return Ok(None);
+            }
+            // Nothing left to do for it locally, including any pending complete.
+            batch
+                .delete(keys::inflight(activity_id))
+                .delete(keys::claim_job(activity_id))
+                .delete(keys::broadcast_claim(activity_id))
+                .delete(keys::broadcast_complete(activity_id));
+            if activity.isCompleted { Repair::MarkedDone } else { Repair::Expired }
+        } else if let Some(mut rec) = inflight {
+            if (rec.instance_id.is_some() || instance_id.is_none()) && (rec.expires_at_ms.is_some() || expires_at_ms.is_none()) {
+                return Ok(None);
+            }
+            rec.instance_id = rec.instance_id.or(instance_id);
+            rec.expires_at_ms = rec.expires_at_ms.or(expires_at_ms);
+            batch.put_record(keys::inflight(activity_id), &rec)?;
+            Repair::Backfilled
+        } else if done.is_some() {
+            // Finished locally; the complete tx has not landed yet.
+            return Ok(None);
+        } else {
+            let rec = InflightRecord {
+                activity_id: activity_id.to_string(),
+                // The queue name is not recorded on-chain.
+                queue: String::new(),
+                claimed_at_ms: secs_to_ms(activity.assignedAt).unwrap_or(now_ms),
+                assignment_status: AssignmentStatus::Pending,
+                instance_id,
+                expires_at_ms,
+                inputs: Vec::new(),
+                upload_prefix: None,
+            };
+            batch
+                .put_record(keys::inflight(activity_id), &rec)?
+                .delete(keys::claim_job(activity_id))
+                .delete(keys::broadcast_claim(activity_id));
+            Repair::RebuiltInflight
+        };
+
+        self.store.write(batch)?;
+        metrics::REPAIRS.with_label_values(&[repair.as_str()]).inc();
+        info!(activity_id = %activity_id, repair = repair.as_str(), "reconciled activity");
+        Ok(Some(repair))
+    }
+
+    /// Finish a local activity the chain no longer has us on: `done:` as
+    /// expired, unless something finished it first, and none of its claim or
+    /// complete work left for the Assigner or Broadcaster to pick up.
+    fn drop_stale(&self, rec: &InflightRecord, reason: Stale, now_ms: i64) -> Result<Option<Repair>> {
+        let activity_id = rec.activity_id.as_str();
+        let done = DoneRecord {
+            activity_id: activity_id.to_string(),
+            outcome: DoneOutcome::Expired,
+            result_ref: None,
+            error: Some(reason.as_error().to_string()),
+            finished_at_ms: now_ms,
+            instance_id: rec.instance_id.clone(),
+            queue: Some(rec.queue.clone()).filter(|q| !q.is_empty()),
+        };
+        let cleanup = || {
+            let mut batch = Batch::new();
+            batch
+                .delete(keys::inflight(activity_id))
+                .delete(keys::claim_job(activity_id))
+                .delete(keys::broadcast_claim(activity_id))
+                .delete(keys::broadcast_complete(activity_id));
+            batch
+        };
+        let mut batch = cleanup();
+        batch.put_record(keys::done(activity_id), &done)?;
+        if !self.store.write_if_absent(keys::done(activity_id), batch)? {
+            self.store.write_if_present(keys::done(activity_id), cleanup())?;
+        }
+        if reason == Stale::Reassigned {
+            metrics::CLAIMS.with_label_values(&["lost"]).inc();
+        }
+        metrics::REPAIRS.with_label_values(&[Repair::DroppedStale.as_str()]).inc();
+        info!(activity_id = %activity_id, repair = Repair::DroppedStale.as_str(), ?reason, "reconciled activity");
+        Ok(Some(Repair::DroppedStale))
+    }
+}
+
+/// A chain read failed for one activity; leave it for the next pass rather than abort this one.
+fn skipped(activity_id: &str, e: &anyhow::Error) {
+    let _span = activity_span("reconcile", activity_id).entered();
+    metrics::RECONCILE_ERRORS.with_label_values(&["get_activity"]).inc();
+    warn!(activity_id = %activity_id, error = %e, "getActivity failed; retrying next pass");
+}
+
+/// Next message, or never once the channel is gone (or was not configured).
+async fn recv<T>(rx: &mut Option<mpsc::Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// On-chain unix seconds to ms; `None` for unset (zero) timestamps.
+fn secs_to_ms(secs: U256) -> Option<i64> {
+    let secs = i64::try_from(secs).unwrap_or(i64::MAX / 1000);
+    (secs > 0).then(|| secs.saturating_mul(1000))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::ProviderBuilder;
+    use alloy::sol_types::SolValue;
+    use alloy::transports::mock::Asserter;
+    use subnet_wcp_persistence::backend::MemBackend;
+    use subnet_wcp_persistence::records::{BroadcastJob, ClaimJob};
+
+    const WORKER: Address = Address::repeat_byte(0x11);
+    const NOW_MS: i64 = 1_700_000_000_000;
+
+    fn id(n: u8) -> String {
+        format!("0x{}", B256::with_last_byte(n).encode_hex::<String>())
+    }
+
+    fn reconciler(store: KvStore<MemBackend>, asserter: Asserter) -> Reconciler<impl Provider + Clone, MemBackend> {
+        let provider = ProviderBuilder::new().connect_mocked_client(asserter);
+        Reconciler::new(store, provider, Address::ZERO, WORKER, Duration::from_secs(60), Duration::from_secs(600))
+    }
+
+    fn inflight(activity_id: &str, claimed_at_ms: i64, instance_id: Option<&str>) -> InflightRecord {
+        InflightRecord {
+            activity_id: activity_id.into(),
+            queue: "q".into(),
+            claimed_at_ms,
+            assignment_status: AssignmentStatus::Running,
+            instance_id: instance_id.map(Into::into),
+            expires_at_ms: Some(NOW_MS + 60_000),
+            inputs: Vec::new(),
+            upload_prefix: None,
+        }
+    }
+
+    fn assigned(expires_at_s: u64) -> Activity {
+        Activity {
+            activityId: B256::ZERO,
+            workflowInstanceId: B256::repeat_byte(0x22),
+            activityType: Address::ZERO,
+            inputData: Default::default(),
+            initData: Default::default(),
+            resultData: Default::default(),
+            createdAt: U256::ZERO,
+            expiresAt: U256::from(expires_at_s),
+            requester: Address::ZERO,
+            assignedWorker: WORKER,
+            assignedAt: U256::from(NOW_MS / 1000 - 10),
+            lastHeartbeat: U256::ZERO,
+            progress: 0,
+            isAssigned: true,
+            isCompleted: false,
+            success: false,
+        }
+    }
+
+    fn live() -> Activity {
+        assigned((NOW_MS / 1000 + 60) as u64)
+    }
+
+    fn done(store: &KvStore<MemBackend>, activity_id: &str) -> Option<DoneOutcome> {
+        store.get_record::<DoneRecord>(keys::done(activity_id)).unwrap().map(|d| d.outcome)
+    }
+
+    #[test]
+    fn claimed_activity_without_local_state_is_rebuilt() {
+        let store = KvStore::in_memory();
+        let job = ClaimJob { activity_id: id(1), queue_name: "q".into(), created_at_ms: 0, partition: 0, expires_at_ms: None };
+        store.put_record(keys::claim_job(&id(1)), &job).unwrap();
+        let r = reconciler(store.clone(), Asserter::new());
+
+        assert_eq!(r.reconcile_activity(&id(1), &live(), NOW_MS).unwrap(), Some(Repair::RebuiltInflight));
+        let rec: InflightRecord = store.get_record(keys::inflight(&id(1))).unwrap().unwrap();
+        assert_eq!(rec.assignment_status, AssignmentStatus::Pending);
+        assert_eq!(rec.instance_id, Some(format!("0x{}", B256::repeat_byte(0x22).encode_hex::<String>())));
+        assert!(store.get(keys::claim_job(&id(1))).unwrap().is_none());
+        // A second pass finds nothing left to repair.
+        assert_eq!(r.reconcile_activity(&id(1), &live(), NOW_MS).unwrap(), None);
+    }
+
+    #[test]
+    fn missing_instance_id_is_backfilled() {
+        let store = KvStore::in_memory();
+        store.put_record(keys::inflight(&id(1)), &inflight(&id(1), NOW_MS, None)).unwrap();
+        let r = reconciler(store.clone(), Asserter::new());
+
+        assert_eq!(r.reconcile_activity(&id(1), &live(), NOW_MS).unwrap(), Some(Repair::Backfilled));
+        let rec: InflightRecord = store.get_record(keys::inflight(&id(1))).unwrap().unwrap();
+        assert!(rec.instance_id.is_some());
+        assert_eq!(rec.assignment_status, AssignmentStatus::Running);
+    }
+
+    #[test]
+    fn completed_and_expired_activities_are_finished_locally() {
+        let store = KvStore::in_memory();
+        for n in [1, 2, 3] {
+            store.put_record(keys::inflight(&id(n)), &inflight(&id(n), NOW_MS, Some("0x01"))).unwrap();
+        }
+        let r = reconciler(store.clone(), Asserter::new());
+
+        let ok = Activity { isCompleted: true, success: true, ..live() };
+        let failed = Activity { isCompleted: true, success: false, ..live() };
+        let expired = assigned((NOW_MS / 1000 - 1) as u64);
+        assert_eq!(r.reconcile_activity(&id(1), &ok, NOW_MS).unwrap(), Some(Repair::MarkedDone));
+        assert_eq!(r.reconcile_activity(&id(2), &failed, NOW_MS).unwrap(), Some(Repair::MarkedDone));
+        assert_eq!(r.reconcile_activity(&id(3), &expired, NOW_MS).unwrap(), Some(Repair::Expired));
+        assert_eq!(done(&store, &id(1)), Some(DoneOutcome::Ok));
+        assert_eq!(done(&store, &id(2)), Some(DoneOutcome::Failed));
+        assert_eq!(done(&store, &id(3)), Some(DoneOutcome::Expired));
+        assert!(store.scan_records::<InflightRecord>("inflight:").unwrap().is_empty());
+    }
+
+    #[test]
+    fn only_reassigned_activities_count_as_lost_claims() {
+        let store = KvStore::in_memory();
+        for n in [1, 2] {
+            store.put_record(keys::inflight(&id(n)), &inflight(&id(n), NOW_MS, Some("0x01"))).unwrap();
+        }
+        let r = reconciler(store.clone(), Asserter::new());
+        let lost = || metrics::CLAIMS.with_label_values(&["lost"]).get();
+        let before = lost();
+
+        let released = Activity { assignedWorker: Address::ZERO, ..live() };
+        assert_eq!(r.reconcile_activity(&id(1), &released, NOW_MS).unwrap(), Some(Repair::DroppedStale));
+        assert_eq!(lost(), before);
+
+        let reassigned = Activity { assignedWorker: Address::repeat_byte(0x33), ..live() };
+        assert_eq!(r.reconcile_activity(&id(2), &reassigned, NOW_MS).unwrap(), Some(Repair::DroppedStale));
+        assert_eq!(lost(), before + 1);
+        assert!(store.scan_records::<InflightRecord>("inflight:").unwrap().is_empty());
+    }
+
+    #[test]
+    fn stale_activities_are_finished_and_their_pending_work_dropped() {
+        let store = KvStore::in_memory();
+        let job = BroadcastJob { id: id(1), created_at_ms: 0 };
+        for n in [1, 2] {
+            store.put_record(keys::inflight(&id(n)), &inflight(&id(n), NOW_MS, Some("0x01"))).unwrap();
+            let claim = ClaimJob { activity_id: id(n), queue_name: "q".into(), created_at_ms: 0, partition: 0, expires_at_ms: None };
+            store.put_record(keys::claim_job(&id(n)), &claim).unwrap();
+            store.put_record(keys::broadcast_claim(&id(n)), &job).unwrap();
+            store.put_record(keys::broadcast_complete(&id(n)), &job).unwrap();
+        }
+        // An operator already failed the second one; that outcome stands.
+        let failed = DoneRecord {
+            activity_id: id(2),
+            outcome: DoneOutcome::Failed,
+            result_ref: None,
+            error: Some("operator".into()),
+            finished_at_ms: 0,
+            instance_id: None,
+            queue: None,
+        };
+        store.put_record(keys::done(&id(2)), &failed).unwrap();
+        let r = reconciler(store.clone(), Asserter::new());
+
+        let reassigned = Activity { assignedWorker: Address::repeat_byte(0x33), ..live() };
+        for n in [1, 2] {
+            assert_eq!(r.reconcile_activity(&id(n), &reassigned, NOW_MS).unwrap(), Some(Repair::DroppedStale));
+            for key in [keys::inflight(&id(n)), keys::claim_job(&id(n)), keys::broadcast_claim(&id(n)), keys::broadcast_complete(&id(n))] {
+                assert!(store.get(&key).unwrap().is_none(), "{key}");
+            }
+        }
+        let dropped = store.get_record::<DoneRecord>(keys::done(&id(1))).unwrap().unwrap();
+        assert_eq!(dropped.outcome, DoneOutcome::Expired);
+        assert_eq!(dropped.error.as_deref(), Some("reassigned to another worker on-chain"));
+        assert_eq!(dropped.queue.as_deref(), Some("q"));
+        assert_eq!(store.get_record::<DoneRecord>(keys::done(&id(2))).unwrap(), Some(failed));
+    }
+
+    #[tokio::test]
+    async fn failed_reads_are_skipped_without_aborting_the_pass() {
+        let store = KvStore::in_memory();
+        let now_ms = Utc::now().timestamp_millis();
+        // Recent: kept for the next pass. Older than the window: dropped as unknown.
+        store.put_record(keys::inflight(&id(1)), &inflight(&id(1), now_ms, Some("0x01"))).unwrap();
+        store.put_record(keys::inflight(&id(2)), &inflight(&id(2), now_ms - 3_600_000, Some("0x01"))).unwrap();
+        let asserter = Asserter::new();
+        asserter.push_success(&alloy::primitives::Bytes::from((Vec::<B256>::new(),).abi_encode_params()));
+        asserter.push_failure_msg("rpc down");
+        asserter.push_failure_msg("rpc down");
+        let r = reconciler(store.clone(), asserter);
+        let errors = || metrics::RECONCILE_ERRORS.with_label_values(&["get_activity"]).get();
+        let before = errors();
+
+        assert_eq!(r.reconcile_once().await.unwrap(), vec![(id(2), Repair::DroppedStale)]);
+        assert_eq!(errors(), before + 1);
+        assert!(store.get(keys::inflight(&id(1))).unwrap().is_some());
+        assert!(store.get(keys::inflight(&id(2))).unwrap().is_none());
+        assert_eq!(done(&store, &id(2)), Some(DoneOutcome::Expired));
+    }
+}
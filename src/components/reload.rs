@@ -0,0 +1,217 @@
+//! Config reload on SIGHUP or when the config file changes.
+//!
+//! Only `[scheduler]` and `[tx_policy]` reach the running process, through
+//! watch channels the components check each tick. A reloaded config that fails
+//! validation is rejected as a whole; changes to any other section (wallet,
+//! signer, contracts, endpoints, storage) are logged and ignored until restart.
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use subnet_wcp_config::{self as config, ConfigProblem, SchedulerConfig, TxPolicyConfig, WcpConfig};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+pub struct Reloader {
+    /// What is running: the startup config with reloaded sections applied.
+    current: WcpConfig,
+    scheduler: watch::Sender<SchedulerConfig>,
+    tx_policy: watch::Sender<TxPolicyConfig>,
+    file: PathBuf,
+}
+
+impl Reloader {
+    pub fn new(current: WcpConfig) -> Self {
+        let (scheduler, _) = watch::channel(current.scheduler.clone());
+        let (tx_policy, _) = watch::channel(current.tx_policy.clone());
+        Self { current, scheduler, tx_policy, file: PathBuf::from(config::config_file()) }
+    }
+
+    pub fn scheduler(&self) -> watch::Receiver<SchedulerConfig> {
+        self.scheduler.subscribe()
+    }
+
+    pub fn tx_policy(&self) -> watch::Receiver<TxPolicyConfig> {
+        self.tx_policy.subscribe()
+    }
+
+    pub async fn run(mut self) -> Result<()> {
+        let mut hangup = signal(SignalKind::hangup())?;
+        let watch_file = self.current.reload.watch_file;
+        let interval = self.current.reload.interval;
+        let mut modified = self.modified();
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    info!("SIGHUP: reloading config");
+                    self.reload();
+                }
+                _ = sleep_if(watch_file, interval) => {
+                    let now = self.modified();
+                    if now != modified {
+                        modified = now;
+                        info!(file = %self.file.display(), "config file changed; reloading");
+                        self.reload();
+                    }
+                }
+            }
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.file).and_then(|m| m.modified()).ok()
+    }
+
+    fn reload(&mut self) {
+        self.apply(WcpConfig::load());
+    }
+
+    /// Switch to a freshly loaded config, or keep the running one if it has problems.
+    fn apply(&mut self, loaded: Result<(WcpConfig, Vec<ConfigProblem>)>) {
+        let next = match loaded {
+            Ok((next, problems)) if problems.is_empty() => next,
+            Ok((_, problems)) => {
+                warn!("config reload rejected; keeping the running config: {}", config::report(&problems));
+                return;
+            }
+            Err(e) => {
+                warn!(error = %e, "config reload failed; keeping the running config");
+                return;
+            }
+        };
+        for section in fixed_changes(&self.current, &next) {
+            warn!(section, "config section changed but is not reloadable; restart to apply");
+        }
+        if next.scheduler != self.current.scheduler {
+            info!(
+                poll_interval = ?next.scheduler.poll_interval,
+                max_inflight = next.scheduler.max_inflight,
+                queues = ?next.scheduler.queues().iter().map(|q| q.name.as_str()).collect::<Vec<_>>(),
+                policy = ?next.scheduler.policy,
+                "applying reloaded [scheduler]"
+            );
+            self.scheduler.send_replace(next.scheduler.clone());
+            self.current.scheduler = next.scheduler;
+        }
+        if next.tx_policy != self.current.tx_policy {
+            info!(gas_bump_percent = next.tx_policy.gas_bump_percent, "applying reloaded [tx_policy]");
+            self.tx_policy.send_replace(next.tx_policy.clone());
+            self.current.tx_policy = next.tx_policy;
+        }
+    }
+}
+
+/// Sections other than `[scheduler]` and `[tx_policy]` that differ.
+fn fixed_changes(running: &WcpConfig, next: &WcpConfig) -> Vec<&'static str> {
+    let sections = [
+        ("ethereum", running.ethereum != next.ethereum),
+        ("signer", running.signer != next.signer),
+        ("subnet_api", running.subnet_api != next.subnet_api),
+        ("reconciler", running.reconciler != next.reconciler),
+        ("events", running.events != next.events),
+        ("profitability", running.profitability != next.profitability),
+        ("api", running.api != next.api),
+        ("storage", running.storage != next.storage),
+        ("protocol", running.protocol != next.protocol),
+        ("reload", running.reload != next.reload),
+        ("logging", running.logging != next.logging),
+        ("telemetry", running.telemetry != next.telemetry),
+        ("wep_endpoint", running.wep_endpoint != next.wep_endpoint),
+        ("wep_grpc_endpoint", running.wep_grpc_endpoint != next.wep_grpc_endpoint),
+        ("dev_mode", running.dev_mode != next.dev_mode),
+    ];
+    sections.into_iter().filter(|(_, changed)| *changed).map(|(name, _)| name).collect()
+}
+
+async fn sleep_if(enabled: bool, interval: Duration) {
+    if enabled {
+        tokio::time::sleep(interval).await
+    } else {
+        std::future::pending().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local() -> WcpConfig {
+        ::config::Config::builder()
+            .add_source(::config::File::from_str(include_str!("../../configs/local.toml"), ::config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    fn loaded(cfg: WcpConfig) -> Result<(WcpConfig, Vec<ConfigProblem>)> {
+        let problems = cfg.validate();
+        Ok((cfg, problems))
+    }
+
+    #[test]
+    fn scheduler_changes_are_sent() {
+        let mut reloader = Reloader::new(local());
+        let mut rx = reloader.scheduler();
+        let mut next = local();
+        next.scheduler.max_inflight += 1;
+        reloader.apply(loaded(next.clone()));
+
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(*rx.borrow_and_update(), next.scheduler);
+        assert_eq!(reloader.current, next);
+
+        // Reloading the same file again sends nothing
+        reloader.apply(loaded(next));
+        assert!(!rx.has_changed().unwrap());
+    }
+
+    #[test]
+    fn tx_policy_changes_are_sent() {
+        let mut reloader = Reloader::new(local());
+        let mut rx = reloader.tx_policy();
+        let scheduler = reloader.scheduler();
+        let mut next = local();
+        next.tx_policy.gas_bump_percent += 5;
+        assert_eq!(fixed_changes(&reloader.current, &next), Vec::<&str>::new());
+        reloader.apply(loaded(next.clone()));
+
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(rx.borrow_and_update().gas_bump_percent, next.tx_policy.gas_bump_percent);
+        assert!(!scheduler.has_changed().unwrap());
+        assert_eq!(reloader.current, next);
+    }
+
+    #[test]
+    fn invalid_config_is_rejected_whole() {
+        let mut reloader = Reloader::new(local());
+        let rx = reloader.scheduler();
+        let tx_policy = reloader.tx_policy();
+        let mut next = local();
+        next.scheduler.max_inflight += 1;
+        next.tx_policy.gas_bump_percent += 5;
+        next.telemetry.sample_ratio = 2.0;
+        reloader.apply(loaded(next));
+        reloader.apply(Err(anyhow::anyhow!("unparsable")));
+
+        assert!(!rx.has_changed().unwrap());
+        assert!(!tx_policy.has_changed().unwrap());
+        assert_eq!(reloader.current, local());
+    }
+
+    #[test]
+    fn other_sections_only_warn() {
+        let mut reloader = Reloader::new(local());
+        let rx = reloader.scheduler();
+        let tx_policy = reloader.tx_policy();
+        let mut next = local();
+        next.ethereum.rpc_url = "https://other.example".into();
+        next.signer.timeout += Duration::from_secs(1);
+        assert_eq!(fixed_changes(&reloader.current, &next), ["ethereum", "signer"]);
+        reloader.apply(loaded(next));
+
+        assert!(!rx.has_changed().unwrap());
+        assert!(!tx_policy.has_changed().unwrap());
+        assert_eq!(reloader.current, local());
+    }
+}
@@ -0,0 +1,53 @@
+//! Periodically samples gauges that have no natural update point: records per
+//! key prefix and the worker wallet balance.
+use alloy::primitives::Address;
+use alloy::providers::Provider;
+use anyhow::Result;
+use std::time::Duration;
+use subnet_wcp_metrics as metrics;
+use subnet_wcp_persistence::backend::{RocksBackend, StorageBackend};
+use subnet_wcp_persistence::KvStore;
+use tracing::warn;
+
+/// Prefixes exported as `wcp_keys{prefix}`.
+const KEY_PREFIXES: &[&str] = &[
+    "claim_job:",
+    "broadcast:claim:",
+    "inflight:",
+    "tx:",
+    "broadcast:complete:",
+    "broadcast:resume:",
+    "done:",
+];
+
+pub struct Sampler<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend = RocksBackend> {
+    store: KvStore<B>,
+    provider: P,
+    wallet: Address,
+    interval: Duration,
+}
+
+impl<P: Provider + Clone + Send + Sync + 'static, B: StorageBackend> Sampler<P, B> {
+    pub fn new(store: KvStore<B>, provider: P, wallet: Address, interval: Duration) -> Self {
+        Self { store, provider, wallet, interval }
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        loop {
+            if let Err(e) = self.sample().await {
+                warn!(error = %e, "metrics sampling failed");
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    async fn sample(&self) -> Result<()> {
+        for prefix in KEY_PREFIXES {
+            let n = self.store.count_prefix(prefix)?;
+            metrics::KEYS.with_label_values(&[prefix]).set(n as i64);
+        }
+        let balance = self.provider.get_balance(self.wallet).await?;
+        metrics::WALLET_BALANCE_WEI.set(f64::from(balance));
+        Ok(())
+    }
+}
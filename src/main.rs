@@ -1,52 +1,123 @@
 use anyhow::Result;
-use tracing_subscriber::{fmt, EnvFilter};
-use subnet_wcp_config::WcpConfig;
+use clap::Parser;
+use subnet_wcp_config::{LoggingConfig, TelemetryConfig, WcpConfig};
 mod components;
-use subnet_wcp_persistence::KvStore;
+use subnet_wcp_persistence::{lock, migrations, KvStore};
 use components::poller::Poller;
 use components::assigner::Assigner;
 use alloy::providers::ProviderBuilder;
-use alloy_signer_local::PrivateKeySigner;
 use components::broadcaster::Broadcaster as ChainBroadcaster;
+use components::reconciler::Reconciler;
+use components::api::Api;
+use components::admin::Admin;
+use components::sampler::Sampler;
+use components::health::{self, Health};
+use components::reload::Reloader;
 use alloy::primitives::Address;
-use subnet_wcp_chain::control_plane as scp;
+use subnet_wcp_event::EventPoller;
+use tokio::sync::mpsc;
+use subnet_wcp_scheduler::{policy, ProfitGate, Scheduler};
+use subnet_wcp_storage::StorageBroker;
+
+/// Subnet Worker Client Process
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Validate the config and load the signer, report every problem found, and exit.
+    #[arg(long)]
+    check_config: bool,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    fmt().with_env_filter(filter).init();
+    let args = Args::parse();
+    // Flushes exported spans on the way out
+    let _telemetry = subnet_wcp_telemetry::init(&LoggingConfig::from_env()?, &TelemetryConfig::from_env()?)?;
 
     tracing::info!("Starting Subnet Worker Client Process (WCP)");
+    subnet_wcp_metrics::init();
 
-    let cfg = WcpConfig::from_env()?;
-    let store = KvStore::open("./wcp.db")?;
+    // Every config problem at once, including a signer that fails to load.
+    // The worker address is whatever the configured signer signs as.
+    let (cfg, mut problems) = WcpConfig::load()?;
+    let signer = match subnet_wcp_signer::check(&cfg, &mut problems).await {
+        Some(signer) if problems.is_empty() => signer,
+        _ => anyhow::bail!("{}", subnet_wcp_config::report(&problems)),
+    };
+    let worker_addr = signer.address;
+    if args.check_config {
+        println!("config OK: worker {} ({} signer)", worker_addr, cfg.signer.kind.as_str());
+        return Ok(());
+    }
+    tracing::info!(worker = %worker_addr, signer = cfg.signer.kind.as_str(), "Loaded signer");
+    // One data dir per worker: wcp.lock names the wallet that owns wcp.db
+    std::fs::create_dir_all(&cfg.storage.data_dir)?;
+    lock::acquire(&cfg.storage.lock_path(), &worker_addr.to_string())?;
+    let store = KvStore::open(cfg.storage.db_path(), &cfg.storage)?;
+    tracing::info!(path = %cfg.storage.db_path().display(), "Opened DB");
+    for step in migrations::run(&store)? {
+        tracing::info!(version = step.version, description = step.description, "Applied DB schema migration");
+    }
     // Provider with wallet for tx signing
-    let signer: PrivateKeySigner = cfg.ethereum.wallet_private_key.parse()?;
-    let provider = ProviderBuilder::new().wallet(signer).connect_http(cfg.ethereum.rpc_url.parse()?);
+    let provider = ProviderBuilder::new().wallet(signer.wallet).connect_http(cfg.ethereum.rpc_url.parse()?);
     // Contract protocol semver check
     let scp_addr: Address = cfg.ethereum.subnet_control_plane_address.parse()?;
-    let ver = scp::get_protocol_version(&provider, scp_addr).await?;
-    let allowed_min = &cfg.protocol.contract_min;
-    let allowed_max = &cfg.protocol.contract_max;
-    if !semver_in_range(&ver, allowed_min, allowed_max) {
-        tracing::error!(onchain=%ver, min=%allowed_min, max=%allowed_max, "Contract protocol version out of supported range");
+    if let Err(e) = health::protocol_gate(&provider, scp_addr, &cfg.protocol).await {
+        tracing::error!(error=%e, "Contract protocol version out of supported range");
         anyhow::bail!("contract protocol version incompatible");
     }
 
     // Registration gate: ensure this worker is active
-    let worker_addr: Address = cfg.ethereum.wallet_address.parse()?;
-    let active = scp::is_worker_active(&provider, scp_addr, worker_addr).await?;
-    if !active {
-        tracing::error!(worker=%cfg.ethereum.wallet_address, "Worker is not active. Refusing to start.");
+    if let Err(e) = health::worker_gate(&provider, scp_addr, worker_addr).await {
+        tracing::error!(worker=%worker_addr, error=%e, "Worker is not active. Refusing to start.");
         anyhow::bail!("worker not active on SubnetControlPlane");
     }
 
+    // Spawn event poller (eth_getLogs with a last_scan_block checkpoint)
+    let task_queue_addr: Address = cfg.ethereum.task_queue_address.parse()?;
+    let workflow_engine_addr: Address = cfg.ethereum.workflow_engine_address.parse()?;
+    let (enqueued_tx, enqueued_rx) = mpsc::channel(1024);
+    let (worker_events_tx, worker_events_rx) = mpsc::channel(1024);
+    let events_enabled = cfg.events.enabled;
+    // Long-running components, watched by /healthz
+    let mut tasks = Vec::new();
+    if events_enabled {
+        let ev = EventPoller::new(
+            store.clone(),
+            provider.clone(),
+            vec![task_queue_addr, workflow_engine_addr],
+            &cfg.events,
+            worker_addr,
+            enqueued_tx,
+            worker_events_tx,
+        );
+        let ev = match cfg.ethereum.ws_url.clone().filter(|u| !u.trim().is_empty()) {
+            Some(url) => ev.with_ws(url),
+            None => ev,
+        };
+        tasks.push(("event_poller", tokio::spawn(async move { let _ = ev.run().await; })));
+    }
+
+    // Config reload (SIGHUP, file watch) feeds [scheduler]/[tx_policy] to the components
+    let reloader = Reloader::new(cfg.clone());
+    let scheduler_settings = reloader.scheduler();
+    let tx_policy = reloader.tx_policy();
+    tasks.push(("reloader", tokio::spawn(async move {
+        if let Err(e) = reloader.run().await {
+            tracing::error!(error = %e, "config reloader stopped");
+        }
+    })));
+
     // Spawn poller
     let poll_interval = cfg.scheduler.poll_interval;
-    let queue_name = cfg.scheduler.queue_name.clone();
-    let task_queue_addr: Address = cfg.ethereum.task_queue_address.parse()?;
-    let poll = Poller::new(store.clone(), poll_interval, queue_name, provider.clone(), task_queue_addr);
+    let queues = cfg.scheduler.queues();
+    let mut poll = Poller::new(store.clone(), poll_interval, queues.clone(), cfg.scheduler.max_inflight, provider.clone(), task_queue_addr)
+        .with_reload(scheduler_settings.clone());
+    if events_enabled {
+        poll = poll.with_events(enqueued_rx);
+    }
     let poller = tokio::spawn(async move { let _ = poll.run().await; });
+    tasks.push(("poller", poller));
 
     // Spawn WEP Assigner (REST API by default)
     let wep_endpoint = cfg.wep_endpoint.clone()
@@ -56,25 +127,84 @@ async fn main() -> Result<()> {
     if cfg.dev_mode.unwrap_or(false) {
         std::env::set_var("DEV_MOCK_ASSIGNER", "1");
     }
-    let assigner = Assigner::new(store.clone(), wep_endpoint, max_inflight);
+    let dev_mock = std::env::var("DEV_MOCK_ASSIGNER").is_ok();
+    let storage = if !dev_mock && !cfg.subnet_api.grpc_endpoint.trim().is_empty() {
+        Some(StorageBroker::new(cfg.subnet_api.grpc_endpoint.clone(), cfg.subnet_api.presign_ttl)?)
+    } else {
+        None
+    };
+    let mut assigner = Assigner::new(store.clone(), wep_endpoint.clone(), max_inflight).with_reload(scheduler_settings.clone());
+    if let Some(storage) = storage.clone() {
+        assigner = assigner.with_storage(storage);
+    }
     let assigner_task = tokio::spawn(async move { let _ = assigner.run().await; });
+    tasks.push(("assigner", assigner_task));
+
+    // Spawn metrics sampler (key prefix depths, wallet balance)
+    let sampler = Sampler::new(store.clone(), provider.clone(), worker_addr, std::time::Duration::from_secs(15));
+    tasks.push(("sampler", tokio::spawn(async move { let _ = sampler.run().await; })));
+
+    // Spawn Scheduler (claim_job:* → broadcast:claim:* by policy and capacity)
+    let weights = queues.iter().map(|q| (q.name.clone(), q.weight)).collect();
+    let mut sched = Scheduler::new(
+        store.clone(),
+        provider.clone(),
+        policy::from_kind(cfg.scheduler.policy, weights),
+        queues,
+        max_inflight,
+        poll_interval,
+        (!dev_mock).then_some(wep_endpoint.clone()),
+    )
+    .with_reload(scheduler_settings.clone());
+    if cfg.profitability.enabled {
+        sched = sched.with_profit_gate(ProfitGate::new(cfg.profitability.clone()));
+    }
+    let scheduler_task = tokio::spawn(async move { let _ = sched.run().await; });
+    tasks.push(("scheduler", scheduler_task));
 
     // Spawn Broadcaster (chain tx pipeline skeleton)
     let task_queue_addr_bc: Address = cfg.ethereum.task_queue_address.parse()?;
     let workflow_engine_addr_bc: Address = cfg.ethereum.workflow_engine_address.parse()?;
-    let chain_bc = ChainBroadcaster::new(store.clone(), provider.clone(), task_queue_addr_bc, workflow_engine_addr_bc);
+    let chain_bc = ChainBroadcaster::new(store.clone(), provider.clone(), task_queue_addr_bc, workflow_engine_addr_bc, tx_policy);
     let broadcaster_task = tokio::spawn(async move { let _ = chain_bc.run().await; });
+    tasks.push(("broadcaster", broadcaster_task));
 
-    let _ = tokio::join!(poller, assigner_task, broadcaster_task);
+    // Spawn Reconciler (repairs local state from getWorkerActivities/getActivity)
+    let mut reconciler = Reconciler::new(store.clone(), provider.clone(), task_queue_addr, worker_addr, cfg.reconciler.interval, cfg.reconciler.window);
+    if events_enabled {
+        reconciler = reconciler.with_events(worker_events_rx);
+    }
+    // On-demand passes requested through the admin API
+    let (reconcile_tx, reconcile_rx) = mpsc::channel(4);
+    reconciler = reconciler.with_triggers(reconcile_rx);
+    let reconciler_task = tokio::spawn(async move { let _ = reconciler.run().await; });
+    tasks.push(("reconciler", reconciler_task));
 
-    Ok(())
-}
-fn semver_in_range(ver: &str, min: &str, max: &str) -> bool {
-    use semver::Version;
-    match (Version::parse(ver), Version::parse(min), Version::parse(max)) {
-        (Ok(v), Ok(lo), Ok(hi)) => v >= lo && v <= hi,
-        _ => false,
+    // Spawn HTTP API (metrics, health probes, presigned URL refresh for the WEP, admin; shares the broker's URL cache)
+    if !cfg.api.listen.trim().is_empty() {
+        let addr: std::net::SocketAddr = cfg.api.listen.parse()?;
+        let mut probes = Health::new(store.clone(), provider.clone(), scp_addr, worker_addr, cfg.protocol.clone(), (!dev_mock).then_some(wep_endpoint));
+        for (name, task) in &tasks {
+            probes = probes.watch(name, task);
+        }
+        let mut api = Api::new(store.clone(), storage).with_routes(probes.router());
+        if !cfg.api.admin_token.is_empty() {
+            api = api.with_routes(Admin::new(store.clone(), cfg.api.admin_token.clone(), scheduler_settings, reconcile_tx).router());
+        } else {
+            tracing::info!("api.admin_token not set; admin API disabled");
+        }
+        tokio::spawn(async move {
+            if let Err(e) = api.run(addr).await {
+                tracing::error!(error = %e, "api server stopped");
+            }
+        });
+    }
+
+    for (_, task) in tasks {
+        let _ = task.await;
     }
+
+    Ok(())
 }
 
 